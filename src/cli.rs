@@ -0,0 +1,249 @@
+// the command line; `cisc <command> <inputs...> [flags]`
+
+use crate::diagnostics::MessageFormat;
+use crate::lint;
+
+pub const USAGE: &str = "\
+usage: cisc <command> <inputs...> [flags]
+
+commands:
+    asm <in.cisc>             assemble into a disc image (in.img, or -o)
+    run <in.img | in.cisc>    boot the emulator off of an image (assembling it first, for src)
+    disasm <in.img | in.obj>  print the instructions of an image or object
+    check <in.cisc>           only report the errors & warnings
+    link <a.obj> <b.obj@addr> link objects into a disc image (a.img, or -o); @addr places an object's code
+    fmt <in.cisc...>          format the files in place
+    cc <in.c>                 compile the c-like src into assembly (in.cisc, or -o)
+    lsp                       serve the language server protocol over stdio, for editors
+
+flags:
+    -o <path>                 the output path
+    --object                  (asm) assemble into a relocatable object (.obj) instead
+    -D <name>[=<value>]       (asm, run, check, lsp) define a !const (1 when no value's given), for the .if's
+    -O, --optimize            (asm, run) rewrite the instructions into cheaper ones that do the same, before placing them
+    --pic                     (asm, run) emit the jumps & calls to labels pc-relative, so the code runs wherever it's loaded
+    -g, --debug-info          (asm) embed the src in the image, so running it traces through its lines
+    --listing                 (asm) also write a listing (.lst) next to the output
+    --symbols                 (asm, link) also write the symbol map (.sym) next to the output
+    --load-symbols=<path>     (run, disasm) name addrs through the symbol map
+    --allow=<lint>, --warn=<lint>, --deny=<lint>
+                              (asm, run, check, lsp) silence a lint, warn about it (the default) or make it an error;
+                              unreachable-code, ret-without-call, protected-instruction, kernel-register or all
+    --check                   (fmt) only report the files that aren't formatted, rather than rewriting them
+    --save                    (run) write the disc, with the guest's writes, back to the image (or -o) once it stops
+    --message-format=<human|json>
+    -q, --quiet               only report errors
+    -v, --verbose             also print the assembler's internal stages
+    -h, --help";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Asm,
+    Run,
+    Disasm,
+    Check,
+    Link,
+    Lsp,
+    Fmt,
+    Cc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub command: Command,
+    pub inputs: Vec<String>,
+    pub defines: Vec<String>,  // name=value, as given
+    pub lints: Vec<(String, lint::Level)>,  // in the order given, so later ones win
+    pub output: Option<String>,
+    pub object: bool,
+    pub optimize: bool,
+    pub pic: bool,
+    pub debug_info: bool,
+    pub listing: bool,
+    pub symbols: bool,
+    pub load_symbols: Vec<String>,
+    pub save: bool,
+    pub check: bool,
+    pub message_format: MessageFormat,
+    pub verbosity: Verbosity,
+}
+
+impl Options {
+    // the output path given, or the first input's with the extension swapped
+    pub fn output_or(&self, extension: &str) -> std::path::PathBuf {
+        match &self.output {
+            Some(output) => std::path::PathBuf::from(output),
+            None => std::path::Path::new(self.inputs.first().map_or("out", |input| input.as_str())).with_extension(extension),
+        }
+    }
+}
+
+fn lint_level(flag: &str) -> Option<lint::Level> {
+    match flag {
+        "--allow" => Some(lint::Level::Allow),
+        "--warn" => Some(lint::Level::Warn),
+        "--deny" => Some(lint::Level::Deny),
+        _ => None,
+    }
+}
+
+// None when the help was asked for; the error is the reason the arguments are invalid
+pub fn parse(args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut args = args.peekable();
+    let command = match args.next().as_deref() {
+        Some("asm") => Command::Asm,
+        Some("run") => Command::Run,
+        Some("disasm") => Command::Disasm,
+        Some("check") => Command::Check,
+        Some("link") => Command::Link,
+        Some("lsp") => Command::Lsp,
+        Some("fmt") => Command::Fmt,
+        Some("cc") => Command::Cc,
+        Some("-h" | "--help") => { return Ok(None); },
+        Some(other) => { return Err(format!("unknown command '{}'", other)); },
+        None => { return Err(String::from("no command given")); },
+    };
+    let mut options = Options {
+        command,
+        inputs: vec![],
+        defines: vec![],
+        lints: vec![],
+        output: None,
+        object: false,
+        optimize: false,
+        pic: false,
+        debug_info: false,
+        listing: false,
+        symbols: false,
+        load_symbols: vec![],
+        save: false,
+        check: false,
+        message_format: MessageFormat::Human,
+        verbosity: Verbosity::Normal,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => { return Ok(None); },
+            "-o" => { options.output = Some(args.next().ok_or("expected a path after '-o'")?); },
+            "-D" => { options.defines.push(args.next().ok_or("expected a name after '-D'")?); },
+            arg if arg.starts_with("-D") => { options.defines.push(arg[2..].to_string()); },
+            "--object" => { options.object = true; },
+            "-O" | "--optimize" => { options.optimize = true; },
+            "--pic" => { options.pic = true; },
+            "-g" | "--debug-info" => { options.debug_info = true; },
+            "--listing" => { options.listing = true; },
+            "--symbols" => { options.symbols = true; },
+            "--save" => { options.save = true; },
+            "--check" => { options.check = true; },
+            "-q" | "--quiet" => { options.verbosity = Verbosity::Quiet; },
+            "-v" | "--verbose" => { options.verbosity = Verbosity::Verbose; },
+            "--message-format=human" => { options.message_format = MessageFormat::Human; },
+            "--message-format=json" => { options.message_format = MessageFormat::Json; },
+            arg if let Some((flag, name)) = arg.split_once('=') && let Some(level) = lint_level(flag) => {
+                if name != "all" && !lint::LINTS.contains(&name) { return Err(format!("unknown lint '{}'", name)); }
+                options.lints.push((name.to_string(), level));
+            },
+            arg if arg.starts_with("--load-symbols=") => { options.load_symbols.push(arg["--load-symbols=".len()..].to_string()); },
+            arg if arg.starts_with('-') && arg.len() > 1 => { return Err(format!("unknown flag '{}'", arg)); },
+            _ => { options.inputs.push(arg); },
+        }
+    }
+    match (command, options.inputs.len()) {
+        (Command::Lsp, 0) => {},
+        (Command::Lsp, _) => { return Err(String::from("lsp doesn't take any inputs")); },
+        (_, 0) => { return Err(String::from("no input given")); },
+        (Command::Link | Command::Fmt, _) | (_, 1) => {},
+        _ => { return Err(format!("expected a single input, but {} were given", options.inputs.len())); },
+    }
+    if options.object && command != Command::Asm {
+        return Err(String::from("'--object' only applies to asm"));
+    }
+    if options.optimize && !matches!(command, Command::Asm | Command::Run) {
+        return Err(String::from("'--optimize' only applies to asm and run"));
+    }
+    if options.pic && !matches!(command, Command::Asm | Command::Run) {
+        return Err(String::from("'--pic' only applies to asm and run"));
+    }
+    if options.check && command != Command::Fmt {
+        return Err(String::from("'--check' only applies to fmt"));
+    } Ok(Some(options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &str) -> Result<Option<Options>, String> {
+        parse(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn parses_commands_and_flags() {
+        let options = parse_args("asm in.cisc -o out.img -DA -D B=2 -DC=0x10 -O --pic -g --listing --symbols --allow=all --deny=kernel-register -q --message-format=json").unwrap().unwrap();
+        assert_eq!((options.command, options.inputs.as_slice(), options.output.as_deref()), (Command::Asm, &[String::from("in.cisc")][..], Some("out.img")));
+        assert_eq!(options.defines, ["A", "B=2", "C=0x10"]);
+        assert!(options.optimize && options.pic && options.debug_info && options.listing && options.symbols);
+        assert_eq!(options.lints, [(String::from("all"), lint::Level::Allow), (String::from("kernel-register"), lint::Level::Deny)]);
+        assert_eq!((options.verbosity, options.message_format), (Verbosity::Quiet, MessageFormat::Json));
+        assert_eq!(options.output_or("img"), std::path::PathBuf::from("out.img"));
+
+        let options = parse_args("run in.cisc -v --load-symbols=a.sym --load-symbols=b.sym --save").unwrap().unwrap();
+        assert_eq!((options.command, options.verbosity, options.message_format), (Command::Run, Verbosity::Verbose, MessageFormat::Human));
+        assert_eq!(options.load_symbols, ["a.sym", "b.sym"]);
+        assert_eq!(options.output_or("img"), std::path::PathBuf::from("in.img"));
+
+        assert_eq!(parse_args("link a.obj b.obj@0x100").unwrap().unwrap().inputs, ["a.obj", "b.obj@0x100"]);
+        assert_eq!(parse_args("fmt a.cisc b.cisc --check").unwrap().unwrap().inputs.len(), 2);
+        assert_eq!(parse_args("lsp").unwrap().unwrap().command, Command::Lsp);
+        for args in ["-h", "--help", "asm in.cisc --help"] {
+            assert!(parse_args(args).unwrap().is_none(), "{}", args);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_args() {
+        for (args, error) in [
+            ("", "no command given"),
+            ("build in.cisc", "unknown command 'build'"),
+            ("asm", "no input given"),
+            ("asm a.cisc b.cisc", "expected a single input, but 2 were given"),
+            ("lsp in.cisc", "lsp doesn't take any inputs"),
+            ("asm in.cisc -o", "expected a path after '-o'"),
+            ("asm in.cisc -D", "expected a name after '-D'"),
+            ("asm in.cisc --fast", "unknown flag '--fast'"),
+            ("asm in.cisc --message-format=xml", "unknown flag '--message-format=xml'"),
+            ("check in.cisc --warn=everything", "unknown lint 'everything'"),
+            ("run in.cisc --object", "'--object' only applies to asm"),
+            ("check in.cisc -O", "'--optimize' only applies to asm and run"),
+            ("disasm in.img --pic", "'--pic' only applies to asm and run"),
+            ("asm in.cisc --check", "'--check' only applies to fmt"),
+        ] {
+            assert_eq!(parse_args(args).unwrap_err(), error, "{}", args);
+        }
+    }
+
+    #[test]
+    fn commands_fail_on_errors() {
+        // main exits with 1 when the command fails (and 2 for the errors above)
+        let dir = std::env::temp_dir().join(format!("cisc-cli-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let check = |text: &str, flags: &str| {
+            let path = dir.join("test.cisc");
+            std::fs::write(&path, text).unwrap();
+            crate::check_command(&parse_args(&format!("check {} -q {}", path.display(), flags)).unwrap().unwrap())
+        };
+        assert!(check("!header main\n*Kill  ; lint:allow(protected-instruction)\n", ""));
+        assert!(!check("!header main\nLdi %nope $1\n", ""));
+        // a warning only fails once it's denied
+        assert!(check("!header main\n*Kill\n", ""));
+        assert!(!check("!header main\n*Kill\n", "--deny=protected-instruction"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,1413 @@
+// the cc compiler; a small c-like language (u16 ints, pointers, arrays, structs, functions, if/while) compiled into
+//    .cisc src, which then goes through the assembler like any other
+//
+//    the stack can't be pointed into, so the locals sit in frames in ram; %rdy points at the current one and %rdx just
+//    past the last. the stack carries the return addrs, the args (read with *IndexOffConst, as *Index/*Edit only take
+//    fixed indices), the caller's %rdy and the temporaries still live across a call. expressions are worked out in
+//    %rda..%rdv with %rdw as scratch, values are returned in %rda, and %rdz is left to the kernel
+
+use crate::diagnostics::{codes, Diagnostic, Span};
+use crate::{formatter, parse_literal, COMPARISON_JUMPS, REGISTERS};
+
+// the registers expressions are worked out in (%rda..%rdv); an operand's depth in the expression picks its register
+const TEMPORARIES: usize = 22;
+static SCRATCH: &str = "%rdw";
+static STACK_POINTER: &str = "%rdx";  // just past the last frame
+static FRAME_POINTER: &str = "%rdy";
+// the ram set aside for the frames, past the globals & strings
+const FRAME_WORDS: usize = 0x400;
+
+static KEYWORDS: &[&str] = &["u16", "void", "struct", "if", "else", "while", "break", "continue", "return", "asm", "sizeof"];
+// longest first, so << isn't taken for two <'s
+static PUNCTUATION: &[&str] = &[
+    "<<=", ">>=", "->", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=",
+    "+", "-", "*", "/", "%", "&", "|", "^", "~", "!", "<", ">", "=", "(", ")", "{", "}", "[", "]", ";", ",", ".",
+];
+static ASSIGNMENTS: &[&str] = &["=", "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=", "<<=", ">>="];
+static UNARY: &[&str] = &["-", "~", "!", "*", "&"];
+// the binary operators, loosest binding first
+static PRECEDENCE: &[&[&str]] = &[&["||"], &["&&"], &["|"], &["^"], &["&"], &["==", "!="], &["<", ">", "<=", ">="], &["<<", ">>"], &["+", "-"], &["*", "/", "%"]];
+// the operators worked out into a register, and the instructions taking a %register or a $constant on the right
+static ARITHMETIC: &[(&str, &str, &str)] = &[
+    ("+", "Add", "Add"), ("-", "Sub", "Sub"), ("*", "Mul", "Mul"), ("/", "Div", "Div"), ("%", "Mod", "Mod"),
+    ("&", "*And", "*AndImm"), ("|", "*Or", "*OrImm"), ("^", "*Xor", "*XorImm"), ("<<", "*Left", "*LeftImm"), (">>", "*Right", "*RightImm"),
+];
+
+fn error(message: impl Into<String>, span: &Span) -> Diagnostic {
+    Diagnostic::error(codes::COMPILE, message).with_span(span.clone())
+}
+
+fn register(depth: usize) -> String {
+    format!("%{}", REGISTERS[depth])
+}
+
+// a local's slot in the frame
+fn slot(offset: usize) -> String {
+    match offset {
+        0 => format!("[{}]", FRAME_POINTER),
+        offset => format!("[{} + ${}]", FRAME_POINTER, offset),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Ident,
+    Keyword,
+    Number,  // char literals included, as their code
+    String,  // the text is unescaped
+    Punct,
+    End,
+}
+
+#[derive(Debug, Clone)]
+struct Tok {
+    kind: Kind,
+    text: String,
+    span: Span,
+}
+
+struct Lexer<'a> {
+    text: &'a str,
+    file: usize,
+    offset: usize,
+    line: usize,
+    line_start: usize,
+}
+
+impl Lexer<'_> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.offset..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let chr = self.peek()?;
+        self.offset += chr.len_utf8();
+        if chr == '\n' {
+            self.line += 1;
+            self.line_start = self.offset;
+        } Some(chr)
+    }
+
+    // from the start (on the current line) up to where the lexer is
+    fn span(&self, start: usize) -> Span {
+        Span { file: self.file, line: self.line, columns: start - self.line_start..self.offset - self.line_start }
+    }
+
+    // the chars of a "string" or 'c' literal, after its opening quote
+    fn literal(&mut self, quote: char, start: usize) -> Result<String, Diagnostic> {
+        let mut text = String::new();
+        loop {
+            match self.bump() {
+                None | Some('\n') => { return Err(error(format!("unterminated {} literal", if quote == '"' { "string" } else { "character" }), &self.span(start))); },
+                Some(chr) if chr == quote => { return Ok(text); },
+                Some('\\') => text.push(match self.bump() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    Some('0') => '\0',
+                    Some(chr @ ('\\' | '\'' | '"')) => chr,
+                    chr => { return Err(error(format!("invalid escape sequence '\\{}'", chr.unwrap_or(' ')), &self.span(start))); },
+                }),
+                Some(chr) => text.push(chr),
+            }
+        }
+    }
+}
+
+fn lex(text: &str, file: usize) -> Result<Vec<Tok>, Diagnostic> {
+    let mut lexer = Lexer { text, file, offset: 0, line: 0, line_start: 0 };
+    let mut tokens = vec![];
+    while let Some(chr) = lexer.peek() {
+        let start = lexer.offset;
+        let rest = &text[start..];
+        if chr.is_whitespace() {
+            lexer.bump();
+            continue;
+        }
+        if rest.starts_with("//") {
+            while lexer.peek().is_some_and(|chr| chr != '\n') { lexer.bump(); }
+            continue;
+        }
+        if rest.starts_with("/*") {
+            let Some(end) = rest.find("*/") else {
+                return Err(error("unterminated comment", &Span { file, line: lexer.line, columns: start - lexer.line_start..start - lexer.line_start + 2 }));
+            };
+            while lexer.offset < start + end + 2 { lexer.bump(); }
+            continue;
+        }
+        let (kind, value) = if chr.is_ascii_alphabetic() || chr == '_' {
+            while lexer.peek().is_some_and(|chr| chr.is_ascii_alphanumeric() || chr == '_') { lexer.bump(); }
+            let word = &text[start..lexer.offset];
+            (if KEYWORDS.contains(&word) { Kind::Keyword } else { Kind::Ident }, word.to_string())
+        } else if chr.is_ascii_digit() {
+            while lexer.peek().is_some_and(|chr| chr.is_ascii_alphanumeric() || chr == '_') { lexer.bump(); }
+            (Kind::Number, text[start..lexer.offset].to_string())
+        } else if chr == '"' || chr == '\'' {
+            lexer.bump();
+            let value = lexer.literal(chr, start)?;
+            if chr == '"' {
+                (Kind::String, value)
+            } else {
+                let mut chars = value.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(chr), None) if chr as u32 <= u16::MAX as u32 => (Kind::Number, (chr as u32).to_string()),
+                    _ => { return Err(error("a character literal holds a single character (that fits in a u16)", &lexer.span(start))); },
+                }
+            }
+        } else if let Some(punct) = PUNCTUATION.iter().find(|punct| rest.starts_with(**punct)) {
+            lexer.offset += punct.len();
+            (Kind::Punct, punct.to_string())
+        } else {
+            lexer.bump();
+            return Err(error(format!("unexpected character '{}'", chr), &lexer.span(start)));
+        };
+        tokens.push(Tok { kind, text: value, span: lexer.span(start) });
+    }
+    tokens.push(Tok { kind: Kind::End, text: String::new(), span: lexer.span(lexer.offset) });
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Type {
+    U16,
+    Void,
+    Pointer (Box<Type>),
+    Array (Box<Type>, usize),
+    Struct (usize),  // index into the structs
+}
+
+impl Type {
+    // fits in a register; what can be passed, returned and assigned
+    fn is_scalar(&self) -> bool {
+        matches!(self, Type::U16 | Type::Pointer(_))
+    }
+}
+
+#[derive(Debug)]
+struct Field {
+    name: String,
+    ty: Type,
+    offset: usize,
+}
+
+#[derive(Debug)]
+struct StructDef {
+    name: String,
+    fields: Vec<Field>,
+    size: Option<usize>,  // None until its closing '}', so it can only be pointed to inside of itself
+}
+
+#[derive(Debug)]
+enum ExprKind {
+    Number (i64),
+    String (String),
+    Name (String),
+    Unary (String, Box<Expr>),
+    Binary (String, Box<Expr>, Box<Expr>),
+    Assign (Option<String>, Box<Expr>, Box<Expr>),  // the operator of a compound assignment (+= etc.)
+    Call (String, Vec<Expr>),
+    Index (Box<Expr>, Box<Expr>),
+    Member (Box<Expr>, String, bool),  // whether it's through a pointer (->)
+    Cast (Type, Box<Expr>),
+}
+
+#[derive(Debug)]
+struct Expr {
+    kind: ExprKind,
+    span: Span,
+}
+
+#[derive(Debug)]
+enum Stmt {
+    Block (Vec<Stmt>),
+    Local { name: String, ty: Type, init: Option<Expr>, span: Span },
+    If (Expr, Box<Stmt>, Option<Box<Stmt>>),
+    While (Expr, Box<Stmt>),
+    Break (Span),
+    Continue (Span),
+    Return (Option<Expr>, Span),
+    Asm (String),
+    Expr (Expr),
+}
+
+#[derive(Debug)]
+enum Init {
+    Value (Expr),
+    List (Vec<Expr>),
+}
+
+#[derive(Debug)]
+struct Global {
+    name: String,
+    ty: Type,
+    init: Option<Init>,
+    span: Span,
+}
+
+#[derive(Debug)]
+struct Function {
+    name: String,
+    ret: Type,
+    params: Vec<(String, Type, Span)>,
+    body: Vec<Stmt>,
+    span: Span,
+}
+
+#[derive(Debug, Default)]
+struct Program {
+    structs: Vec<StructDef>,
+    globals: Vec<Global>,
+    functions: Vec<Function>,
+    asm: Vec<String>,  // the top level asm("...") lines, placed before the code (.name, etc.)
+}
+
+impl Program {
+    fn size(&self, ty: &Type) -> usize {
+        match ty {
+            Type::Void => 0,
+            Type::U16 | Type::Pointer(_) => 1,
+            Type::Array(element, len) => self.size(element) * len,
+            Type::Struct(index) => self.structs[*index].size.unwrap_or(0),
+        }
+    }
+
+    fn type_name(&self, ty: &Type) -> String {
+        match ty {
+            Type::U16 => String::from("u16"),
+            Type::Void => String::from("void"),
+            Type::Pointer(ty) => format!("{}*", self.type_name(ty)),
+            Type::Array(element, len) => format!("{}[{}]", self.type_name(element), len),
+            Type::Struct(index) => format!("struct {}", self.structs[*index].name),
+        }
+    }
+}
+
+// the value of an expression of only numbers (sizeof's included), worked out as u16's
+fn constant(expr: &Expr) -> Option<i64> {
+    match &expr.kind {
+        ExprKind::Number(value) => Some(*value),
+        ExprKind::Unary(op, operand) => {
+            let value = constant(operand)? as u16;
+            match op.as_str() {
+                "-" => Some(value.wrapping_neg() as i64),
+                "~" => Some(!value as i64),
+                "!" => Some((value == 0) as i64),
+                _ => None,
+            }
+        },
+        ExprKind::Binary(op, lhs, rhs) => {
+            let (lhs, rhs) = (constant(lhs)? as u16, constant(rhs)? as u16);
+            let value = match op.as_str() {
+                "+" => lhs.wrapping_add(rhs),
+                "-" => lhs.wrapping_sub(rhs),
+                "*" => lhs.wrapping_mul(rhs),
+                "/" => lhs.checked_div(rhs)?,
+                "%" => lhs.checked_rem(rhs)?,
+                "&" => lhs & rhs,
+                "|" => lhs | rhs,
+                "^" => lhs ^ rhs,
+                "<<" => lhs.checked_shl(rhs as u32)?,
+                ">>" => lhs.checked_shr(rhs as u32)?,
+                "<" => (lhs < rhs) as u16,
+                ">" => (lhs > rhs) as u16,
+                "<=" => (lhs <= rhs) as u16,
+                ">=" => (lhs >= rhs) as u16,
+                "==" => (lhs == rhs) as u16,
+                "!=" => (lhs != rhs) as u16,
+                "&&" => (lhs != 0 && rhs != 0) as u16,
+                "||" => (lhs != 0 || rhs != 0) as u16,
+                _ => { return None; },
+            };
+            Some(value as i64)
+        },
+        ExprKind::Cast(Type::U16, operand) => constant(operand),
+        _ => None,
+    }
+}
+
+struct Parser {
+    tokens: Vec<Tok>,
+    index: usize,
+    structs: Vec<StructDef>,
+}
+
+impl Parser {
+    fn peek(&self) -> &Tok {
+        &self.tokens[self.index]
+    }
+
+    fn peek_at(&self, ahead: usize) -> &Tok {
+        &self.tokens[(self.index + ahead).min(self.tokens.len() - 1)]
+    }
+
+    // the end token's never stepped past
+    fn next(&mut self) -> Tok {
+        let token = self.tokens[self.index].clone();
+        if token.kind != Kind::End { self.index += 1; }
+        token
+    }
+
+    fn is(&self, text: &str) -> bool {
+        matches!(self.peek().kind, Kind::Punct | Kind::Keyword) && self.peek().text == text
+    }
+
+    fn eat(&mut self, text: &str) -> bool {
+        let found = self.is(text);
+        if found { self.next(); }
+        found
+    }
+
+    fn unexpected(&self, expected: &str) -> Diagnostic {
+        let token = self.peek();
+        match token.kind {
+            Kind::End => error(format!("expected {}, but the file ended", expected), &token.span),
+            _ => error(format!("expected {}, but found '{}'", expected, token.text), &token.span),
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> Result<Tok, Diagnostic> {
+        match self.is(text) {
+            true => Ok(self.next()),
+            false => Err(self.unexpected(&format!("'{}'", text))),
+        }
+    }
+
+    fn ident(&mut self, expected: &str) -> Result<Tok, Diagnostic> {
+        match self.peek().kind {
+            Kind::Ident => Ok(self.next()),
+            _ => Err(self.unexpected(expected)),
+        }
+    }
+
+    fn is_type(&self) -> bool {
+        self.is("u16") || self.is("void") || self.is("struct")
+    }
+
+    fn size_of(&self, ty: &Type, span: &Span) -> Result<usize, Diagnostic> {
+        match ty {
+            Type::Void => Err(error("void has no size; only pointers to it do", span)),
+            Type::U16 | Type::Pointer(_) => Ok(1),
+            Type::Array(element, len) => Ok(self.size_of(element, span)? * len),
+            Type::Struct(index) => self.structs[*index].size
+                .ok_or_else(|| error(format!("the struct '{}' isn't complete yet; only pointers to it can be used inside of it", self.structs[*index].name), span)),
+        }
+    }
+
+    // u16, void or struct <name>, then any *'s
+    fn base_type(&mut self) -> Result<Type, Diagnostic> {
+        let mut ty = match self.peek().text.as_str() {
+            "u16" if self.peek().kind == Kind::Keyword => { self.next(); Type::U16 },
+            "void" if self.peek().kind == Kind::Keyword => { self.next(); Type::Void },
+            "struct" if self.peek().kind == Kind::Keyword => {
+                self.next();
+                let name = self.ident("the name of the struct")?;
+                let index = self.structs.iter().position(|def| def.name == name.text)
+                    .ok_or_else(|| error(format!("unknown struct '{}'", name.text), &name.span))?;
+                Type::Struct(index)
+            },
+            _ => { return Err(self.unexpected("a type (u16, void or struct <name>)")); },
+        };
+        while self.eat("*") { ty = Type::Pointer(Box::new(ty)); }
+        Ok(ty)
+    }
+
+    // the [len] after an array's name
+    fn array_suffix(&mut self, ty: Type) -> Result<Type, Diagnostic> {
+        if !self.eat("[") { return Ok(ty); }
+        let len = self.expression()?;
+        let Some(value) = constant(&len).filter(|value| *value > 0) else {
+            return Err(error("an array's length has to be a constant above 0", &len.span));
+        };
+        self.expect("]")?;
+        self.array_suffix(ty).map(|ty| Type::Array(Box::new(ty), value as usize))
+    }
+
+    fn program(&mut self) -> Result<Program, Diagnostic> {
+        let mut program = Program::default();
+        while self.peek().kind != Kind::End {
+            if self.is("struct") && self.peek_at(2).text == "{" {
+                self.struct_definition()?;
+                continue;
+            }
+            if self.is("asm") {
+                program.asm.push(self.asm()?);
+                continue;
+            }
+            let ty = self.base_type()?;
+            let name = self.ident("a name for the global or function")?;
+            if self.is("(") {
+                program.functions.push(self.function(ty, name)?);
+                continue;
+            }
+            let ty = self.array_suffix(ty)?;
+            self.size_of(&ty, &name.span)?;
+            let init = match self.eat("=") {
+                true if self.eat("{") => {
+                    let mut values = vec![];
+                    while !self.eat("}") {
+                        values.push(self.expression()?);
+                        if !self.is("}") { self.expect(",")?; }
+                    }
+                    Some(Init::List(values))
+                },
+                true => Some(Init::Value(self.expression()?)),
+                false => None,
+            };
+            self.expect(";")?;
+            program.globals.push(Global { name: name.text, ty, init, span: name.span });
+        }
+        program.structs = std::mem::take(&mut self.structs);
+        Ok(program)
+    }
+
+    // struct <name> { <type> <field>; ... };
+    fn struct_definition(&mut self) -> Result<(), Diagnostic> {
+        self.expect("struct")?;
+        let name = self.ident("the name of the struct")?;
+        self.expect("{")?;
+        if self.structs.iter().any(|def| def.name == name.text) {
+            return Err(error(format!("the struct '{}' is defined more than once", name.text), &name.span));
+        }
+        let index = self.structs.len();
+        self.structs.push(StructDef { name: name.text, fields: vec![], size: None });
+        let mut fields = vec![];
+        let mut size = 0;
+        while !self.eat("}") {
+            let ty = self.base_type()?;
+            let field = self.ident("the name of the field")?;
+            let ty = self.array_suffix(ty)?;
+            self.expect(";")?;
+            if fields.iter().any(|other: &Field| other.name == field.text) {
+                return Err(error(format!("the field '{}' is declared more than once", field.text), &field.span));
+            }
+            let field_size = self.size_of(&ty, &field.span)?;
+            fields.push(Field { name: field.text, ty, offset: size });
+            size += field_size;
+        }
+        self.expect(";")?;
+        self.structs[index].fields = fields;
+        self.structs[index].size = Some(size);
+        Ok(())
+    }
+
+    // asm("<line>"); placed in the output as is
+    fn asm(&mut self) -> Result<String, Diagnostic> {
+        self.expect("asm")?;
+        self.expect("(")?;
+        if self.peek().kind != Kind::String { return Err(self.unexpected("the line of assembly, as a string")); }
+        let line = self.next().text;
+        self.expect(")")?;
+        self.expect(";")?;
+        Ok(line)
+    }
+
+    fn function(&mut self, ret: Type, name: Tok) -> Result<Function, Diagnostic> {
+        if !(ret.is_scalar() || ret == Type::Void) {
+            return Err(error("functions return a u16, a pointer or void", &name.span));
+        }
+        self.expect("(")?;
+        let mut params = vec![];
+        if self.is("void") && self.peek_at(1).text == ")" { self.next(); }
+        while !self.eat(")") {
+            if !params.is_empty() { self.expect(",")?; }
+            let ty = self.base_type()?;
+            let param = self.ident("the name of the param")?;
+            if !ty.is_scalar() { return Err(error("params are u16s or pointers", &param.span)); }
+            params.push((param.text, ty, param.span));
+        }
+        let body = self.block()?;
+        Ok(Function { name: name.text, ret, params, body, span: name.span })
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, Diagnostic> {
+        self.expect("{")?;
+        let mut statements = vec![];
+        while !self.eat("}") {
+            if self.peek().kind == Kind::End { return Err(self.unexpected("'}'")); }
+            statements.push(self.statement()?);
+        } Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, Diagnostic> {
+        let token = self.peek().clone();
+        let keyword = if token.kind == Kind::Keyword { token.text.as_str() } else { "" };
+        match keyword {
+            _ if self.is("{") => Ok(Stmt::Block(self.block()?)),
+            "if" => {
+                self.next();
+                self.expect("(")?;
+                let condition = self.expression()?;
+                self.expect(")")?;
+                let then = self.statement()?;
+                let otherwise = match self.eat("else") {
+                    true => Some(Box::new(self.statement()?)),
+                    false => None,
+                };
+                Ok(Stmt::If(condition, Box::new(then), otherwise))
+            },
+            "while" => {
+                self.next();
+                self.expect("(")?;
+                let condition = self.expression()?;
+                self.expect(")")?;
+                Ok(Stmt::While(condition, Box::new(self.statement()?)))
+            },
+            "break" | "continue" => {
+                self.next();
+                self.expect(";")?;
+                Ok(if keyword == "break" { Stmt::Break(token.span) } else { Stmt::Continue(token.span) })
+            },
+            "return" => {
+                self.next();
+                let value = if self.is(";") { None } else { Some(self.expression()?) };
+                self.expect(";")?;
+                Ok(Stmt::Return(value, token.span))
+            },
+            "asm" => Ok(Stmt::Asm(self.asm()?)),
+            _ if self.is_type() => {
+                let ty = self.base_type()?;
+                let name = self.ident("the name of the local")?;
+                let ty = self.array_suffix(ty)?;
+                self.size_of(&ty, &name.span)?;
+                let init = if self.eat("=") { Some(self.expression()?) } else { None };
+                self.expect(";")?;
+                Ok(Stmt::Local { name: name.text, ty, init, span: name.span })
+            },
+            _ => {
+                let expr = self.expression()?;
+                self.expect(";")?;
+                Ok(Stmt::Expr(expr))
+            },
+        }
+    }
+
+    // an assignment (right to left), or a binary expression
+    fn expression(&mut self) -> Result<Expr, Diagnostic> {
+        let target = self.binary(0)?;
+        let token = self.peek().clone();
+        if token.kind != Kind::Punct || !ASSIGNMENTS.contains(&token.text.as_str()) { return Ok(target); }
+        self.next();
+        let value = self.expression()?;
+        let op = token.text.strip_suffix('=').filter(|op| !op.is_empty()).map(str::to_string);
+        Ok(Expr { kind: ExprKind::Assign(op, Box::new(target), Box::new(value)), span: token.span })
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, Diagnostic> {
+        if level == PRECEDENCE.len() { return self.unary(); }
+        let mut lhs = self.binary(level + 1)?;
+        while self.peek().kind == Kind::Punct && PRECEDENCE[level].contains(&self.peek().text.as_str()) {
+            let op = self.next();
+            let rhs = self.binary(level + 1)?;
+            lhs = Expr { kind: ExprKind::Binary(op.text, Box::new(lhs), Box::new(rhs)), span: op.span };
+        } Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, Diagnostic> {
+        let token = self.peek().clone();
+        if token.kind == Kind::Punct && UNARY.contains(&token.text.as_str()) {
+            self.next();
+            let operand = self.unary()?;
+            return Ok(Expr { kind: ExprKind::Unary(token.text, Box::new(operand)), span: token.span });
+        }
+        if self.eat("sizeof") {
+            self.expect("(")?;
+            let ty = self.base_type()?;
+            let ty = self.array_suffix(ty)?;
+            let size = self.size_of(&ty, &token.span)?;
+            self.expect(")")?;
+            return Ok(Expr { kind: ExprKind::Number(size as i64), span: token.span });
+        }
+        // a cast; (<type>) <operand>
+        if self.is("(") && matches!(self.peek_at(1).text.as_str(), "u16" | "void" | "struct") && self.peek_at(1).kind == Kind::Keyword {
+            self.next();
+            let ty = self.base_type()?;
+            self.expect(")")?;
+            let operand = self.unary()?;
+            return Ok(Expr { kind: ExprKind::Cast(ty, Box::new(operand)), span: token.span });
+        }
+        self.postfix()
+    }
+
+    fn postfix(&mut self) -> Result<Expr, Diagnostic> {
+        let mut expr = self.primary()?;
+        loop {
+            let token = self.peek().clone();
+            if self.eat("[") {
+                let index = self.expression()?;
+                self.expect("]")?;
+                expr = Expr { kind: ExprKind::Index(Box::new(expr), Box::new(index)), span: token.span };
+            } else if self.eat(".") || self.eat("->") {
+                let field = self.ident("the name of the field")?;
+                expr = Expr { kind: ExprKind::Member(Box::new(expr), field.text, token.text == "->"), span: field.span };
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, Diagnostic> {
+        let token = self.peek().clone();
+        let kind = match token.kind {
+            Kind::Number => {
+                self.next();
+                match parse_literal(&token.text).filter(|value| *value <= u16::MAX as u32) {
+                    Some(value) => ExprKind::Number(value as i64),
+                    None => { return Err(error(format!("'{}' isn't a number that fits in a u16", token.text), &token.span)); },
+                }
+            },
+            Kind::String => {
+                self.next();
+                ExprKind::String(token.text)
+            },
+            Kind::Ident if self.peek_at(1).text == "(" => {
+                self.next();
+                self.next();
+                let mut args = vec![];
+                while !self.eat(")") {
+                    if !args.is_empty() { self.expect(",")?; }
+                    args.push(self.expression()?);
+                }
+                ExprKind::Call(token.text, args)
+            },
+            Kind::Ident => {
+                self.next();
+                ExprKind::Name(token.text)
+            },
+            _ if self.eat("(") => {
+                let expr = self.expression()?;
+                self.expect(")")?;
+                return Ok(expr);
+            },
+            _ => { return Err(self.unexpected("an expression")); },
+        };
+        Ok(Expr { kind, span: token.span })
+    }
+}
+
+// where a variable lives
+enum Place {
+    Frame (usize),  // the offset into the frame
+    Global (String),  // its !define
+}
+
+struct Codegen<'a> {
+    program: &'a Program,
+    strings: Vec<String>,  // the literals, placed after the globals (as __string_<index>)
+    errors: Vec<Diagnostic>,
+    // the function being compiled
+    function: &'a Function,
+    lines: Vec<String>,
+    scopes: Vec<Vec<(String, Type, usize)>>,  // name, type & offset into the frame
+    frame: usize,  // the frame words taken by the locals in scope
+    frame_size: usize,
+    loops: Vec<(String, String)>,  // the labels continue & break jump to
+    next_id: usize,
+    dead: bool,  // past a jump, with no label since; nothing's emitted until one is placed
+}
+
+impl<'a> Codegen<'a> {
+    fn emit(&mut self, line: String) {
+        if !self.dead { self.lines.push(format!("    {}", line)); }
+    }
+
+    fn label(&mut self, name: &str) {
+        self.lines.push(format!("!label {}", name));
+        self.dead = false;
+    }
+
+    fn jump(&mut self, target: &str) {
+        self.emit(format!("*Jmp #{}", target));
+        self.dead = true;
+    }
+
+    fn id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    fn report<T>(&mut self, result: Result<T, Diagnostic>) -> Option<T> {
+        result.map_err(|error| self.errors.push(error)).ok()
+    }
+
+    fn type_name(&self, ty: &Type) -> String {
+        self.program.type_name(ty)
+    }
+
+    fn variable(&self, name: &str, span: &Span) -> Result<(Type, Place), Diagnostic> {
+        if let Some((_, ty, offset)) = self.scopes.iter().rev().flat_map(|scope| scope.iter().rev()).find(|(other, ..)| other == name) {
+            return Ok((ty.clone(), Place::Frame(*offset)));
+        }
+        if let Some(global) = self.program.globals.iter().find(|global| global.name == name) {
+            return Ok((global.ty.clone(), Place::Global(global.name.clone())));
+        }
+        match self.program.functions.iter().any(|function| function.name == name) {
+            true => Err(error(format!("'{}' is a function, which can only be called", name), span)),
+            false => Err(error(format!("undefined variable '{}'", name), span)),
+        }
+    }
+
+    fn check_depth(&self, depth: usize, span: &Span) -> Result<(), Diagnostic> {
+        match depth < TEMPORARIES {
+            true => Ok(()),
+            false => Err(error("the expression is nested too deep to be worked out in the registers", span).with_note("split it up with locals")),
+        }
+    }
+
+    // a value of the type can be stored into (or passed as) the other
+    fn assignable(&self, to: &Type, from: &Type, value: &Expr) -> Result<(), Diagnostic> {
+        let fits = to == from && to.is_scalar() || match (to, from) {
+            (Type::Pointer(_), Type::U16) => constant(value) == Some(0),  // the null pointer
+            (Type::Pointer(to), Type::Pointer(from)) => **to == Type::Void || **from == Type::Void,
+            _ => false,
+        };
+        match fits {
+            true => Ok(()),
+            false => Err(error(format!("expected a '{}', but found a '{}'", self.type_name(to), self.type_name(from)), &value.span)),
+        }
+    }
+
+    fn expect_u16(&self, ty: &Type, expr: &Expr) -> Result<(), Diagnostic> {
+        match ty {
+            Type::U16 => Ok(()),
+            ty => Err(error(format!("expected a 'u16', but found a '{}'", self.type_name(ty)), &expr.span)),
+        }
+    }
+
+    // the size of what a pointer points to, for stepping through them
+    fn element_size(&self, element: &Type, span: &Span) -> Result<usize, Diagnostic> {
+        match element {
+            Type::Void => Err(error("can't step through a 'void*'; cast it to a pointer to something first", span)),
+            element => Ok(self.program.size(element)),
+        }
+    }
+
+    // works the expression out into the depth's register, giving back its type (with arrays as pointers to their first element)
+    fn value(&mut self, expr: &Expr, depth: usize) -> Result<Type, Diagnostic> {
+        self.check_depth(depth, &expr.span)?;
+        let reg = register(depth);
+        if let Some(value) = constant(expr) {
+            self.emit(format!("Ldi {} ${}", reg, value as u16));
+            return Ok(Type::U16);
+        }
+        match &expr.kind {
+            ExprKind::String(text) => {
+                let index = self.strings.iter().position(|other| other == text).unwrap_or_else(|| {
+                    self.strings.push(text.clone());
+                    self.strings.len() - 1
+                });
+                self.emit(format!("Ldi {} $__string_{}", reg, index));
+                Ok(Type::Pointer(Box::new(Type::U16)))
+            },
+            ExprKind::Name(_) | ExprKind::Index(..) | ExprKind::Member(..) => self.load(expr, depth),
+            ExprKind::Unary(op, _) if op == "*" => self.load(expr, depth),
+            ExprKind::Unary(op, operand) if op == "&" => Ok(Type::Pointer(Box::new(self.address(operand, depth)?))),
+            ExprKind::Unary(op, operand) if op == "-" || op == "~" => {
+                let ty = self.value(operand, depth)?;
+                self.expect_u16(&ty, operand)?;
+                self.emit(format!("*Not {} {}", reg, reg));
+                if op == "-" { self.emit(format!("Add {} $1 {}", reg, reg)); }
+                Ok(Type::U16)
+            },
+            ExprKind::Unary(..) => self.boolean(expr, depth),
+            ExprKind::Binary(op, ..) if op == "&&" || op == "||" || COMPARISON_JUMPS.iter().any(|(comparison, ..)| comparison == op) => self.boolean(expr, depth),
+            ExprKind::Binary(op, lhs, rhs) => {
+                let ty = self.value(lhs, depth)?;
+                self.arithmetic(op, ty, depth, rhs, &expr.span)
+            },
+            ExprKind::Assign(op, target, value) => self.assign(op.as_deref(), target, value, depth),
+            ExprKind::Call(name, args) => self.call(name, args, &expr.span, depth),
+            ExprKind::Cast(ty, operand) => {
+                let from = self.value(operand, depth)?;
+                match from.is_scalar() && ty.is_scalar() || *ty == Type::Void {
+                    true => Ok(ty.clone()),
+                    false => Err(error(format!("can't cast a '{}' to a '{}'", self.type_name(&from), self.type_name(ty)), &expr.span)),
+                }
+            },
+            ExprKind::Number(_) => Ok(Type::U16),  // already loaded, as a constant
+        }
+    }
+
+    // the value of a variable, element, field or pointee
+    fn load(&mut self, expr: &Expr, depth: usize) -> Result<Type, Diagnostic> {
+        let reg = register(depth);
+        // named u16s & pointers straight out of their slot
+        if let ExprKind::Name(name) = &expr.kind {
+            match self.variable(name, &expr.span)? {
+                (ty, Place::Frame(offset)) if ty.is_scalar() => {
+                    self.emit(format!("Get {} {}", slot(offset), reg));
+                    return Ok(ty);
+                },
+                (ty, Place::Global(name)) if ty.is_scalar() => {
+                    self.emit(format!("Get #{} {}", name, reg));
+                    return Ok(ty);
+                },
+                _ => {},
+            }
+        }
+        match self.address(expr, depth)? {
+            Type::Array(element, _) => Ok(Type::Pointer(element)),
+            Type::Struct(index) => Err(error(format!("the struct '{}' can't be used as a value", self.program.structs[index].name), &expr.span)
+                .with_note("use its fields, or a pointer to it")),
+            ty => {
+                self.emit(format!("Get [{}] {}", reg, reg));
+                Ok(ty)
+            },
+        }
+    }
+
+    // the address of a variable, element, field or pointee into the depth's register, giving back the type of what's there
+    fn address(&mut self, expr: &Expr, depth: usize) -> Result<Type, Diagnostic> {
+        self.check_depth(depth, &expr.span)?;
+        let reg = register(depth);
+        match &expr.kind {
+            ExprKind::Name(name) => match self.variable(name, &expr.span)? {
+                (ty, Place::Frame(0)) => {
+                    self.emit(format!("Mov {} {}", FRAME_POINTER, reg));
+                    Ok(ty)
+                },
+                (ty, Place::Frame(offset)) => {
+                    self.emit(format!("Add {} ${} {}", FRAME_POINTER, offset, reg));
+                    Ok(ty)
+                },
+                (ty, Place::Global(name)) => {
+                    self.emit(format!("Ldi {} ${}", reg, name));
+                    Ok(ty)
+                },
+            },
+            ExprKind::Unary(op, operand) if op == "*" => match self.value(operand, depth)? {
+                Type::Pointer(ty) if *ty != Type::Void => Ok(*ty),
+                ty => Err(error(format!("can't dereference a '{}'", self.type_name(&ty)), &expr.span)),
+            },
+            ExprKind::Index(base, index) => {
+                let element = match self.value(base, depth)? {
+                    Type::Pointer(element) => *element,
+                    ty => { return Err(error(format!("can't index into a '{}'", self.type_name(&ty)), &expr.span)); },
+                };
+                let size = self.element_size(&element, &expr.span)?;
+                match constant(index) {
+                    Some(0) => {},
+                    Some(value) => self.emit(format!("Add {} ${} {}", reg, (value * size as i64) as u16, reg)),
+                    None => {
+                        let ty = self.value(index, depth + 1)?;
+                        self.expect_u16(&ty, index)?;
+                        let index = register(depth + 1);
+                        if size != 1 { self.emit(format!("Mul {} ${} {}", index, size, index)); }
+                        self.emit(format!("Add {} {} {}", reg, index, reg));
+                    },
+                } Ok(element)
+            },
+            ExprKind::Member(base, name, through_pointer) => {
+                let ty = match through_pointer {
+                    true => match self.value(base, depth)? {
+                        Type::Pointer(ty) => *ty,
+                        ty => { return Err(error(format!("'->' needs a pointer to a struct, but found a '{}'", self.type_name(&ty)), &expr.span)); },
+                    },
+                    false => self.address(base, depth)?,
+                };
+                let Type::Struct(index) = ty else {
+                    return Err(error(format!("a '{}' has no fields", self.type_name(&ty)), &expr.span));
+                };
+                let def = &self.program.structs[index];
+                let Some(field) = def.fields.iter().find(|field| field.name == *name) else {
+                    return Err(error(format!("the struct '{}' has no field '{}'", def.name, name), &expr.span));
+                };
+                if field.offset > 0 { self.emit(format!("Add {} ${} {}", reg, field.offset, reg)); }
+                Ok(field.ty.clone())
+            },
+            _ => Err(error("expected a variable, element, field or pointee here", &expr.span)),
+        }
+    }
+
+    // the operator applied to the value in the depth's register and the right hand side, back into the register
+    //    (stepping pointers a whole element at a time)
+    fn arithmetic(&mut self, op: &str, lhs: Type, depth: usize, rhs: &Expr, span: &Span) -> Result<Type, Diagnostic> {
+        let reg = register(depth);
+        let Some((_, instruction, immediate)) = ARITHMETIC.iter().find(|(name, ..)| *name == op) else {
+            return Err(error(format!("'{}' doesn't work out a value", op), span));
+        };
+        let mismatch = |codegen: &Self, rhs: &Type| error(format!(
+            "can't apply '{}' to a '{}' and a '{}'", op, codegen.type_name(&lhs), codegen.type_name(rhs)
+        ), span);
+        if let Some(value) = constant(rhs) {
+            let scale = match (&lhs, op) {
+                (Type::Pointer(element), "+" | "-") => self.element_size(element, span)?,
+                (Type::U16, _) => 1,
+                _ => { return Err(mismatch(self, &Type::U16)); },
+            };
+            self.emit(format!("{} {} ${} {}", immediate, reg, (value * scale as i64) as u16, reg));
+            return Ok(lhs);
+        }
+        let other = register(depth + 1);
+        let rhs_ty = self.value(rhs, depth + 1)?;
+        let (ty, divide) = match (op, &lhs, &rhs_ty) {
+            (_, Type::U16, Type::U16) => (Type::U16, 1),
+            ("+" | "-", Type::Pointer(element), Type::U16) => {
+                let size = self.element_size(element, span)?;
+                if size != 1 { self.emit(format!("Mul {} ${} {}", other, size, other)); }
+                (lhs.clone(), 1)
+            },
+            ("+", Type::U16, Type::Pointer(element)) => {
+                let size = self.element_size(element, span)?;
+                if size != 1 { self.emit(format!("Mul {} ${} {}", reg, size, reg)); }
+                (rhs_ty.clone(), 1)
+            },
+            // the elements between two pointers
+            ("-", Type::Pointer(element), Type::Pointer(other)) if element == other => (Type::U16, self.element_size(element, span)?),
+            _ => { return Err(mismatch(self, &rhs_ty)); },
+        };
+        self.emit(format!("{} {} {} {}", instruction, reg, other, reg));
+        if divide != 1 { self.emit(format!("Div {} ${} {}", reg, divide, reg)); }
+        Ok(ty)
+    }
+
+    // a comparison (or &&, || and !) as a value; 1 when it holds, 0 otherwise
+    fn boolean(&mut self, expr: &Expr, depth: usize) -> Result<Type, Diagnostic> {
+        let reg = register(depth);
+        let id = self.id();
+        let (fails, end) = (format!("__{}_false_{}", self.function.name, id), format!("__{}_false_{}_end", self.function.name, id));
+        self.branch(expr, false, &fails, depth)?;
+        self.emit(format!("Ldi {} $1", reg));
+        self.jump(&end);
+        self.label(&fails);
+        self.emit(format!("Ldi {} $0", reg));
+        self.label(&end);
+        Ok(Type::U16)
+    }
+
+    // jumps to the target when the expression is true (or false), falling through otherwise
+    fn branch(&mut self, expr: &Expr, when: bool, target: &str, depth: usize) -> Result<(), Diagnostic> {
+        if let Some(value) = constant(expr) {
+            if (value != 0) == when { self.jump(target); }
+            return Ok(());
+        }
+        match &expr.kind {
+            ExprKind::Unary(op, operand) if op == "!" => self.branch(operand, !when, target, depth),
+            ExprKind::Binary(op, lhs, rhs) if op == "&&" || op == "||" => {
+                // either side alone decides it (false for &&, true for ||); otherwise the first skips past the second
+                if (op == "&&") != when {
+                    self.branch(lhs, when, target, depth)?;
+                    return self.branch(rhs, when, target, depth);
+                }
+                let id = self.id();
+                let skip = format!("__{}_skip_{}", self.function.name, id);
+                self.branch(lhs, !when, &skip, depth)?;
+                self.branch(rhs, when, target, depth)?;
+                self.label(&skip);
+                Ok(())
+            },
+            ExprKind::Binary(op, lhs, rhs) if let Some((_, holds, fails)) = COMPARISON_JUMPS.iter().find(|(comparison, ..)| comparison == op) => {
+                let lhs_ty = self.value(lhs, depth)?;
+                let operand = match constant(rhs) {
+                    Some(value) => {
+                        self.assignable(&lhs_ty, &Type::U16, rhs)?;
+                        format!("${}", value as u16)
+                    },
+                    None => {
+                        let rhs_ty = self.value(rhs, depth + 1)?;
+                        if !lhs_ty.is_scalar() || self.assignable(&lhs_ty, &rhs_ty, rhs).is_err() && self.assignable(&rhs_ty, &lhs_ty, lhs).is_err() {
+                            return Err(error(format!("can't compare a '{}' with a '{}'", self.type_name(&lhs_ty), self.type_name(&rhs_ty)), &expr.span));
+                        }
+                        register(depth + 1)
+                    },
+                };
+                self.emit(format!("{} {} {} #{}", if when { holds } else { fails }, register(depth), operand, target));
+                Ok(())
+            },
+            _ => {
+                let ty = self.value(expr, depth)?;
+                if !ty.is_scalar() { return Err(error(format!("a '{}' can't be used as a condition", self.type_name(&ty)), &expr.span)); }
+                self.emit(format!("{} {} $0 #{}", if when { "Jne" } else { "Jeq" }, register(depth), target));
+                Ok(())
+            },
+        }
+    }
+
+    fn assign(&mut self, op: Option<&str>, target: &Expr, value: &Expr, depth: usize) -> Result<Type, Diagnostic> {
+        let reg = register(depth);
+        let Some(op) = op else {
+            let ty = self.value(value, depth)?;
+            let named = match &target.kind {
+                ExprKind::Name(name) => Some(self.variable(name, &target.span)?),
+                _ => None,
+            };
+            let target_ty = match named {
+                Some((target_ty, Place::Frame(offset))) if target_ty.is_scalar() => {
+                    self.emit(format!("Sto {} {}", slot(offset), reg));
+                    target_ty
+                },
+                Some((target_ty, Place::Global(name))) if target_ty.is_scalar() => {
+                    self.emit(format!("Sto #{} {}", name, reg));
+                    target_ty
+                },
+                _ => {
+                    let target_ty = self.address(target, depth + 1)?;
+                    self.emit(format!("Sto [{}] {}", register(depth + 1), reg));
+                    target_ty
+                },
+            };
+            if !target_ty.is_scalar() {
+                return Err(error(format!("can't assign to a '{}'; only to u16s and pointers", self.type_name(&target_ty)), &target.span));
+            }
+            self.assignable(&target_ty, &ty, value)?;
+            return Ok(target_ty);
+        };
+        // <target> <op>= <value>; the target's address is only worked out the once
+        let target_ty = self.address(target, depth)?;
+        if !target_ty.is_scalar() {
+            return Err(error(format!("can't assign to a '{}'; only to u16s and pointers", self.type_name(&target_ty)), &target.span));
+        }
+        self.check_depth(depth + 1, &target.span)?;
+        let current = register(depth + 1);
+        self.emit(format!("Get [{}] {}", reg, current));
+        let ty = self.arithmetic(op, target_ty.clone(), depth + 1, value, &value.span)?;
+        self.assignable(&target_ty, &ty, value)?;
+        self.emit(format!("Sto [{}] {}", reg, current));
+        self.emit(format!("Mov {} {}", current, reg));
+        Ok(target_ty)
+    }
+
+    fn call(&mut self, name: &str, args: &[Expr], span: &Span, depth: usize) -> Result<Type, Diagnostic> {
+        let Some(function) = self.program.functions.iter().find(|function| function.name == name) else {
+            return Err(error(format!("undefined function '{}'", name), span));
+        };
+        if args.len() != function.params.len() {
+            return Err(error(format!("'{}' takes {} arg{}, but {} were given", name, function.params.len(), if function.params.len() == 1 { "" } else { "s" }, args.len()), span)
+                .with_spanned_note(format!("'{}' is defined here", name), function.span.clone()));
+        }
+        // the temporaries still in use are saved across the call (which is free to use them all), so the args start from %rda
+        for live in 0..depth { self.emit(format!("Psh {}", register(live))); }
+        for (arg, (_, param, _)) in args.iter().zip(&function.params) {
+            let ty = self.value(arg, 0)?;
+            self.assignable(param, &ty, arg)?;
+            self.emit(format!("Psh {}", register(0)));
+        }
+        self.emit(format!("*Call #{}", name));
+        if depth > 0 { self.emit(format!("Mov {} {}", register(0), register(depth))); }
+        for _ in args { self.emit(format!("*Pop {}", SCRATCH)); }
+        for live in (0..depth).rev() { self.emit(format!("*Pop {}", register(live))); }
+        Ok(function.ret.clone())
+    }
+
+    fn declare(&mut self, name: &str, ty: &Type, span: &Span) -> Result<usize, Diagnostic> {
+        if self.scopes.last().is_some_and(|scope| scope.iter().any(|(other, ..)| other == name)) {
+            return Err(error(format!("'{}' is already declared here", name), span));
+        }
+        let offset = self.frame;
+        self.frame += self.program.size(ty);
+        self.frame_size = self.frame_size.max(self.frame);
+        if let Some(scope) = self.scopes.last_mut() { scope.push((name.to_string(), ty.clone(), offset)); }
+        Ok(offset)
+    }
+
+    // a block's locals share the frame words of the blocks beside it
+    fn block(&mut self, statements: &[Stmt]) {
+        let frame = self.frame;
+        self.scopes.push(vec![]);
+        for statement in statements { self.statement(statement); }
+        self.scopes.pop();
+        self.frame = frame;
+    }
+
+    fn statement(&mut self, statement: &Stmt) {
+        let return_label = format!("__{}_return", self.function.name);
+        match statement {
+            Stmt::Block(statements) => self.block(statements),
+            Stmt::Local { name, ty, init, span } => {
+                let stored = match init {
+                    Some(init) if !ty.is_scalar() => {
+                        self.errors.push(error("only u16s and pointers can be given a value where they're declared", &init.span));
+                        None
+                    },
+                    Some(init) => {
+                        let result = self.value(init, 0).and_then(|value_ty| self.assignable(ty, &value_ty, init));
+                        self.report(result)
+                    },
+                    None => None,
+                };
+                let result = self.declare(name, ty, span);
+                if let Some(offset) = self.report(result) && stored.is_some() {
+                    self.emit(format!("Sto {} {}", slot(offset), register(0)));
+                }
+            },
+            Stmt::If(condition, then, otherwise) => {
+                let id = self.id();
+                let (else_label, end_label) = (format!("__{}_if_{}_else", self.function.name, id), format!("__{}_if_{}_end", self.function.name, id));
+                let result = self.branch(condition, false, &else_label, 0);
+                self.report(result);
+                self.statement(then);
+                match otherwise {
+                    Some(otherwise) => {
+                        self.jump(&end_label);
+                        self.label(&else_label);
+                        self.statement(otherwise);
+                        self.label(&end_label);
+                    },
+                    None => self.label(&else_label),
+                }
+            },
+            Stmt::While(condition, body) => {
+                let id = self.id();
+                let (start, end) = (format!("__{}_while_{}", self.function.name, id), format!("__{}_while_{}_end", self.function.name, id));
+                self.label(&start);
+                let result = self.branch(condition, false, &end, 0);
+                self.report(result);
+                self.loops.push((start.clone(), end.clone()));
+                self.statement(body);
+                self.loops.pop();
+                self.jump(&start);
+                self.label(&end);
+            },
+            Stmt::Break(span) | Stmt::Continue(span) => match self.loops.last().cloned() {
+                Some((start, end)) => self.jump(if matches!(statement, Stmt::Break(_)) { &end } else { &start }),
+                None => self.errors.push(error(format!("'{}' outside of any while", if matches!(statement, Stmt::Break(_)) { "break" } else { "continue" }), span)),
+            },
+            Stmt::Return(value, span) => {
+                let function = self.function;
+                match (value, &function.ret) {
+                    (Some(value), Type::Void) => self.errors.push(error(format!("'{}' returns nothing, but a value's given", function.name), &value.span)),
+                    (Some(value), ret) => {
+                        let result = self.value(value, 0).and_then(|ty| self.assignable(ret, &ty, value));
+                        self.report(result);
+                    },
+                    (None, Type::Void) => {},
+                    (None, ret) => self.errors.push(error(format!("'{}' returns a '{}', but no value's given", function.name, self.type_name(ret)), span)),
+                }
+                self.jump(&return_label);
+            },
+            Stmt::Asm(line) => self.emit(line.clone()),
+            Stmt::Expr(expr) => {
+                let result = self.value(expr, 0);
+                self.report(result);
+            },
+        }
+    }
+
+    // the function's label, its prologue & epilogue around the body
+    fn function(&mut self, function: &'a Function) -> Vec<String> {
+        self.function = function;
+        self.lines = vec![];
+        self.scopes = vec![vec![]];
+        self.frame = 0;
+        self.frame_size = 0;
+        self.next_id = 0;
+        self.dead = false;
+        // the args are copied out of the stack into the frame, so they can be pointed to like any other local
+        let mut params = vec![];
+        for (name, ty, span) in &function.params {
+            let result = self.declare(name, ty, span);
+            params.extend(self.report(result));
+        }
+        for statement in &function.body { self.statement(statement); }
+
+        let mut lines = vec![format!("!header {}", function.name), format!("    Psh {}", FRAME_POINTER), format!("    Mov {} {}", STACK_POINTER, FRAME_POINTER)];
+        if self.frame_size > 0 { lines.push(format!("    Add {} ${} {}", STACK_POINTER, self.frame_size, STACK_POINTER)); }
+        for (index, offset) in params.iter().enumerate() {
+            // past the caller's %rdy and the return addr, the last arg being on top
+            lines.push(format!("    *IndexOffConst ${} {}", params.len() - index + 2, SCRATCH));
+            lines.push(format!("    Sto {} {}", slot(*offset), SCRATCH));
+        }
+        lines.append(&mut self.lines);
+        lines.push(format!("!label __{}_return", function.name));
+        lines.push(format!("    Mov {} {}", FRAME_POINTER, STACK_POINTER));
+        lines.push(format!("    *Pop {}", FRAME_POINTER));
+        lines.push(String::from("    *Ret"));
+        lines
+    }
+}
+
+// the words of a string for an !alloc; printable runs as "text", the rest as $values, then the terminating $0
+fn string_data(text: &str) -> String {
+    let mut data = vec![];
+    let mut run = String::new();
+    for chr in text.chars() {
+        match chr {
+            ' '..='~' | '\n' | '\t' => run.push_str(&match chr {
+                '\n' => String::from("\\n"),
+                '\t' => String::from("\\t"),
+                '\\' | '"' => format!("\\{}", chr),
+                chr => chr.to_string(),
+            }),
+            chr => {
+                if !run.is_empty() { data.push(format!("\"{}\"", std::mem::take(&mut run))); }
+                data.push(format!("${}", chr as u32 & 0xFFFF));
+            },
+        }
+    }
+    if !run.is_empty() { data.push(format!("\"{}\"", run)); }
+    data.push(String::from("$0"));
+    data.join(" ")
+}
+
+// a global's initial value as !alloc data
+fn global_data(global: &Global, program: &Program, strings: &mut Vec<String>) -> Result<String, Diagnostic> {
+    let Some(init) = &global.init else { return Ok(String::new()); };
+    let value = |expr: &Expr, ty: &Type, strings: &mut Vec<String>| match (&expr.kind, ty) {
+        (ExprKind::String(text), Type::Pointer(_)) => {
+            strings.push(text.clone());
+            Ok(format!("$__string_{}", strings.len() - 1))
+        },
+        (_, ty) if ty.is_scalar() => constant(expr).map(|value| format!("${}", value as u16))
+            .ok_or_else(|| error("a global's initial value has to be a constant (or a string, for a pointer)", &expr.span)),
+        _ => Err(error(format!("a '{}' can't be given a value where it's declared", program.type_name(ty)), &expr.span)),
+    };
+    match (init, &global.ty) {
+        (Init::Value(expr), Type::Array(element, len)) if **element == Type::U16 && let ExprKind::String(text) = &expr.kind => {
+            if text.chars().count() + 1 > *len {
+                return Err(error(format!("the string (and its terminating 0) doesn't fit in the {} elements", len), &expr.span));
+            } Ok(string_data(text))
+        },
+        (Init::Value(expr), ty) => value(expr, ty, strings),
+        (Init::List(values), Type::Array(element, len)) => {
+            if values.len() > *len {
+                return Err(error(format!("{} values are given for the {} elements", values.len(), len), &global.span));
+            }
+            values.iter().map(|expr| value(expr, element, strings)).collect::<Result<Vec<String>, Diagnostic>>().map(|data| data.join(" "))
+        },
+        (Init::List(_), ty) => Err(error(format!("a '{}' can't be given a list of values", program.type_name(ty)), &global.span)),
+    }
+}
+
+// the .cisc src compiled from the c-like src (of the file in the source map)
+pub fn compile(text: &str, file: usize, path: &str) -> Result<String, Vec<Diagnostic>> {
+    let tokens = lex(text, file).map_err(|error| vec![error])?;
+    let program = Parser { tokens, index: 0, structs: vec![] }.program().map_err(|error| vec![error])?;
+    let mut errors = vec![];
+    let names = program.globals.iter().map(|global| (&global.name, &global.span)).chain(program.functions.iter().map(|function| (&function.name, &function.span)));
+    for (index, (name, span)) in names.clone().enumerate() {
+        if let Some((_, other)) = names.clone().take(index).find(|(other, _)| *other == name) {
+            errors.push(error(format!("'{}' is defined more than once", name), span).with_spanned_note("it's first defined here", other.clone()));
+        }
+    }
+    match program.functions.iter().find(|function| function.name == "main") {
+        Some(main) if !main.params.is_empty() => errors.push(error("main doesn't take any params", &main.span)),
+        Some(_) => {},
+        None => errors.push(Diagnostic::error(codes::COMPILE, "there's no main function").with_span(Span { file, line: 0, columns: 0..0 })),
+    }
+
+    // the globals, then the strings, from the start of ram
+    let mut data = vec![];
+    let mut strings = vec![];
+    let mut addr = 0;
+    for global in &program.globals {
+        data.push(format!("!define {} {}", global.name, addr));
+        match global_data(global, &program, &mut strings) {
+            Ok(values) if values.is_empty() => {},
+            Ok(values) => data.push(format!("!alloc {} {}", global.name, values)),
+            Err(error) => errors.push(error),
+        }
+        addr += program.size(&global.ty);
+    }
+    let Some(first) = program.functions.first() else { return Err(errors); };
+    let mut codegen = Codegen {
+        program: &program, strings, errors, function: first, lines: vec![], scopes: vec![], frame: 0, frame_size: 0, loops: vec![], next_id: 0, dead: false,
+    };
+    let code = program.functions.iter().flat_map(|function| codegen.function(function)).collect::<Vec<String>>();
+    if !codegen.errors.is_empty() { return Err(codegen.errors); }
+    for (index, text) in codegen.strings.iter().enumerate() {
+        data.push(format!("!define __string_{} {}", index, addr));
+        data.push(format!("!alloc __string_{} {}", index, string_data(text)));
+        addr += text.chars().count() + 1;
+    }
+    if addr + FRAME_WORDS > u16::MAX as usize {
+        return Err(vec![Diagnostic::error(codes::COMPILE, format!("the globals & strings ({} words) don't leave room in ram for the frames", addr))]);
+    }
+
+    let mut lines = vec![format!("; compiled from {} by cisc cc", path)];
+    if !program.asm.iter().any(|line| line.trim_start().starts_with(".ram_size")) {
+        lines.push(format!(".ram_size {}", addr + FRAME_WORDS));
+    }
+    lines.extend(program.asm.iter().cloned());
+    lines.extend(data);
+    lines.push(String::new());
+    lines.push(format!("Ldi {} ${}  ; the frames start past the globals & strings", STACK_POINTER, addr));
+    lines.push(String::from("*Call #main"));
+    lines.push(String::from("*Kill  ; lint:allow(protected-instruction)  faults into the kernel (when unprotected), which ends the program"));
+    lines.extend(code);
+    Ok(formatter::format(&(lines.join("\n") + "\n")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compile;
+    use crate::tests::{register, run_text};
+
+    // what main returns, compiled and run as is, optimized, and position independent (which all have to agree)
+    fn run(text: &str) -> u16 {
+        let src = compile(text, 0, "test.c").unwrap_or_else(|errors| panic!("{:?}", errors.iter().map(|error| &error.message).collect::<Vec<_>>()));
+        let results = [&[][..], &["-O"], &["--pic"]].map(|args| register(&run_text(&src, args), "rda"));
+        assert!(results.iter().all(|result| *result == results[0]), "the runs disagree: {:?}", results);
+        results[0]
+    }
+
+    fn errors(text: &str) -> Vec<String> {
+        compile(text, 0, "test.c").err().unwrap_or_default().into_iter().map(|error| error.message).collect()
+    }
+
+    #[test]
+    fn recursion() {
+        assert_eq!(run("u16 fib(u16 n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }\nu16 main() { return fib(10); }"), 55);
+    }
+
+    #[test]
+    fn struct_fields() {
+        assert_eq!(run("\
+            struct pair { u16 a; u16 b; };
+            struct pair global;
+            u16 main() {
+                struct pair local;
+                struct pair *at = &global;
+                local.a = 3;
+                at->b = 4;
+                global.a = local.a + 1;
+                return local.a * 100 + global.a * 10 + at->b;
+            }"), 344);
+    }
+
+    #[test]
+    fn arrays() {
+        assert_eq!(run("\
+            u16 squares[5];
+            u16 primes[4] = {2, 3, 5, 7};
+            u16 main() {
+                u16 i = 0;
+                while (i < 5) { squares[i] = i * i; i += 1; }
+                u16 *at = &primes[1];
+                return squares[4] + squares[3] + at[2] * 100;
+            }"), 725);
+    }
+
+    #[test]
+    fn break_and_continue() {
+        assert_eq!(run("\
+            u16 main() {
+                u16 i = 0;
+                u16 total = 0;
+                while (1) {
+                    i += 1;
+                    if (i == 3) continue;
+                    if (i > 6) break;
+                    total += i;
+                }
+                return total;
+            }"), 1 + 2 + 4 + 5 + 6);
+    }
+
+    #[test]
+    fn short_circuits() {
+        // bump is only called where the left side doesn't decide it
+        assert_eq!(run("\
+            u16 calls;
+            u16 bump() { calls += 1; return 1; }
+            u16 main() {
+                u16 results = (0 && bump()) + (1 || bump()) * 10 + (1 && bump()) * 100 + (0 || bump()) * 1000;
+                return results + calls * 10000;
+            }"), 21110);
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(errors("u16 main() { return missing + 1; }"), ["undefined variable 'missing'"]);
+        assert_eq!(errors("u16 f(u16 a) { return a; }\nu16 main() { return f(1, 2); }"), ["'f' takes 1 arg, but 2 were given"]);
+        assert_eq!(errors("u16 values[0];\nvoid main() {}"), ["an array's length has to be a constant above 0"]);
+    }
+}
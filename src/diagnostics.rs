@@ -0,0 +1,355 @@
+// assembler diagnostics; errors and warnings pointing into the src files,
+//    rendered rustc style for people or as json (one object per line) for editors
+
+use std::io::IsTerminal;
+use std::ops::Range;
+
+// the error codes, grouped roughly by the stage reporting them
+pub mod codes {
+    pub const SYNTAX: &str = "E0001";  // malformed line (missing names, operands, brackets, etc.)
+    pub const UNKNOWN_DIRECTIVE: &str = "E0002";  // !label or .trait kind that doesn't exist
+    pub const REDEFINITION: &str = "E0003";  // label or macro defined twice
+    pub const UNKNOWN_INSTRUCTION: &str = "E0004";
+    pub const OPERAND_COUNT: &str = "E0005";
+    pub const OPERAND_TYPE: &str = "E0006";
+    pub const UNKNOWN_REGISTER: &str = "E0007";
+    pub const EXPRESSION: &str = "E0008";  // invalid { ... } expression or number
+    pub const MACRO: &str = "E0009";  // invalid macro definition or call
+    pub const ALLOC: &str = "E0010";  // invalid or overlapping !alloc
+    pub const STRING: &str = "E0011";  // invalid string literal
+    pub const INCLUDE: &str = "E0012";  // unreadable or cyclic !include
+    pub const UNDEFINED_SYMBOL: &str = "E0013";
+    pub const OUTPUT: &str = "E0014";  // couldn't write an output file (the listing, etc.)
+    pub const SYMBOL_MAP: &str = "E0015";  // unreadable or malformed symbol map
+    pub const LINK: &str = "E0016";  // unresolved or conflicting symbols, overlapping sections, or a malformed object
+    pub const IMAGE: &str = "E0017";  // unreadable or malformed disc image
+    pub const DATA: &str = "E0018";  // invalid .word/.fill/.ascii/.string/.align data
+    pub const CONDITIONAL: &str = "E0019";  // unbalanced or invalid .if/.ifdef/.ifndef/.else/.endif, or an invalid -D
+    pub const CONTROL_FLOW: &str = "E0020";  // unbalanced !if/!else/!endif or !while/!endwhile, a stray !break/!continue, or an invalid condition
+    pub const OPERAND_RANGE: &str = "E0021";  // an operand that doesn't fit in its param's width, or a trait value that doesn't fit in a byte pair
+    pub const UNFORMATTED: &str = "E0022";  // a file fmt --check found not formatted
+    pub const COMPILE: &str = "E0023";  // an error in the c-like src given to cc
+    pub const INTERNAL: &str = "E0999";  // assembler bug; should never be seen
+    
+    pub const EXTRA_TOKENS: &str = "W0001";  // tokens after a value that get ignored
+    // the lints; errors instead when denied
+    pub const UNREACHABLE_CODE: &str = "W0002";  // code after a *Jmp, *Ret, *Kill, etc. with no label leading to it
+    pub const RET_WITHOUT_CALL: &str = "W0003";  // a *Ret no *Call leads to
+    pub const PROTECTED_INSTRUCTION: &str = "W0004";  // an instruction that faults when running unprotected
+    pub const KERNEL_REGISTER: &str = "W0005";  // %rdz, which the kernel overwrites while scheduling
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+
+    fn color(&self) -> &'static str {
+        match self {
+            Severity::Error => "\x1b[1;31m",
+            Severity::Warning => "\x1b[1;33m",
+        }
+    }
+}
+
+// a range of columns (byte offsets) on a single line (0 based) of a src file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub file: usize,
+    pub line: usize,
+    pub columns: Range<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Note {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub span: Option<Span>,
+    pub notes: Vec<Note>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Diagnostic { severity: Severity::Error, code, message: message.into(), span: None, notes: vec![] }
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Self {
+        Diagnostic { severity: Severity::Warning, code, message: message.into(), span: None, notes: vec![] }
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_note(mut self, message: impl Into<String>) -> Self {
+        self.notes.push(Note { message: message.into(), span: None });
+        self
+    }
+
+    // a note with its own src excerpt (where something was declared, the macro call it came from, etc.)
+    pub fn with_spanned_note(mut self, message: impl Into<String>, span: Span) -> Self {
+        self.notes.push(Note { message: message.into(), span: Some(span) });
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    // the rustc style text:
+    //    error[E0007]: unknown register 'rdq'
+    //     --> scripts/test.cisc:4:6
+    //      |
+    //    4 | Ldi %rdq $1
+    //      |      ^^^
+    pub fn render(&self, sources: &SourceMap, color: bool) -> String {
+        let paint = |style: &str, text: &str| if color { format!("{}{}\x1b[0m", style, text) } else { text.to_string() };
+        let gutter_width = std::iter::once(&self.span).chain(self.notes.iter().map(|note| &note.span))
+            .flatten()
+            .map(|span| (span.line + 1).to_string().len())
+            .max().unwrap_or(0);
+        let mut text = format!(
+            "{}{}\n",
+            paint(self.severity.color(), &format!("{}[{}]", self.severity.name(), self.code)),
+            paint("\x1b[1m", &format!(": {}", self.message))
+        );
+        if let Some(span) = &self.span {
+            text.push_str(&render_excerpt(span, sources, gutter_width, &paint));
+        }
+        for note in &self.notes {
+            match &note.span {
+                Some(span) => {
+                    text.push_str(&format!("{}: {}\n", paint("\x1b[1;32m", "note"), note.message));
+                    text.push_str(&render_excerpt(span, sources, gutter_width, &paint));
+                },
+                None => {
+                    text.push_str(&format!("{} {} {} {}\n", " ".repeat(gutter_width), paint("\x1b[1;34m", "="), paint("\x1b[1m", "note:"), note.message));
+                },
+            }
+        } text
+    }
+
+    // a single line json object; lines and columns are 1 based (columns counted in chars), with the end exclusive
+    pub fn to_json(&self, sources: &SourceMap) -> String {
+        let notes = self.notes.iter().map(|note| format!(
+            "{{\"message\":{},\"span\":{}}}", json_string(&note.message), span_json(note.span.as_ref(), sources)
+        )).collect::<Vec<String>>().join(",");
+        format!(
+            "{{\"severity\":\"{}\",\"code\":\"{}\",\"message\":{},\"span\":{},\"notes\":[{}],\"rendered\":{}}}",
+            self.severity.name(), self.code, json_string(&self.message), span_json(self.span.as_ref(), sources),
+            notes, json_string(&self.render(sources, false))
+        )
+    }
+}
+
+fn render_excerpt(span: &Span, sources: &SourceMap, gutter_width: usize, paint: &dyn Fn(&str, &str) -> String) -> String {
+    let line = sources.line(span.file, span.line).unwrap_or("");
+    let start = span.columns.start.min(line.len());
+    let end = span.columns.end.clamp(start, line.len());
+    let (column, _) = char_columns(line, start..end);
+    let blank = " ".repeat(gutter_width);
+    let bar = paint("\x1b[1;34m", "|");
+    // tabs are copied over so the carets line up with however the terminal renders them
+    let padding = line[..start].chars().map(|chr| if chr == '\t' { '\t' } else { ' ' }).collect::<String>();
+    let carets = "^".repeat(line[start..end].chars().count().max(1));
+    format!(
+        "{}{} {}:{}:{}\n{} {}\n{} {} {}\n{} {} {}{}\n",
+        blank, paint("\x1b[1;34m", "-->"), sources.path(span.file), span.line + 1, column,
+        blank, bar,
+        paint("\x1b[1;34m", &format!("{:>width$}", span.line + 1, width = gutter_width)), bar, line,
+        blank, bar, padding, paint("\x1b[1;31m", &carets),
+    )
+}
+
+// the 1 based char columns of a byte range within the line; (start, exclusive end)
+fn char_columns(line: &str, columns: Range<usize>) -> (usize, usize) {
+    let start = columns.start.min(line.len());
+    let end = columns.end.clamp(start, line.len());
+    let start_column = line.get(..start).map_or(0, |text| text.chars().count()) + 1;
+    (start_column, start_column + line.get(start..end).map_or(0, |text| text.chars().count()))
+}
+
+fn span_json(span: Option<&Span>, sources: &SourceMap) -> String {
+    let Some(span) = span else { return String::from("null"); };
+    let (column_start, column_end) = char_columns(sources.line(span.file, span.line).unwrap_or(""), span.columns.clone());
+    format!(
+        "{{\"file\":{},\"line\":{},\"column_start\":{},\"column_end\":{}}}",
+        json_string(sources.path(span.file)), span.line + 1, column_start, column_end
+    )
+}
+
+pub fn json_string(text: &str) -> String {
+    let mut escaped = String::from("\"");
+    for chr in text.chars() {
+        match chr {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            chr if (chr as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", chr as u32)),
+            chr => escaped.push(chr),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageFormat {
+    Human,
+    Json,
+}
+
+// prints the diagnostics to stderr; human output ends with a count of the errors & warnings
+pub fn emit(diagnostics: &[Diagnostic], sources: &SourceMap, format: MessageFormat) {
+    match format {
+        MessageFormat::Json => {
+            for diagnostic in diagnostics {
+                eprintln!("{}", diagnostic.to_json(sources));
+            }
+        },
+        MessageFormat::Human => {
+            let color = std::io::stderr().is_terminal();
+            for diagnostic in diagnostics {
+                eprintln!("{}", diagnostic.render(sources, color));
+            }
+            let errors = diagnostics.iter().filter(|diagnostic| diagnostic.is_error()).count();
+            let warnings = diagnostics.len() - errors;
+            if errors > 0 {
+                eprintln!(
+                    "{}: aborting due to {} previous error{}{}",
+                    if color { "\x1b[1;31merror\x1b[0m" } else { "error" }, errors, if errors == 1 { "" } else { "s" },
+                    if warnings > 0 { format!("; {} warning{} emitted", warnings, if warnings == 1 { "" } else { "s" }) } else { String::new() }
+                );
+            } else if warnings > 0 {
+                eprintln!(
+                    "{}: {} warning{} emitted",
+                    if color { "\x1b[1;33mwarning\x1b[0m" } else { "warning" }, warnings, if warnings == 1 { "" } else { "s" }
+                );
+            }
+        },
+    }
+}
+
+// every src file read in, so diagnostics can quote them
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: Vec<(String, String)>,  // path, text
+}
+
+impl SourceMap {
+    pub fn add(&mut self, path: impl Into<String>, text: String) -> usize {
+        self.files.push((path.into(), text));
+        self.files.len() - 1
+    }
+
+    pub fn path(&self, file: usize) -> &str {
+        self.files.get(file).map_or("<unknown>", |(path, _)| path.as_str())
+    }
+
+    pub fn text(&self, file: usize) -> &str {
+        self.files.get(file).map_or("", |(_, text)| text.as_str())
+    }
+
+    pub fn line(&self, file: usize, line: usize) -> Option<&str> {
+        self.text(file).lines().nth(line)
+    }
+
+    // every file's lines, back to back
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        self.files.iter().flat_map(|(_, text)| text.lines())
+    }
+
+    // the index of a file's line within lines()
+    pub fn global_line(&self, file: usize, line: usize) -> usize {
+        self.files.iter().take(file).map(|(_, text)| text.lines().count()).sum::<usize>() + line
+    }
+
+    // the whole of a line, minus the indentation and trailing whitespace
+    pub fn line_span(&self, file: usize, line: usize) -> Span {
+        let text = self.line(file, line).unwrap_or("");
+        let start = text.len() - text.trim_start().len();
+        Span { file, line, columns: start..text.trim_end().len().max(start) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an unknown register on a tab indented line, with a note pointing back at the !header and one without a span
+    fn diagnostic() -> (Diagnostic, SourceMap) {
+        let mut sources = SourceMap::default();
+        let file = sources.add("test.cisc", String::from("!header main\n\tLdi %rdq $1  ; é\n"));
+        let diagnostic = Diagnostic::error(codes::UNKNOWN_REGISTER, "unknown register 'rdq'")
+            .with_span(Span { file, line: 1, columns: 6..9 })
+            .with_spanned_note("the header is here", Span { file, line: 0, columns: 8..12 })
+            .with_note("registers are rda through rdz");
+        (diagnostic, sources)
+    }
+
+    static RENDERED: &str = "\
+error[E0007]: unknown register 'rdq'
+ --> test.cisc:2:7
+  |
+2 | \tLdi %rdq $1  ; é
+  | \t     ^^^
+note: the header is here
+ --> test.cisc:1:9
+  |
+1 | !header main
+  |         ^^^^
+  = note: registers are rda through rdz
+";
+
+    #[test]
+    fn renders_like_rustc() {
+        let (diagnostic, sources) = diagnostic();
+        assert_eq!(diagnostic.render(&sources, false), RENDERED);
+        let colored = diagnostic.render(&sources, true);
+        assert!(colored.starts_with("\x1b[1;31merror[E0007]\x1b[0m\x1b[1m: unknown register 'rdq'\x1b[0m\n"));
+        // without a span, it's only the message
+        assert_eq!(Diagnostic::warning(codes::EXTRA_TOKENS, "ignored").render(&sources, false), "warning[W0001]: ignored\n");
+    }
+
+    #[test]
+    fn renders_as_json() {
+        let (diagnostic, sources) = diagnostic();
+        assert_eq!(diagnostic.to_json(&sources), format!(
+            "{{\"severity\":\"error\",\"code\":\"E0007\",\"message\":\"unknown register 'rdq'\",\
+            \"span\":{{\"file\":\"test.cisc\",\"line\":2,\"column_start\":7,\"column_end\":10}},\
+            \"notes\":[{{\"message\":\"the header is here\",\"span\":{{\"file\":\"test.cisc\",\"line\":1,\"column_start\":9,\"column_end\":13}}}},\
+            {{\"message\":\"registers are rda through rdz\",\"span\":null}}],\
+            \"rendered\":{}}}", json_string(RENDERED)
+        ));
+        assert!(crate::json::parse(&diagnostic.to_json(&sources)).is_ok());
+    }
+
+    #[test]
+    fn columns_count_chars() {
+        // the comment's 'é' being 2 bytes
+        let (_, sources) = diagnostic();
+        let warning = Diagnostic::warning(codes::EXTRA_TOKENS, "a comment").with_span(Span { file: 0, line: 1, columns: 14..18 });
+        assert!(warning.to_json(&sources).contains("\"column_start\":15,\"column_end\":18"));
+        assert!(warning.render(&sources, false).contains(" --> test.cisc:2:15\n"));
+        assert!(warning.render(&sources, false).ends_with("  | \t             ^^^\n"));
+    }
+}
@@ -0,0 +1,83 @@
+// the disassembler; decodes byte pairs back into native instructions through INSTRUCTIONS,
+//    written so they'd assemble back into the same byte pairs
+
+use crate::encoding;
+use crate::symbol_map::SymbolMap;
+use crate::{Param, INSTRUCTIONS, REGISTERS, RELATIVE_FORMS};
+
+fn register(index: u8) -> String {
+    match REGISTERS.get(index as usize) {
+        Some(name) => format!("%{}", name),
+        None => format!("%r{}", index),  // past the named registers; not something that'd assemble
+    }
+}
+
+// the instruction at the start of the byte pairs and how many byte pairs it took up (3, or more for long operands)
+//    an address operand naming a label is written as such, with the nearest label noted for those that don't; the
+//    offsets of the pc-relative jumps are signed
+//    None when the op code isn't an instruction, or the byte pairs run out partway through it
+pub fn decode(byte_pairs: &[u16], symbols: Option<&SymbolMap>) -> Option<(String, usize)> {
+    let op_code = (*byte_pairs.first()? >> 8) as u8;
+    let instruction = INSTRUCTIONS.iter().find(|instruction| instruction.op_code == op_code)?;
+    let len = encoding::instruction_len(instruction.params);
+    if len > byte_pairs.len() { return None; }
+    let relative = RELATIVE_FORMS.iter().any(|(_, relative)| *relative == instruction.name);
+
+    let mut operands = vec![];
+    let mut notes = vec![];
+    for (param, value) in instruction.params.iter().zip(encoding::decode_operands(instruction.params, byte_pairs)) {
+        operands.push(match param {
+            Param::Reg => register(value as u8),
+            Param::Ptr => format!("[{}]", register(value as u8)),
+            Param::Const16 if relative => format!("${}", value as u16 as i16),
+            Param::Const8 | Param::Const16 => format!("${}", value),
+            Param::Addr16 | Param::Addr32 => match symbols.and_then(|symbols| symbols.nearest_label(value as usize)) {
+                Some((name, 0)) => format!("#{}", name),
+                Some((name, past)) => {
+                    notes.push(format!("{}+{}", name, past));
+                    format!("#0x{:04x}", value)
+                },
+                None => format!("#0x{:04x}", value),
+            },
+        });
+    }
+    let mut text = format!("*{}", instruction.name);
+    if !operands.is_empty() { text = format!("{} {}", text, operands.join(" ")); }
+    if !notes.is_empty() { text = format!("{}  ; {}", text, notes.join(", ")); }
+    Some((text, len))
+}
+
+// a row per instruction (disc addr, the byte pairs, the instruction) under the labels at each addr
+//    runs of empty (all zero) instructions are collapsed, as sparse images are mostly them
+pub fn disassemble(byte_pairs: &[u16], start: usize, symbols: Option<&SymbolMap>) -> String {
+    let mut text = String::new();
+    let mut index = 0;
+    while index < byte_pairs.len() {
+        let addr = start + index;
+        if let Some(symbols) = symbols {
+            for name in symbols.labels_at(addr) {
+                text.push_str(&format!("{}:\n", name));
+            }
+        }
+        // up to the next label, as that's where the next instruction starts
+        let labelled = |at: usize| symbols.is_some_and(|symbols| symbols.labels_at(start + at).next().is_some());
+        let run = byte_pairs[index..].iter().enumerate()
+            .take_while(|(offset, byte_pair)| **byte_pair == 0 && (*offset == 0 || !labelled(index + offset)))
+            .count();
+        if run > 3 {
+            text.push_str(&format!("{:04x}  ; {} empty byte pairs ({:04x}..{:04x})\n", addr, run, addr, addr + run));
+            index += run;
+            continue;
+        }
+        let (instruction, len) = decode(&byte_pairs[index..], symbols).unwrap_or_else(|| {
+            let op_code = (byte_pairs[index] >> 8) as u8;
+            match INSTRUCTIONS.iter().find(|instruction| instruction.op_code == op_code) {
+                Some(instruction) => (format!("; *{} cut off by the end of the byte pairs", instruction.name), 1),
+                None => (format!("; unknown op code 0x{:02x}", op_code), 1),
+            }
+        });
+        let words = byte_pairs[index..index + len].iter().map(|byte_pair| format!("{:04x}", byte_pair)).collect::<Vec<String>>().join(" ");
+        text.push_str(&format!("{:04x}  {:<14}  {}\n", addr, words, instruction));
+        index += len;
+    } text
+}
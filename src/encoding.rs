@@ -0,0 +1,150 @@
+// the operand layout of the native instructions; what the assembler encodes through, and the emulator &
+//    disassembler decode through, so the three can't drift apart
+//
+//    the op code byte, then each operand in the order of its params (multi byte ones low byte first),
+//    packed into byte pairs with the first byte high and padded out to 3 byte pairs
+
+use crate::diagnostics::{codes, Diagnostic};
+use crate::{Instruction, Param, Parameter};
+
+// the byte pairs every instruction takes up (what the emulator fetches per step)
+pub const INSTRUCTION_LEN: usize = 3;
+// the most operands any instruction takes
+pub const MAX_OPERANDS: usize = 5;
+
+// the bytes an operand takes up in the encoding
+pub fn param_size(param: &Param) -> usize {
+    match param {
+        Param::Const8 | Param::Reg | Param::Ptr => 1,
+        Param::Const16 | Param::Addr16 => 2,
+        Param::Addr32 => 4,
+    }
+}
+
+// the byte offset of an operand within the encoded instruction (just past the op code and the operands before it)
+pub fn param_offset(params: &[Param], param_index: usize) -> usize {
+    1 + params[..param_index].iter().map(param_size).sum::<usize>()
+}
+
+// the byte pairs an instruction with these params takes up
+pub fn instruction_len(params: &[Param]) -> usize {
+    param_offset(params, params.len()).div_ceil(2).max(INSTRUCTION_LEN)
+}
+
+// the op code followed by the operands
+pub fn encode_instruction(instruction: &Instruction, parameters: &[Parameter]) -> Result<Vec<u8>, Diagnostic> {
+    let mut bytes = vec![instruction.op_code];
+    for (param, parameter) in instruction.params.iter().zip(parameters) {
+        let value = match (param, parameter) {
+            (Param::Reg, Parameter::Register(value)) | (Param::Ptr, Parameter::Pointer(value)) => *value as u32,
+            (Param::Const8 | Param::Const16, Parameter::Constant(value)) => *value as u32,
+            (Param::Addr16 | Param::Addr32, Parameter::Address(value)) => *value,
+            _ => { return Err(Diagnostic::error(codes::INTERNAL, format!("invalid parameter: {:?}", parameter))); }
+        };
+        bytes.extend(&value.to_le_bytes()[..param_size(param)]);
+    } Ok(bytes)
+}
+
+// packs the bytes into byte pairs (the first byte high), padded out to the 3 byte pairs every instruction takes at least
+pub fn pack_byte_pairs(bytes: &[u8]) -> Vec<u16> {
+    let mut byte_pairs = bytes.chunks(2)
+        .map(|pair| ((pair[0] as u16) << 8) | pair.get(1).copied().unwrap_or(0) as u16)
+        .collect::<Vec<u16>>();
+    if byte_pairs.len() < INSTRUCTION_LEN { byte_pairs.resize(INSTRUCTION_LEN, 0); }
+    byte_pairs
+}
+
+// the byte at an offset into the byte pairs (0 past the end)
+fn byte_at(byte_pairs: &[u16], offset: usize) -> u8 {
+    byte_pairs.get(offset / 2).map_or(0, |byte_pair| if offset.is_multiple_of(2) { (byte_pair >> 8) as u8 } else { *byte_pair as u8 })
+}
+
+// each operand's raw value, read from its param's position in the byte pairs (the op code's included);
+//    the slots past the params are left 0
+pub fn decode_operands(params: &[Param], byte_pairs: &[u16]) -> [u32; MAX_OPERANDS] {
+    let mut operands = [0; MAX_OPERANDS];
+    let mut offset = 1;
+    for (operand, param) in operands.iter_mut().zip(params) {
+        *operand = (0..param_size(param)).fold(0, |value, byte| value | (byte_at(byte_pairs, offset + byte) as u32) << (byte * 8));
+        offset += param_size(param);
+    } operands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::INSTRUCTIONS;
+
+    // the operands as the assembler would've been given them
+    fn decode_parameters(params: &[Param], byte_pairs: &[u16]) -> Vec<Parameter> {
+        params.iter().zip(decode_operands(params, byte_pairs)).map(|(param, value)| match param {
+            Param::Reg => Parameter::Register(value as u8),
+            Param::Ptr => Parameter::Pointer(value as u8),
+            Param::Const8 | Param::Const16 => Parameter::Constant(value as i64),
+            Param::Addr16 | Param::Addr32 => Parameter::Address(value),
+        }).collect()
+    }
+
+    // operands with a distinct value in every byte, so an operand read from the wrong position shows
+    fn sample_parameters(instruction: &Instruction) -> Vec<Parameter> {
+        instruction.params.iter().enumerate().map(|(index, param)| {
+            let index = index as u32;
+            match param {
+                Param::Reg => Parameter::Register(0x11 + index as u8),
+                Param::Ptr => Parameter::Pointer(0x21 + index as u8),
+                Param::Const8 => Parameter::Constant(0x31 + index as i64),
+                Param::Const16 => Parameter::Constant(0x4152 + index as i64),
+                Param::Addr16 => Parameter::Address(0x6173 + index),
+                Param::Addr32 => Parameter::Address(0x8192_A3B4 + index),
+            }
+        }).collect()
+    }
+
+    #[test]
+    fn every_instruction_round_trips() {
+        for instruction in INSTRUCTIONS {
+            let parameters = sample_parameters(instruction);
+            let byte_pairs = pack_byte_pairs(&encode_instruction(instruction, &parameters).unwrap());
+            assert_eq!((byte_pairs[0] >> 8) as u8, instruction.op_code, "{}'s op code", instruction.name);
+            assert_eq!(byte_pairs.len(), instruction_len(instruction.params), "{}'s length", instruction.name);
+            assert_eq!(decode_parameters(instruction.params, &byte_pairs), parameters, "{}'s operands", instruction.name);
+        }
+    }
+
+    #[test]
+    fn negative_constants_are_twos_complement() {
+        let instruction = INSTRUCTIONS.iter().find(|instruction| instruction.name == "StoPtrOff").unwrap();
+        let parameters = [Parameter::Pointer(1), Parameter::Constant(-2), Parameter::Register(3)];
+        let byte_pairs = pack_byte_pairs(&encode_instruction(instruction, &parameters).unwrap());
+        assert_eq!(decode_operands(instruction.params, &byte_pairs)[1], 0xFFFE);
+    }
+
+    #[test]
+    fn every_instruction_fits_a_fetch() {
+        for instruction in INSTRUCTIONS {
+            assert_eq!(instruction_len(instruction.params), INSTRUCTION_LEN, "{} doesn't fit in one fetch", instruction.name);
+            assert!(instruction.params.len() <= MAX_OPERANDS, "{} has too many operands", instruction.name);
+        }
+    }
+
+    #[test]
+    fn op_codes_are_unique() {
+        for (index, instruction) in INSTRUCTIONS.iter().enumerate() {
+            if let Some(other) = INSTRUCTIONS[..index].iter().find(|other| other.op_code == instruction.op_code) {
+                panic!("{} and {} share the op code {:#04x}", other.name, instruction.name, instruction.op_code);
+            }
+        }
+    }
+
+    #[test]
+    fn every_instruction_disassembles() {
+        for instruction in INSTRUCTIONS {
+            let byte_pairs = pack_byte_pairs(&encode_instruction(instruction, &sample_parameters(instruction)).unwrap());
+            let (text, len) = crate::disassembler::decode(&byte_pairs, None).unwrap();
+            assert_eq!(len, byte_pairs.len(), "{}'s length", instruction.name);
+            let mut words = text.split_whitespace();
+            assert_eq!(words.next(), Some(format!("*{}", instruction.name).as_str()));
+            assert_eq!(words.count(), instruction.params.len(), "{}'s operands: {}", instruction.name, text);
+        }
+    }
+}
@@ -0,0 +1,278 @@
+// the src formatter; re-emits each line from its tokens, indenting the blocks and lining the operands & comments of
+//    neighbouring lines up into columns; every line (and comment) is kept, so the lines still number the same
+//
+//    !header/!function bodies, !loop ... !end, !while, !if, !macro and .if/.ifdef/.ifndef blocks are each indented
+//    a level; lines that don't lex are left as written
+
+use crate::lexer::{self, Token, TokenKind};
+use crate::{INSTRUCTIONS, SUDO_INSTRUCTIONS};
+
+static INDENT: &str = "    ";
+
+// the directives opening a block, and the ones that can close (or split, for the else's) each
+static BLOCKS: &[(&str, &[&str])] = &[
+    ("!while", &["!endwhile"]),
+    ("!if", &["!else", "!endif"]),
+    ("!macro", &["!endmacro"]),
+    ("!loop", &["!end"]),
+    (".if", &[".else", ".endif"]),
+    (".ifdef", &[".else", ".endif"]),
+    (".ifndef", &[".else", ".endif"]),
+];
+static MIDDLES: &[&str] = &["!else", ".else"];
+// the data directives; the rest of the .'s are traits (.page, .name, etc.), which sit outside of any !header
+static DATA: &[&str] = &[".word", ".fill", ".ascii", ".string", ".align"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Columns {
+    Instruction,  // the mnemonic, then its operands
+    Definition,  // !const/!define and the name, then its value
+    None,
+}
+
+enum Line {
+    Blank,
+    Verbatim (String),
+    Comment (usize, String),  // depth, comment
+    Code { depth: usize, head: String, rest: String, columns: Columns, comment: Option<String> },
+}
+
+// the <name> of a macro param, rather than a comparison
+fn is_macro_param(tokens: &[Token], open: usize) -> bool {
+    matches!(tokens.get(open..open + 3), Some([open, name, close]) if open.is(TokenKind::Open, "<") && name.kind == TokenKind::Ident && close.is(TokenKind::Close, ">"))
+}
+
+// the tokens back into text; one space between them, except after sigils, unary minuses & opening brackets and before
+//    closing ones (a sigil keeps a space after it when it had one, as % is also modulo)
+fn join(tokens: &[Token]) -> String {
+    let mut text = String::new();
+    for (index, token) in tokens.iter().enumerate() {
+        if index > 0 {
+            let before = &tokens[index - 1];
+            let opens = before.kind == TokenKind::Open && (before.text != "<" || is_macro_param(tokens, index - 1));
+            let closes = token.kind == TokenKind::Close && (token.text != ">" || index >= 2 && is_macro_param(tokens, index - 2));
+            let unary = before.is(TokenKind::Operator, "-") && (index == 1 || matches!(tokens[index - 2].kind, TokenKind::Sigil | TokenKind::Open | TokenKind::Operator));
+            let native = index == 1 && before.is(TokenKind::Operator, "*");
+            let sigil = before.kind == TokenKind::Sigil && token.span.columns.start == before.span.columns.end;
+            if !(opens || closes || unary || native || sigil) { text.push(' '); }
+        }
+        text.push_str(&token.text);
+    } text
+}
+
+// the mnemonic as the tables spell it (macros being left as they're written)
+fn mnemonic(name: &str, native: bool) -> &str {
+    let canonical = match native {
+        true => INSTRUCTIONS.iter().map(|instruction| instruction.name).find(|other| other.eq_ignore_ascii_case(name)),
+        false => SUDO_INSTRUCTIONS.iter().map(|sudo| sudo.name).find(|other| other.eq_ignore_ascii_case(name)),
+    };
+    canonical.unwrap_or(name)
+}
+
+// the part that's lined up into a column, and the rest after it
+fn split(code: &[Token]) -> (String, String, Columns) {
+    let first = &code[0];
+    if first.kind == TokenKind::Directive {
+        return match (first.text.as_str(), code.get(1)) {
+            ("!const" | "!define", Some(name)) if code.len() > 2 => (format!("{} {}", first.text, name.text), join(&code[2..]), Columns::Definition),
+            _ => (join(code), String::new(), Columns::None),
+        };
+    }
+    let native = first.is(TokenKind::Operator, "*");
+    match code.get(native as usize) {
+        Some(name) if name.kind == TokenKind::Ident => {
+            let head = format!("{}{}", if native { "*" } else { "" }, mnemonic(&name.text, native));
+            (head, join(&code[native as usize + 1..]), Columns::Instruction)
+        },
+        _ => (join(code), String::new(), Columns::None),
+    }
+}
+
+// the depth of the line, given the blocks open before it (which it then opens or closes)
+fn depth(directive: &str, blocks: &mut Vec<String>) -> usize {
+    // a !header's (or !function's) body runs until the next one, a trait, or the end of the block it's in
+    if matches!(directive, "!header" | "!function") || directive.starts_with('.') && !DATA.contains(&directive) && !BLOCKS.iter().any(|(open, ends)| *open == directive || ends.contains(&directive)) {
+        if blocks.last().is_some_and(|block| block == "!header") { blocks.pop(); }
+        let depth = blocks.len();
+        if directive.starts_with('!') { blocks.push(String::from("!header")); }
+        return depth;
+    }
+    if BLOCKS.iter().any(|(open, _)| *open == directive) {
+        blocks.push(directive.to_string());
+        return blocks.len() - 1;
+    }
+    let closes = |block: &String| BLOCKS.iter().any(|(open, ends)| open == block && ends.contains(&directive));
+    match blocks.iter().rposition(closes) {
+        // !end only closes a !loop it's directly in (it's a plain label otherwise)
+        Some(index) if directive == "!end" && index + 1 != blocks.len() => blocks.len(),
+        Some(index) if MIDDLES.contains(&directive) => {
+            blocks.truncate(index + 1);
+            index
+        },
+        Some(index) => {
+            blocks.truncate(index);
+            index
+        },
+        None => blocks.len(),
+    }
+}
+
+pub fn format(text: &str) -> String {
+    let mut lines = vec![];
+    let mut blocks = vec![];
+    for (index, text) in text.lines().enumerate() {
+        let mut errors = vec![];
+        let mut code = lexer::lex_line(text, 0, index, &mut errors);
+        if !errors.is_empty() {
+            lines.push(Line::Verbatim(text.trim_end().to_string()));
+            continue;
+        }
+        let comment = code.pop_if(|token| token.kind == TokenKind::Comment).map(|token| token.text.trim_end().to_string());
+        lines.push(match (code.first(), comment) {
+            (None, None) => Line::Blank,
+            (None, Some(comment)) => Line::Comment(blocks.len(), comment),
+            (Some(first), comment) => {
+                let depth = match first.kind {
+                    TokenKind::Directive => depth(&first.text, &mut blocks),
+                    _ => blocks.len(),
+                };
+                let (head, rest, columns) = split(&code);
+                Line::Code { depth, head, rest, columns, comment }
+            },
+        });
+    }
+
+    // comments right above a line that's outdented (a !header, an !endif, etc.) go with it, rather than the block before it
+    let mut next_depth = None;
+    for line in lines.iter_mut().rev() {
+        match line {
+            Line::Code { depth, .. } => { next_depth = Some(*depth); },
+            Line::Comment(depth, _) => if let Some(next) = next_depth && next < *depth { *depth = next; },
+            _ => { next_depth = None; },
+        }
+    }
+
+    // runs of code at the same depth are lined up together
+    let mut formatted = String::new();
+    let mut start = 0;
+    while start < lines.len() {
+        let run_depth = match &lines[start] {
+            Line::Code { depth, .. } => Some(*depth),
+            _ => None,
+        };
+        let end = start + lines[start..].iter().take_while(|line| matches!((line, run_depth), (Line::Code { depth, .. }, Some(run)) if *depth == run)).count().max(1);
+        let width = |kind: Columns| lines[start..end].iter().filter_map(|line| match line {
+            Line::Code { head, rest, columns, .. } if *columns == kind && !rest.is_empty() => Some(head.chars().count()),
+            _ => None,
+        }).max().unwrap_or(0);
+        let widths = [(Columns::Instruction, width(Columns::Instruction)), (Columns::Definition, width(Columns::Definition))];
+        let codes = lines[start..end].iter().map(|line| match line {
+            Line::Code { depth, head, rest, columns, .. } if !rest.is_empty() => {
+                let width = widths.iter().find(|(kind, _)| kind == columns).map_or(0, |(_, width)| *width);
+                format!("{}{:<width$} {}", INDENT.repeat(*depth), head, rest, width = width)
+            },
+            Line::Code { depth, head, .. } => format!("{}{}", INDENT.repeat(*depth), head),
+            Line::Comment(depth, comment) => format!("{}{}", INDENT.repeat(*depth), comment),
+            Line::Verbatim(text) => text.clone(),
+            Line::Blank => String::new(),
+        }).collect::<Vec<String>>();
+        let comment_column = lines[start..end].iter().zip(&codes).filter_map(|(line, code)| match line {
+            Line::Code { comment: Some(_), .. } => Some(code.chars().count() + 2),
+            _ => None,
+        }).max().unwrap_or(0);
+        for (line, code) in lines[start..end].iter().zip(codes) {
+            match line {
+                Line::Code { comment: Some(comment), .. } => formatted.push_str(&format!("{:<width$}{}", code, comment, width = comment_column)),
+                _ => formatted.push_str(&code),
+            }
+            formatted.push('\n');
+        }
+        start = end;
+    } formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format;
+    use crate::tests::options;
+
+    static UNFORMATTED: &str = "\
+!const   LIMIT 3  ; the limit
+!const MAX_VALUE {LIMIT*2}
+!header main
+; counts up
+ldi %rda $0 ; start
+!while %rda < $LIMIT
+add %rda $1 %rda   ; one more
+*CALL   #bump ; bumped
+!if %rda == $2
+*jmp #done
+.ifdef DEBUG
+*ldi %rdb $1
+.endif
+!endif
+!endwhile
+
+!alloc #0 \"a ; not a comment\" ; but this is
+!label done
+*kill
+";
+
+    static FORMATTED: &str = "\
+!const LIMIT     3  ; the limit
+!const MAX_VALUE {LIMIT * 2}
+!header main
+    ; counts up
+    Ldi %rda $0  ; start
+    !while %rda < $LIMIT
+        Add   %rda $1 %rda  ; one more
+        *Call #bump         ; bumped
+        !if %rda == $2
+            *Jmp #done
+            .ifdef DEBUG
+                *Ldi %rdb $1
+            .endif
+        !endif
+    !endwhile
+
+    !alloc #0 \"a ; not a comment\"  ; but this is
+    !label done
+    *Kill
+";
+
+    #[test]
+    fn indents_aligns_and_normalises() {
+        assert_eq!(format(UNFORMATTED), FORMATTED);
+        // every line (and so every comment) is kept where it was
+        assert_eq!(format(UNFORMATTED).lines().count(), UNFORMATTED.lines().count());
+    }
+
+    #[test]
+    fn is_idempotent() {
+        assert_eq!(format(FORMATTED), FORMATTED);
+        for text in ["", "\n\n", "; only a comment\n", "!macro twice <reg>\nadd <reg> <reg> <reg>\n!endmacro\ntwice %rda\n", "Ldi %rda \"open\n"] {
+            let formatted = format(text);
+            assert_eq!(format(&formatted), formatted, "{:?}", text);
+        }
+        // a line that doesn't lex is left as written
+        assert_eq!(format("  Ldi %rda \"open  \n"), "  Ldi %rda \"open\n");
+    }
+
+    #[test]
+    fn check_fails_on_unformatted_files() {
+        let dir = std::env::temp_dir().join(format!("cisc-fmt-check-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (formatted, unformatted) = (dir.join("formatted.cisc"), dir.join("unformatted.cisc"));
+        std::fs::write(&formatted, FORMATTED).unwrap();
+        std::fs::write(&unformatted, UNFORMATTED).unwrap();
+        let check = |path: &std::path::Path| crate::fmt_command(&options(&["fmt", "--check", "-q", &path.display().to_string()]));
+        assert!(check(&formatted));
+        assert!(!check(&unformatted));
+        // --check leaves the file alone; without it, it's rewritten formatted
+        assert_eq!(std::fs::read_to_string(&unformatted).unwrap(), UNFORMATTED);
+        assert!(crate::fmt_command(&options(&["fmt", "-q", &unformatted.display().to_string()])));
+        assert_eq!(std::fs::read_to_string(&unformatted).unwrap(), FORMATTED);
+        assert!(check(&unformatted));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
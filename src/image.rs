@@ -0,0 +1,201 @@
+// disc images (.img); what's placed on the disc as sparse extents, the initialized ram for booting it,
+//    and optionally the src it came from so the emulator can trace back through it
+//
+//    all little endian
+//        "CISC-IMG", version u16, flags u16 (bit 0: has debug info)
+//        extent count u32; per extent: disc addr u32, byte pair count u32, the byte pairs
+//        alloc count u32; per alloc: ram addr u32, byte pair count u32, the byte pairs
+//        debug info (when flagged):
+//            line count u32; per line: byte count u32, the utf8 text
+//            trace count u32; per entry: disc addr u32, line index u32
+
+use crate::RamAllocs;
+
+pub const IMAGE_MAGIC: &[u8; 8] = b"CISC-IMG";
+pub const IMAGE_VERSION: u16 = 1;
+const FLAG_DEBUG_INFO: u16 = 1;
+pub const DISC_LEN: usize = u32::MAX as usize;  // byte pairs on the emulator's disc
+
+#[derive(Debug, Clone, Default)]
+pub struct DebugInfo {
+    pub lines: Vec<String>,  // every src file's lines back to back (comments stripped)
+    pub trace: Vec<(usize, usize)>,  // disc addr, index into the lines
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DiscImage {
+    pub extents: Vec<(usize, Vec<u16>)>,  // disc addr, byte pairs; sorted & not overlapping
+    pub allocs: RamAllocs,
+    pub debug: Option<DebugInfo>,
+}
+
+impl DiscImage {
+    // (disc addr, byte pair) in any order; neighbouring addrs are merged into one extent
+    pub fn from_byte_pairs(byte_pairs: impl Iterator<Item = (usize, u16)>) -> Self {
+        let mut byte_pairs = byte_pairs.collect::<Vec<(usize, u16)>>();
+        byte_pairs.sort_by_key(|(addr, _)| *addr);
+        let mut extents: Vec<(usize, Vec<u16>)> = vec![];
+        for (addr, byte_pair) in byte_pairs {
+            match extents.last_mut() {
+                Some((start, pairs)) if *start + pairs.len() == addr => pairs.push(byte_pair),
+                Some((start, pairs)) if *start + pairs.len() > addr => pairs[addr - *start] = byte_pair,  // placed twice; the last wins
+                _ => extents.push((addr, vec![byte_pair])),
+            }
+        }
+        DiscImage { extents, allocs: vec![], debug: None }
+    }
+
+    pub fn byte_pairs(&self) -> impl Iterator<Item = (usize, u16)> + '_ {
+        self.extents.iter().flat_map(|(start, pairs)| pairs.iter().enumerate().map(move |(index, byte_pair)| (start + index, *byte_pair)))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = IMAGE_MAGIC.to_vec();
+        bytes.extend(IMAGE_VERSION.to_le_bytes());
+        bytes.extend((if self.debug.is_some() { FLAG_DEBUG_INFO } else { 0 }).to_le_bytes());
+        for runs in [&self.extents, &self.allocs] {
+            bytes.extend((runs.len() as u32).to_le_bytes());
+            for (addr, byte_pairs) in runs {
+                bytes.extend((*addr as u32).to_le_bytes());
+                bytes.extend((byte_pairs.len() as u32).to_le_bytes());
+                bytes.extend(byte_pairs.iter().flat_map(|byte_pair| byte_pair.to_le_bytes()));
+            }
+        }
+        if let Some(debug) = &self.debug {
+            bytes.extend((debug.lines.len() as u32).to_le_bytes());
+            for line in &debug.lines {
+                bytes.extend((line.len() as u32).to_le_bytes());
+                bytes.extend(line.as_bytes());
+            }
+            bytes.extend((debug.trace.len() as u32).to_le_bytes());
+            for (addr, line) in &debug.trace {
+                bytes.extend((*addr as u32).to_le_bytes());
+                bytes.extend((*line as u32).to_le_bytes());
+            }
+        } bytes
+    }
+
+    // the error says why it isn't a (readable) image
+    pub fn from_bytes(data: &[u8]) -> Result<Self, String> {
+        if !data.starts_with(IMAGE_MAGIC) {
+            return Err(String::from("not a cisc disc image (missing the 'CISC-IMG' magic)"));
+        }
+        let mut reader = Reader { data, at: IMAGE_MAGIC.len() };
+        let version = reader.u16()?;
+        if version != IMAGE_VERSION {
+            return Err(format!("unsupported image version {} (expected {})", version, IMAGE_VERSION));
+        }
+        let flags = reader.u16()?;
+        let extents = reader.runs("extent")?;
+        let allocs = reader.runs("alloc")?;
+        if let Some((addr, _)) = extents.iter().find(|(addr, byte_pairs)| addr + byte_pairs.len() > DISC_LEN) {
+            return Err(format!("an extent at {:04x} runs past the end of the disc", addr));
+        }
+        if let Some((addr, _)) = allocs.iter().find(|(addr, byte_pairs)| addr + byte_pairs.len() > u16::MAX as usize) {
+            return Err(format!("an alloc at {:04x} runs past the end of ram", addr));
+        }
+        for pair in extents.windows(2) {
+            if pair[0].0 + pair[0].1.len() > pair[1].0 {
+                return Err(format!("the extents at disc {:04x} and {:04x} are out of order or overlap", pair[0].0, pair[1].0));
+            }
+        }
+        let debug = if flags & FLAG_DEBUG_INFO != 0 {
+            let lines = (0..reader.u32()?).map(|_| {
+                let len = reader.u32()? as usize;
+                String::from_utf8(reader.take(len)?.to_vec()).map_err(|_| String::from("a src line of the debug info isn't utf8"))
+            }).collect::<Result<Vec<String>, String>>()?;
+            let trace = (0..reader.u32()?).map(|_| {
+                let (addr, line) = (reader.u32()? as usize, reader.u32()? as usize);
+                if line >= lines.len() { return Err(format!("the debug info traces disc {:04x} to line {}, past the {} src lines", addr, line + 1, lines.len())); }
+                Ok((addr, line))
+            }).collect::<Result<Vec<(usize, usize)>, String>>()?;
+            Some(DebugInfo { lines, trace })
+        } else { None };
+        if reader.at != data.len() {
+            return Err(format!("{} unexpected bytes at the end", data.len() - reader.at));
+        }
+        Ok(DiscImage { extents, allocs, debug })
+    }
+}
+
+struct Reader<'d> {
+    data: &'d [u8],
+    at: usize,
+}
+
+impl<'d> Reader<'d> {
+    fn take(&mut self, len: usize) -> Result<&'d [u8], String> {
+        let bytes = self.data.get(self.at..self.at.saturating_add(len)).ok_or_else(|| format!("cut off at byte {}", self.data.len()))?;
+        self.at += len;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        self.take(2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        self.take(4).map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    // the extents or allocs; a count, then each one's addr, length and byte pairs
+    fn runs(&mut self, kind: &str) -> Result<Vec<(usize, Vec<u16>)>, String> {
+        (0..self.u32()?).map(|_| {
+            let addr = self.u32()? as usize;
+            let len = self.u32()? as usize;
+            if addr + len > u32::MAX as usize {
+                return Err(format!("an {} at {:04x} runs past the end of the address space", kind, addr));
+            }
+            let bytes = self.take(len.saturating_mul(2))?;
+            Ok((addr, bytes.chunks(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect()))
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Emulator;
+
+    fn image() -> DiscImage {
+        let mut image = DiscImage::from_byte_pairs([(0, 0x0040), (1, 9), (2, 0), (3, 0x1234), (4, 0xABCD), (0x2000, 7)].into_iter());
+        image.allocs = vec![(2, vec![7, 'h' as u16, 'i' as u16])];
+        image.debug = Some(DebugInfo { lines: vec![String::from("!header main"), String::from("*Kill")], trace: vec![(3, 1), (0x2000, 0)] });
+        image
+    }
+
+    #[test]
+    fn round_trips_through_its_bytes() {
+        let image = image();
+        assert_eq!(image.extents, [(0, vec![0x0040, 9, 0, 0x1234, 0xABCD]), (0x2000, vec![7])]);
+        let bytes = image.to_bytes();
+        let parsed = DiscImage::from_bytes(&bytes).unwrap();
+        assert_eq!(format!("{:?}", parsed), format!("{:?}", image));
+        assert_eq!(parsed.to_bytes(), bytes);
+        let plain = DiscImage { debug: None, ..image };
+        assert!(DiscImage::from_bytes(&plain.to_bytes()).unwrap().debug.is_none());
+    }
+
+    #[test]
+    fn rejects_a_truncated_or_corrupt_image() {
+        let bytes = image().to_bytes();
+        assert!(DiscImage::from_bytes(b"CISC-IM").unwrap_err().contains("not a cisc disc image"));
+        assert!(DiscImage::from_bytes(&[b"cisc-img", &bytes[8..]].concat()).unwrap_err().contains("not a cisc disc image"));
+        assert!(DiscImage::from_bytes(&[&bytes[..8], &2u16.to_le_bytes()[..], &bytes[10..]].concat()).unwrap_err().contains("unsupported image version"));
+        for len in [9, 20, bytes.len() - 1] {
+            assert!(DiscImage::from_bytes(&bytes[..len]).unwrap_err().starts_with("cut off"), "cut off at {}", len);
+        }
+        assert_eq!(DiscImage::from_bytes(&[&bytes[..], &[0]].concat()).unwrap_err(), "1 unexpected bytes at the end");
+        // one extent, 0x20 byte pairs long, 0x10 from the end of the disc
+        let past_the_end = [&bytes[..12], &1u32.to_le_bytes(), &0xFFFF_FFF0u32.to_le_bytes(), &0x20u32.to_le_bytes()].concat();
+        assert!(DiscImage::from_bytes(&past_the_end).unwrap_err().contains("runs past the end"));
+    }
+
+    #[test]
+    fn isnt_loaded_past_the_end_of_the_disc() {
+        let mut emulator = Emulator::new(0x1_0000, vec![], vec![], false);
+        assert!(emulator.load_image(&image()).is_ok());
+        let image = DiscImage::from_byte_pairs([(0xFFFF, 1), (0x1_0000, 2)].into_iter());
+        assert_eq!(emulator.load_image(&image).unwrap_err(), "the extent at disc ffff runs past the end of the disc");
+    }
+}
@@ -0,0 +1,296 @@
+// a minimal json value; what the language server reads its json-rpc messages into, and writes its replies out of
+
+use crate::diagnostics::json_string;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool (bool),
+    Number (f64),
+    String (String),
+    Array (Vec<Json>),
+    Object (Vec<(String, Json)>),  // in the order given
+}
+
+static NULL: Json = Json::Null;
+
+impl Json {
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+
+    // the field of an object; null when missing (or not an object), so lookups can be chained
+    pub fn get(&self, key: &str) -> &Json {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(name, _)| name == key).map_or(&NULL, |(_, value)| value),
+            _ => &NULL,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Json::Number(number) if *number >= 0.0 && number.fract() == 0.0 => Some(*number as usize),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for Json {
+    fn from(text: &str) -> Self {
+        Json::String(text.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(text: String) -> Self {
+        Json::String(text)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(number: usize) -> Self {
+        Json::Number(number as f64)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Json::Bool(value)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(values: Vec<Json>) -> Self {
+        Json::Array(values)
+    }
+}
+
+impl std::fmt::Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(number) if number.fract() == 0.0 && number.abs() < 1e15 => write!(f, "{}", *number as i64),
+            Json::Number(number) if number.is_finite() => write!(f, "{}", number),
+            Json::Number(_) => write!(f, "null"),
+            Json::String(text) => write!(f, "{}", json_string(text)),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 { write!(f, ",")?; }
+                    write!(f, "{}", value)?;
+                } write!(f, "]")
+            },
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (index, (key, value)) in fields.iter().enumerate() {
+                    if index > 0 { write!(f, ",")?; }
+                    write!(f, "{}:{}", json_string(key), value)?;
+                } write!(f, "}}")
+            },
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Json, String> {
+    let mut parser = Parser { chars: text.chars().collect(), index: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.index < parser.chars.len() {
+        return Err(format!("unexpected '{}' after the value", parser.chars[parser.index]));
+    } Ok(value)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    index: usize,
+}
+
+impl Parser {
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.index).is_some_and(|chr| chr.is_whitespace()) { self.index += 1; }
+    }
+
+    fn next(&mut self) -> Result<char, String> {
+        let chr = self.chars.get(self.index).copied().ok_or("unexpected end of the json")?;
+        self.index += 1;
+        Ok(chr)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.next()? {
+            chr if chr == expected => Ok(()),
+            chr => Err(format!("expected '{}', but found '{}'", expected, chr)),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Json) -> Result<Json, String> {
+        for expected in keyword.chars() {
+            if self.next()? != expected { return Err(format!("expected '{}'", keyword)); }
+        } Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.chars.get(self.index).copied().ok_or("unexpected end of the json")? {
+            'n' => self.keyword("null", Json::Null),
+            't' => self.keyword("true", Json::Bool(true)),
+            'f' => self.keyword("false", Json::Bool(false)),
+            '"' => Ok(Json::String(self.string()?)),
+            '[' => {
+                self.index += 1;
+                let mut values = vec![];
+                self.skip_whitespace();
+                if self.chars.get(self.index) == Some(&']') {
+                    self.index += 1;
+                    return Ok(Json::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.next()? {
+                        ',' => {},
+                        ']' => { return Ok(Json::Array(values)); },
+                        chr => { return Err(format!("expected ',' or ']', but found '{}'", chr)); },
+                    }
+                }
+            },
+            '{' => {
+                self.index += 1;
+                let mut fields = vec![];
+                self.skip_whitespace();
+                if self.chars.get(self.index) == Some(&'}') {
+                    self.index += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    fields.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.next()? {
+                        ',' => {},
+                        '}' => { return Ok(Json::Object(fields)); },
+                        chr => { return Err(format!("expected ',' or '}}', but found '{}'", chr)); },
+                    }
+                }
+            },
+            _ => self.number(),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.index;
+        while self.chars.get(self.index).is_some_and(|chr| chr.is_ascii_digit() || matches!(chr, '-' | '+' | '.' | 'e' | 'E')) {
+            self.index += 1;
+        }
+        let text = self.chars[start..self.index].iter().collect::<String>();
+        text.parse::<f64>().map(Json::Number).map_err(|_| format!("invalid value '{}'", text))
+    }
+
+    fn hex_escape(&mut self) -> Result<u32, String> {
+        let digits = (0..4).map(|_| self.next()).collect::<Result<String, String>>()?;
+        u32::from_str_radix(&digits, 16).map_err(|_| format!("invalid escape '\\u{}'", digits))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.next()? != '"' { return Err(String::from("expected a string")); }
+        let mut text = String::new();
+        loop {
+            match self.next()? {
+                '"' => { return Ok(text); },
+                '\\' => match self.next()? {
+                    'n' => text.push('\n'),
+                    't' => text.push('\t'),
+                    'r' => text.push('\r'),
+                    'b' => text.push('\u{8}'),
+                    'f' => text.push('\u{c}'),
+                    'u' => {
+                        let mut code = self.hex_escape()?;
+                        // the high half of a surrogate pair; the low half follows as its own escape
+                        if (0xD800..0xDC00).contains(&code) && self.chars.get(self.index..self.index + 2) == Some(&['\\', 'u']) {
+                            self.index += 2;
+                            let low = self.hex_escape()?;
+                            if (0xDC00..0xE000).contains(&low) {
+                                code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                            } else {
+                                // not a low half after all; the high one stands alone
+                                text.push(char::REPLACEMENT_CHARACTER);
+                                code = low;
+                            }
+                        }
+                        text.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                    },
+                    chr => text.push(chr),  // \" \\ \/
+                },
+                chr => text.push(chr),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_kind_of_value() {
+        let value = parse(r#" {"a": [1, -2.5, 1e3, true, false, null], "b": {}, "c": [], "d": "x"} "#).unwrap();
+        assert_eq!(value.get("a"), &Json::Array(vec![
+            Json::Number(1.0), Json::Number(-2.5), Json::Number(1000.0), Json::Bool(true), Json::Bool(false), Json::Null,
+        ]));
+        assert_eq!((value.get("b"), value.get("c"), value.get("d").as_str()), (&Json::Object(vec![]), &Json::Array(vec![]), Some("x")));
+        assert_eq!(value.get("missing").get("deeper"), &Json::Null);
+        assert_eq!(parse("3").unwrap().as_usize(), Some(3));
+        assert_eq!(parse("-3").unwrap().as_usize(), None);
+        assert_eq!(parse("3.5").unwrap().as_usize(), None);
+    }
+
+    #[test]
+    fn unescapes_strings() {
+        assert_eq!(parse(r#""a\"\\\/\n\t\r\b\f""#).unwrap().as_str(), Some("a\"\\/\n\t\r\u{8}\u{c}"));
+        assert_eq!(parse(r#""\u00e9\u0041""#).unwrap().as_str(), Some("éA"));
+        // a surrogate pair is one char; a half on its own isn't one
+        assert_eq!(parse(r#""\ud83d\ude00""#).unwrap().as_str(), Some("😀"));
+        assert_eq!(parse(r#""\ud83d""#).unwrap().as_str(), Some("\u{FFFD}"));
+        assert_eq!(parse(r#""\ud83d\u0041""#).unwrap().as_str(), Some("\u{FFFD}A"));
+        assert_eq!(parse(r#""\ude00x""#).unwrap().as_str(), Some("\u{FFFD}x"));
+        assert!(parse(r#""\u12g4""#).unwrap_err().contains("invalid escape"));
+    }
+
+    #[test]
+    fn rejects_what_isnt_json() {
+        assert_eq!(parse("1 2").unwrap_err(), "unexpected '2' after the value");
+        assert_eq!(parse("{} x").unwrap_err(), "unexpected 'x' after the value");
+        assert_eq!(parse("[1, 2").unwrap_err(), "unexpected end of the json");
+        assert_eq!(parse("[1 2]").unwrap_err(), "expected ',' or ']', but found '2'");
+        assert_eq!(parse(r#"{"a" 1}"#).unwrap_err(), "expected ':', but found '1'");
+        assert_eq!(parse(r#""open"#).unwrap_err(), "unexpected end of the json");
+        assert_eq!(parse("1.2.3").unwrap_err(), "invalid value '1.2.3'");
+        assert_eq!(parse("nul").unwrap_err(), "unexpected end of the json");
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn round_trips_through_its_text() {
+        let value = Json::object(vec![("text", "a\"b\n😀".into()), ("list", vec![Json::Null, 7usize.into(), Json::Number(0.5)].into())]);
+        assert_eq!(value.to_string(), r#"{"text":"a\"b\n😀","list":[null,7,0.5]}"#);
+        assert_eq!(parse(&value.to_string()).unwrap(), value);
+    }
+}
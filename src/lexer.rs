@@ -0,0 +1,189 @@
+// the tokenizer; turns each src line into typed tokens that know where they sit in the src
+
+use crate::diagnostics::{codes, Diagnostic, Span};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Directive,  // !label kinds and .traits; the text keeps the '!' or '.'
+    Ident,  // instruction, label, register and macro names (labels may be namespaced; module::label)
+    Number,  // 12, 0x1F, 0b101
+    String,  // "text"; the quotes and escapes are kept as written
+    Sigil,  // % register, $ @ constant, # address (% is also modulo inside of { ... })
+    Open,  // [ { ( <
+    Close,  // ] } ) >
+    Operator,  // + - * / & | ^ << >> (and * marking native instructions, and the <= >= == != comparisons of !if/!while)
+    Comment,  // ; to the end of the line
+    Unknown,  // anything else; reported when lexing
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub text: String,
+    pub span: Span,
+}
+
+impl Token {
+    pub fn is(&self, kind: TokenKind, text: &str) -> bool {
+        self.kind == kind && self.text == text
+    }
+}
+
+fn is_ident_start(chr: char) -> bool {
+    chr.is_ascii_alphabetic() || chr == '_'
+}
+
+fn is_ident_char(chr: char) -> bool {
+    chr.is_ascii_alphanumeric() || chr == '_'
+}
+
+// the byte index just past the closing quote of the string literal starting the text (None if it's never closed)
+pub fn string_literal_end(text: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (index, chr) in text.char_indices().skip(1) {
+        if escaped { escaped = false; continue; }
+        match chr {
+            '\\' => { escaped = true; },
+            '"' => { return Some(index + 1); },
+            _ => {},
+        }
+    } None
+}
+
+// steps over the chars matching the predicate, moving the end of the token along with them
+fn take_while(chars: &mut std::iter::Peekable<std::str::CharIndices>, end: &mut usize, predicate: fn(char) -> bool) {
+    while let Some(&(index, next)) = chars.peek() && predicate(next) {
+        *end = index + next.len_utf8();
+        chars.next();
+    }
+}
+
+// the tokens of a single src line (0 based line number); invalid characters and unterminated strings are reported
+//    commas are treated as whitespace, and the comment (if any) is the last token
+pub fn lex_line(text: &str, file: usize, line: usize, diagnostics: &mut Vec<Diagnostic>) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, chr)) = chars.next() {
+        // the end of the token, in bytes; single char tokens unless the kind says otherwise
+        let mut end = start + chr.len_utf8();
+        let kind = match chr {
+            chr if chr.is_whitespace() || chr == ',' => { continue; },
+            ';' => {
+                end = text.len();
+                while chars.next().is_some() {}
+                TokenKind::Comment
+            },
+            '"' => {
+                let closed = string_literal_end(&text[start..]);
+                end = start + closed.unwrap_or(text.len() - start);
+                while chars.peek().is_some_and(|&(index, _)| index < end) { chars.next(); }
+                if closed.is_none() {
+                    diagnostics.push(Diagnostic::error(codes::STRING, "unterminated string literal")
+                        .with_span(Span { file, line, columns: start..end }));
+                }
+                TokenKind::String
+            },
+            '!' | '=' if chars.peek().is_some_and(|&(_, next)| next == '=') => {
+                chars.next();
+                end += 1;
+                TokenKind::Operator
+            },
+            '!' | '.' => {
+                if chars.peek().is_some_and(|&(_, next)| is_ident_start(next)) {
+                    take_while(&mut chars, &mut end, is_ident_char);
+                    TokenKind::Directive
+                } else {
+                    diagnostics.push(Diagnostic::error(codes::SYNTAX, format!("expected a name right after the '{}'", chr))
+                        .with_span(Span { file, line, columns: start..end }));
+                    TokenKind::Unknown
+                }
+            },
+            chr if chr.is_ascii_digit() => {
+                take_while(&mut chars, &mut end, is_ident_char);
+                TokenKind::Number
+            },
+            chr if is_ident_start(chr) => {
+                // namespaced names (module::label) are a single token
+                loop {
+                    take_while(&mut chars, &mut end, is_ident_char);
+                    let mut ahead = chars.clone();
+                    if !matches!((ahead.next(), ahead.next(), ahead.peek()), (Some((_, ':')), Some((_, ':')), Some(&(_, next))) if is_ident_start(next)) { break; }
+                    chars.next();
+                    chars.next();
+                }
+                TokenKind::Ident
+            },
+            '%' | '$' | '#' | '@' => TokenKind::Sigil,
+            '<' | '>' if chars.peek().is_some_and(|&(_, next)| next == chr || next == '=') => {
+                chars.next();
+                end += 1;
+                TokenKind::Operator
+            },
+            '[' | '{' | '(' | '<' => TokenKind::Open,
+            ']' | '}' | ')' | '>' => TokenKind::Close,
+            '+' | '-' | '*' | '/' | '&' | '|' | '^' => TokenKind::Operator,
+            _ => {
+                diagnostics.push(Diagnostic::error(codes::SYNTAX, format!("unexpected character '{}'", chr))
+                    .with_span(Span { file, line, columns: start..end }));
+                TokenKind::Unknown
+            },
+        };
+        tokens.push(Token { kind, text: text[start..end].to_string(), span: Span { file, line, columns: start..end } });
+    } tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // (kind, text, columns) of each token
+    fn lex(text: &str) -> Vec<(TokenKind, String, std::ops::Range<usize>)> {
+        let mut diagnostics = vec![];
+        let tokens = lex_line(text, 0, 0, &mut diagnostics);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        tokens.into_iter().map(|token| (token.kind, token.text, token.span.columns)).collect()
+    }
+
+    fn kinds(text: &str) -> Vec<TokenKind> {
+        lex(text).into_iter().map(|(kind, ..)| kind).collect()
+    }
+
+    #[test]
+    fn tokens_know_their_kind_and_columns() {
+        use TokenKind::{Comment, Directive, Ident, Number, Operator, Sigil};
+        assert_eq!(lex("  *Ldi %rda, $0x1F ; load it"), [
+            (Operator, String::from("*"), 2..3), (Ident, String::from("Ldi"), 3..6), (Sigil, String::from("%"), 7..8),
+            (Ident, String::from("rda"), 8..11), (Sigil, String::from("$"), 13..14), (Number, String::from("0x1F"), 14..18),
+            (Comment, String::from("; load it"), 19..28),
+        ]);
+        assert_eq!(lex("!header io::print_char"), [(Directive, String::from("!header"), 0..7), (Ident, String::from("io::print_char"), 8..22)]);
+        assert_eq!(lex(".ascii \"a\\\"b;c\" ;x"), [(Directive, String::from(".ascii"), 0..6), (TokenKind::String, String::from("\"a\\\"b;c\""), 7..15), (Comment, String::from(";x"), 16..18)]);
+    }
+
+    #[test]
+    fn operators_take_their_longest_form() {
+        use TokenKind::{Close, Ident, Number, Open, Operator, Sigil};
+        assert_eq!(kinds("Get [%rda + @2] {x << 2 >> 1 % 3}"), [
+            Ident, Open, Sigil, Ident, Operator, Sigil, Number, Close, Open, Ident, Operator, Number, Operator, Number, Sigil, Number, Close,
+        ]);
+        assert_eq!(lex("<= >= == != < >").into_iter().map(|(kind, text, _)| (kind, text)).collect::<Vec<_>>(), [
+            (Operator, String::from("<=")), (Operator, String::from(">=")), (Operator, String::from("==")),
+            (Operator, String::from("!=")), (Open, String::from("<")), (Close, String::from(">")),
+        ]);
+        assert_eq!(kinds("Add <reg> <reg>"), [Ident, Open, Ident, Close, Open, Ident, Close]);
+    }
+
+    #[test]
+    fn reports_what_isnt_a_token() {
+        for (text, message, columns) in [
+            ("Ldi ? 1", "unexpected character '?'", 4..5),
+            (".ascii \"open", "unterminated string literal", 7..12),
+            ("! header", "expected a name right after the '!'", 0..1),
+        ] {
+            let mut diagnostics = vec![];
+            lex_line(text, 3, 7, &mut diagnostics);
+            assert_eq!(diagnostics.iter().map(|error| (error.message.as_str(), error.span.clone())).collect::<Vec<_>>(),
+                [(message, Some(Span { file: 3, line: 7, columns }))], "{}", text);
+        }
+    }
+}
@@ -0,0 +1,196 @@
+// lays relocatable objects out into one disc image; the os header first, then every object's relocatable sections
+//    back to back (or from the addr given for that object), with the fixed (.page) sections at their own addrs
+
+use crate::diagnostics::{codes, Diagnostic};
+use crate::object::{ObjectFile, RelocTarget};
+use crate::symbol_map::{MapSymbol, SymbolMap};
+use crate::symbols::SymbolKind;
+use crate::RamAllocs;
+
+pub struct LinkInput {
+    pub path: String,
+    pub object: ObjectFile,
+    pub placement: Option<usize>,  // the disc addr for the object's relocatable sections (kernel.obj@1600)
+}
+
+pub struct Linked {
+    pub byte_pairs: Vec<(usize, u16)>,  // disc addr, byte pair
+    pub allocs: RamAllocs,
+    pub symbols: SymbolMap,  // every export, at its linked addr
+}
+
+fn trait_value(object: &ObjectFile, name: &str) -> Option<u16> {
+    object.traits.iter().find(|(trait_name, _)| trait_name == name).map(|(_, value)| *value)
+}
+
+// the byte pairs hold two bytes each, the first byte high
+fn write_byte(byte_pairs: &mut [u16], offset: usize, byte: u8) {
+    let pair = &mut byte_pairs[offset / 2];
+    *pair = if offset.is_multiple_of(2) { (*pair & 0x00FF) | ((byte as u16) << 8) } else { (*pair & 0xFF00) | byte as u16 };
+}
+
+pub fn link(inputs: &[LinkInput]) -> Result<Linked, Vec<Diagnostic>> {
+    let mut errors = vec![];
+
+    // placing every section; (disc addrs, what's there) for catching overlaps
+    let mut placed: Vec<(std::ops::Range<usize>, String)> = vec![(0..3, String::from("the os header"))];
+    let mut bases = vec![];  // by input, then section
+    let mut cursor = 3;
+    for input in inputs {
+        let mut object_cursor = input.placement.unwrap_or(cursor);
+        let mut section_bases = vec![];
+        for (index, section) in input.object.sections.iter().enumerate() {
+            let base = match section.fixed {
+                Some(addr) => addr,
+                None => {
+                    object_cursor += section.byte_pairs.len();
+                    object_cursor - section.byte_pairs.len()
+                },
+            };
+            section_bases.push(base);
+            if section.byte_pairs.is_empty() { continue; }
+            let range = base..base + section.byte_pairs.len();
+            let name = format!("section {} of '{}'", index, input.path);
+            if let Some((other_range, other)) = placed.iter().find(|(other, _)| range.start < other.end && other.start < range.end) {
+                errors.push(Diagnostic::error(codes::LINK, format!(
+                    "{} (disc {:04x}..{:04x}) overlaps {} (disc {:04x}..{:04x})", name, range.start, range.end, other, other_range.start, other_range.end
+                )));
+            }
+            placed.push((range, name));
+        }
+        if input.placement.is_none() { cursor = object_cursor; }
+        bases.push(section_bases);
+    }
+
+    // the exports at their linked addrs; a name exported twice is only an error when something imports it
+    let exports = inputs.iter().zip(&bases).flat_map(|(input, section_bases)| {
+        input.object.exports.iter().map(move |(name, section, offset)| (name.as_str(), section_bases[*section] + offset, input.path.as_str()))
+    }).collect::<Vec<(&str, usize, &str)>>();
+    for input in inputs {
+        for import in &input.object.imports {
+            let definitions = exports.iter().filter(|(name, ..)| name == import).collect::<Vec<_>>();
+            match definitions[..] {
+                [] => errors.push(Diagnostic::error(codes::LINK, format!("undefined symbol '{}'", import))
+                    .with_note(format!("'{}' imports it through !extern, but no object exports it", input.path))),
+                [_] => {},
+                [(_, _, first), (_, _, second), ..] => errors.push(Diagnostic::error(codes::LINK, format!(
+                    "'{}' (imported by '{}') is exported by both '{}' and '{}'", import, input.path, first, second
+                ))),
+            }
+        }
+    }
+
+    // patching the relocated operands
+    let mut sections = inputs.iter().map(|input| {
+        input.object.sections.iter().map(|section| section.byte_pairs.clone()).collect::<Vec<Vec<u16>>>()
+    }).collect::<Vec<_>>();
+    for (input_index, input) in inputs.iter().enumerate() {
+        for relocation in &input.object.relocations {
+            let target = match &relocation.target {
+                RelocTarget::Section(section) => bases[input_index].get(*section).map(|base| *base as i64),
+                RelocTarget::Symbol(name) => exports.iter().find(|(export, ..)| export == name).map(|(_, addr, _)| *addr as i64),
+            };
+            let Some(target) = target else { continue; };  // already reported
+            let Some(byte_pairs) = sections[input_index].get_mut(relocation.section)
+                .filter(|byte_pairs| relocation.offset + relocation.size <= byte_pairs.len() * 2) else {
+                errors.push(Diagnostic::error(codes::LINK, format!(
+                    "a relocation in '{}' is outside of its section (section {}, byte {})", input.path, relocation.section, relocation.offset
+                )));
+                continue;
+            };
+            let value = target + relocation.addend;
+            if value < 0 || value >= 1i64 << (relocation.size * 8) {
+                errors.push(Diagnostic::error(codes::LINK, format!(
+                    "the addr {} doesn't fit in the {} bit operand at byte {} of section {} in '{}'",
+                    value, relocation.size * 8, relocation.offset, relocation.section, input.path
+                )));
+                continue;
+            }
+            for byte in 0..relocation.size {
+                let shift = if relocation.word { (relocation.size - 1 - byte) * 8 } else { byte * 8 };
+                write_byte(byte_pairs, relocation.offset + byte, (value >> shift) as u8);
+            }
+        }
+    }
+
+    // the ram of every object shares the one address space too
+    let mut allocs: RamAllocs = vec![];
+    for input in inputs {
+        for (addr, byte_pairs) in &input.object.allocs {
+            let range = *addr..*addr + byte_pairs.len();
+            if let Some((other, _)) = allocs.iter().find(|(other, other_pairs)| range.start < other + other_pairs.len() && *other < range.end) {
+                errors.push(Diagnostic::error(codes::LINK, format!("an !alloc of '{}' (ram {:04x}..{:04x}) overlaps another at ram {:04x}", input.path, range.start, range.end, other)));
+            }
+            allocs.push((*addr, byte_pairs.clone()));
+        }
+    }
+    if !errors.is_empty() { return Err(errors); }
+
+    // the header's traits come from the first object (the one booted)
+    let first = inputs.first().map(|input| &input.object);
+    let program_size = sections.iter().flatten().map(Vec::len).sum::<usize>() + 3;
+    let mut byte_pairs = vec![
+        (0, first.and_then(|object| trait_value(object, "ram_size")).unwrap_or(0)),
+        (1, first.and_then(|object| trait_value(object, "program_size")).unwrap_or(program_size as u16)),
+        (2, first.and_then(|object| trait_value(object, "name")).unwrap_or(0)),
+    ];
+    for (section_bases, input_sections) in bases.iter().zip(&sections) {
+        for (base, section) in section_bases.iter().zip(input_sections) {
+            byte_pairs.extend(section.iter().enumerate().map(|(index, byte_pair)| (base + index, *byte_pair)));
+        }
+    }
+    let mut symbols = SymbolMap::default();
+    for (name, addr, path) in exports {
+        symbols.push(MapSymbol { kind: SymbolKind::Label, name: name.to_string(), value: addr as i64, src: path.to_string() });
+    }
+    Ok(Linked { byte_pairs, allocs, symbols })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::parse_text;
+
+    fn input(path: &str, text: &str) -> LinkInput {
+        let (instructions, _, symbols) = parse_text(text, &[]).unwrap();
+        LinkInput { path: path.to_string(), object: crate::compile_object(instructions, &symbols).unwrap(), placement: None }
+    }
+
+    fn messages(inputs: &[LinkInput]) -> Vec<String> {
+        link(inputs).err().unwrap().into_iter().map(|error| error.message).collect()
+    }
+
+    const MAIN: &str = "\
+        !extern double\n\
+        !header main\n\
+        *Ldi %rda $21\n\
+        *Call #double\n\
+        *Kill\n";
+    const DOUBLE: &str = "\
+        !header double\n\
+        Add %rda %rda %rda\n\
+        *Ret\n";
+
+    #[test]
+    fn resolves_an_extern_across_objects() {
+        let linked = link(&[input("main.obj", MAIN), input("double.obj", DOUBLE)]).unwrap();
+        // main's 3 instructions, then double's
+        assert_eq!([3, 12, 13].map(|addr| linked.symbols.symbolize(addr)), ["main", "double", "double+1"]);
+        // the same as if double had been assembled along with main
+        let whole = link(&[input("whole.obj", &(MAIN.replace("!extern double\n", "") + DOUBLE))]).unwrap();
+        assert_eq!(linked.byte_pairs, whole.byte_pairs);
+    }
+
+    #[test]
+    fn rejects_an_undefined_extern() {
+        assert_eq!(messages(&[input("main.obj", MAIN)]), ["undefined symbol 'double'"]);
+    }
+
+    #[test]
+    fn rejects_an_imported_name_exported_twice() {
+        let inputs = [input("main.obj", MAIN), input("double.obj", DOUBLE), input("again.obj", DOUBLE)];
+        assert_eq!(messages(&inputs), ["'double' (imported by 'main.obj') is exported by both 'double.obj' and 'again.obj'"]);
+        // nothing imports it here, so neither is picked
+        assert!(link(&[input("double.obj", DOUBLE), input("again.obj", DOUBLE)]).is_ok());
+    }
+}
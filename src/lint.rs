@@ -0,0 +1,198 @@
+// the lint pass; warnings about code that assembles fine but is most likely a mistake, run over the parsed program
+//    every lint can be allowed, warned about (the default) or denied from the command line, and silenced in the src
+//    with `; lint:allow(<lint>, ...)`; after code it covers that line, on a line of its own the rest of the file
+
+use crate::diagnostics::{codes, Diagnostic, Severity, SourceMap, Span};
+use crate::lexer::{self, Token, TokenKind};
+use crate::peephole::JUMPS;
+use crate::symbols::SymbolTable;
+use crate::{InstructionStream, Label, Parameter, SrcLine, Union, REGISTERS};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+pub static LINTS: &[&str] = &["unreachable-code", "ret-without-call", "protected-instruction", "kernel-register"];
+
+// never fall through to the next instruction
+static TERMINATORS: &[&str] = &["Jmp", "JmpPtr", "JmpRel", "Ret", "RetFramed", "RetConst", "RetInt", "Kill", "CallPgrm"];
+// pop the return addr a *Call pushed
+static RETURNS: &[&str] = &["Ret", "RetFramed", "RetConst"];
+// fault unless running protected (Int being how programs call into the kernel, it's fine unprotected)
+static PROTECTED_INSTRUCTIONS: &[&str] = &[
+    "SetRamFrame", "SetStackFrame", "SaveRegisters", "LodRegisters", "SetTimeout", "SetTimeoutAdd", "SetIntAddr",
+    "CallPgrm", "RetInt", "SetPgrmStart", "SetRamSize", "SetPgrmSize", "SetStackSize", "SetFaultAddr", "Kill",
+    "CpyRegion", "Plot", "CpyRegionPtr", "VBlank", "SwapFrameBuf", "ColorAt", "ColorPtr", "Place", "CpyShown", "Solid",
+    "Write", "Load", "WritePtr", "LoadPtr", "WriteSeg", "LoadSeg", "readIn", "readInFlag", "writeOut", "writeOutFlag",
+];
+// overwritten by the kernel while scheduling, so programs can't count on it keeping its value
+static KERNEL_REGISTER: &str = "rdz";
+// only save & restore registers, which is harmless for the kernel's one
+static REGISTER_SAVES: &[&str] = &["Psh", "Pop"];
+
+// a lint's level; the last --allow/--warn/--deny naming it (or all) wins
+pub fn level(name: &str, settings: &[(String, Level)]) -> Level {
+    settings.iter().rev()
+        .find(|(setting, _)| setting == name || setting == "all")
+        .map_or(Level::Warn, |(_, level)| *level)
+}
+
+// the lints named by the line's `; lint:allow(...)` comment, and whether it's on a line of its own
+fn allowed_on(sources: &SourceMap, file: usize, line: usize) -> Option<(Vec<String>, bool)> {
+    let tokens = lexer::lex_line(sources.line(file, line)?, file, line, &mut vec![]);
+    let comment = tokens.last().filter(|token| token.kind == TokenKind::Comment)?;
+    let start = comment.text.find("lint:allow(")? + "lint:allow(".len();
+    let end = start + comment.text[start..].find(')')?;
+    let names = comment.text[start..end].split(',').map(|name| name.trim().to_string()).collect();
+    Some((names, tokens.len() == 1))
+}
+
+// every `; lint:allow(...)` of the file; the line, the lints named, and whether it's on a line of its own
+fn allows_in(sources: &SourceMap, file: usize) -> Vec<(usize, Vec<String>, bool)> {
+    (0..sources.text(file).lines().count())
+        .filter(|line| sources.line(file, *line).is_some_and(|text| text.contains("lint:allow(")))
+        .filter_map(|line| allowed_on(sources, file, line).map(|(names, own_line)| (line, names, own_line)))
+        .collect()
+}
+
+// the line's code, without its comment
+fn code_tokens(sources: &SourceMap, at: SrcLine) -> Vec<Token> {
+    let text = sources.line(at.file, at.line).unwrap_or("");
+    lexer::lex_line(text, at.file, at.line, &mut vec![]).into_iter().filter(|token| token.kind != TokenKind::Comment).collect()
+}
+
+fn code_span(sources: &SourceMap, at: SrcLine) -> Span {
+    let tokens = code_tokens(sources, at);
+    match (tokens.first(), tokens.last()) {
+        (Some(first), Some(last)) => Span { file: at.file, line: at.line, columns: first.span.columns.start..last.span.columns.end },
+        _ => sources.line_span(at.file, at.line),
+    }
+}
+
+// reports each lint once per line, at its level
+struct Linter<'a> {
+    sources: &'a SourceMap,
+    settings: &'a [(String, Level)],
+    reported: Vec<(&'static str, SrcLine)>,
+    allows: std::collections::HashMap<usize, Vec<(usize, Vec<String>, bool)>>,  // each file's, read in the first time it's linted
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter<'_> {
+    fn is_allowed(&mut self, lint: &str, at: SrcLine) -> bool {
+        let sources = self.sources;
+        self.allows.entry(at.file).or_insert_with(|| allows_in(sources, at.file)).iter()
+            .any(|(line, names, own_line)| (*line == at.line || *own_line && *line < at.line) && names.iter().any(|allowed| allowed == lint))
+    }
+
+    fn report(&mut self, lint: &'static str, at: SrcLine, warning: Diagnostic) {
+        let level = level(lint, self.settings);
+        if level == Level::Allow || self.reported.contains(&(lint, at)) || self.is_allowed(lint, at) { return; }
+        self.reported.push((lint, at));
+        let severity = if level == Level::Deny { Severity::Error } else { Severity::Warning };
+        self.diagnostics.push(Diagnostic { severity, ..warning.with_note(format!("silence it with `; lint:allow({})` or --allow={}", lint, lint)) });
+    }
+}
+
+// the label can be *Call'd from elsewhere; a !header/!function (which other files & objects can call, through
+//    !include or !extern), or a label whose addr is taken (*Call'd, held in a register or .word, etc.) rather than jumped to
+fn is_entry(name: &String, addrs_taken: &std::collections::HashSet<&String>, symbols: &SymbolTable, sources: &SourceMap) -> bool {
+    let defined_by = symbols.get(name).and_then(|symbol| {
+        let text = sources.line(symbol.span.file, symbol.span.line)?;
+        lexer::lex_line(text, symbol.span.file, symbol.span.line, &mut vec![]).into_iter().next().map(|token| token.text)
+    });
+    matches!(defined_by.as_deref(), Some("!header" | "!function")) || addrs_taken.contains(&name) || symbols.is_fixed(name)
+}
+
+// the lints' diagnostics for the parsed program
+pub fn check(instructions: &InstructionStream, symbols: &SymbolTable, sources: &SourceMap, settings: &[(String, Level)]) -> Vec<Diagnostic> {
+    let mut linter = Linter { sources, settings, reported: vec![], allows: Default::default(), diagnostics: vec![] };
+    let addrs_taken = instructions.iter().flat_map(|item| match item {
+        Union::A((instruction, _, _, refs)) if !JUMPS.contains(&instruction.name) => refs.as_slice(),
+        Union::B(Label::Data(_, _, refs)) => refs.as_slice(),
+        _ => &[],
+    }).map(|(_, name, _)| name).collect::<std::collections::HashSet<&String>>();
+    
+    let mut terminated_at = None;  // the last instruction, when it never falls through (and no label's followed)
+    let mut dead = false;  // already reported the unreachable code up to the next label
+    let mut called = false;  // the code since the last entry point can be reached through a *Call
+    for item in instructions {
+        let (instruction, parameters, at) = match item {
+            Union::A((instruction, parameters, at, _)) => (instruction, parameters, *at),
+            Union::B(Label::Header(name)) => {
+                // any other label is only jumped to, from within the code around it
+                called |= is_entry(name, &addrs_taken, symbols, sources);
+                terminated_at = None;
+                dead = false;
+                continue;
+            },
+            Union::B(_) => { continue; },
+        };
+        let tokens = code_tokens(sources, at);
+        let generated = tokens.first().is_some_and(|token| token.kind == TokenKind::Directive);  // the jumps !if/!while lower into
+        if let Some(terminator) = terminated_at && !dead && !generated {
+            linter.report("unreachable-code", at, Diagnostic::warning(codes::UNREACHABLE_CODE, "unreachable code")
+                .with_span(code_span(sources, at))
+                .with_spanned_note("nothing falls through past this, and no label follows it", code_span(sources, terminator)));
+            dead = true;
+        }
+        if TERMINATORS.contains(&instruction.name) { terminated_at = Some(at); }
+        
+        if RETURNS.contains(&instruction.name) && !called && !dead {
+            linter.report("ret-without-call", at, Diagnostic::warning(codes::RET_WITHOUT_CALL, format!("*{} with no *Call leading to it", instruction.name))
+                .with_span(code_span(sources, at))
+                .with_note("the return addr it pops was never pushed; put it under a !header, or a label that's *Call'd"));
+        }
+        if PROTECTED_INSTRUCTIONS.contains(&instruction.name) {
+            linter.report("protected-instruction", at, Diagnostic::warning(codes::PROTECTED_INSTRUCTION, format!("*{} only runs protected", instruction.name))
+                .with_span(code_span(sources, at))
+                .with_note("programs run unprotected, where it faults; kernel code can allow it for the whole file"));
+        }
+        let kernel_register = REGISTERS.iter().position(|name| *name == KERNEL_REGISTER).unwrap_or(usize::MAX);
+        let uses_kernel_register = parameters.iter().any(|parameter| matches!(parameter, Parameter::Register(index) | Parameter::Pointer(index) if *index as usize == kernel_register));
+        if uses_kernel_register && !REGISTER_SAVES.contains(&instruction.name) {
+            let span = tokens.iter().find(|token| token.is(TokenKind::Ident, KERNEL_REGISTER)).map_or_else(|| code_span(sources, at), |token| token.span.clone());
+            linter.report("kernel-register", at, Diagnostic::warning(codes::KERNEL_REGISTER, format!("%{} may be overwritten by the kernel", KERNEL_REGISTER))
+                .with_span(span)
+                .with_note("the kernel uses it while scheduling, so programs can't count on it keeping its value"));
+        }
+    } linter.diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diagnostics::codes;
+    use crate::tests::options;
+
+    // the lints' codes & (0 based) lines for the src
+    fn lints(text: &str) -> Vec<(&'static str, usize)> {
+        let (_, _, diagnostics, _) = crate::parse_source("test.cisc", Some(text.to_string()), &options(&["check", "test.cisc"]));
+        diagnostics.iter().map(|diagnostic| (diagnostic.code, diagnostic.span.as_ref().map_or(0, |span| span.line))).collect()
+    }
+
+    #[test]
+    fn ret_without_call_only_past_no_entry() {
+        assert_eq!(lints("Ldi %rda $1\n*Ret\n"), [(codes::RET_WITHOUT_CALL, 1)]);
+        assert_eq!(lints("*Jmp #skip\n!label skip\n*Ret\n"), [(codes::RET_WITHOUT_CALL, 2)]);
+        // a !header can be called from elsewhere, and its labels are part of it
+        assert_eq!(lints("!header helper\nJeq %rda $0 #done\nAdd %rda $1 %rda\n!label done\n*Ret\n"), []);
+        // a label whose addr is taken
+        assert_eq!(lints("Ldi %rdb $handler\n*Kill  ; lint:allow(protected-instruction)\n!label handler\n*Ret\n"), []);
+    }
+
+    #[test]
+    fn unreachable_code_after_a_terminator() {
+        assert_eq!(lints("!header main\n*Jmp #main\nAdd %rda $1 %rda\nAdd %rda $1 %rda\n"), [(codes::UNREACHABLE_CODE, 2)]);
+        assert_eq!(lints("!header main\n!while\n!break\n!endwhile\n*Ret\n"), []);
+    }
+
+    #[test]
+    fn allowed_by_a_comment() {
+        assert_eq!(lints("*Kill  ; lint:allow(protected-instruction)\n!label next\n*Kill\n"), [(codes::PROTECTED_INSTRUCTION, 2)]);
+        assert_eq!(lints("Ldi %rda $1\n; lint:allow(protected-instruction, kernel-register)\n*Kill\n!label next\nLdi %rdz $1\n"), []);
+        assert_eq!(lints("*Kill\n; lint:allow(protected-instruction)\n"), [(codes::PROTECTED_INSTRUCTION, 0)]);
+    }
+}
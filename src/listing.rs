@@ -0,0 +1,122 @@
+// the assembler listing (.lst); what compile_script placed on the disc, one row per instruction,
+//    alongside the src line it came from and the labels its addresses point at
+
+use crate::diagnostics::SourceMap;
+use crate::symbols::{SymbolKind, SymbolTable};
+use crate::{Parameter, SrcLine, INSTRUCTIONS, RELATIVE_FORMS};
+
+// recorded by compile_script as it places things, so the listing is always what was actually encoded
+#[derive(Debug, Clone)]
+pub enum Row {
+    Header (Vec<u16>),  // the os header's byte pairs (ram size, program size, name), at disc addr 0
+    Page (usize),  // a .page jump to the disc addr
+    Label (String),  // a label sitting at the next instruction
+    Alloc (usize, usize),  // !alloc of initialized ram; ram addr, byte pair count
+    Instruction { addr: usize, words: Vec<u16>, line: SrcLine, parameters: Vec<Parameter> },
+    Data { addr: usize, words: Vec<u16>, line: SrcLine },  // .word/.fill/.ascii/.string; shown 3 byte pairs a row
+}
+
+// the labels whose program addr is the value
+fn label_names(symbols: &SymbolTable, addr: u32) -> Vec<&str> {
+    symbols.iter()
+        .filter(|symbol| symbol.kind == SymbolKind::Label && symbol.value == addr as i64)
+        .map(|symbol| symbol.name.as_str())
+        .collect()
+}
+
+pub fn render(rows: &[Row], sources: &SourceMap, symbols: &SymbolTable) -> String {
+    let location = |line: &SrcLine| format!("{}:{}", sources.path(line.file), line.line + 1);
+    let location_width = rows.iter().filter_map(|row| match row {
+        Row::Instruction { line, .. } | Row::Data { line, .. } => Some(location(line).len()),
+        _ => None,
+    }).max().unwrap_or(0);
+    let words_width = rows.iter().filter_map(|row| match row {
+        Row::Instruction { words, .. } | Row::Header(words) => Some((words.len() * 5).saturating_sub(1)),
+        Row::Data { words, .. } => Some((words.len().min(3) * 5).saturating_sub(1)),
+        _ => None,
+    }).max().unwrap_or(0);
+    let words_text = |words: &[u16]| words.iter().map(|word| format!("{:04x}", word)).collect::<Vec<String>>().join(" ");
+    let blank = " ".repeat(6 + words_width + 2);
+
+    let mut text = format!("; listing of {}; disc addr, byte pairs, src line\n", sources.path(0));
+    for row in rows {
+        match row {
+            Row::Header(words) => {
+                text.push_str(&format!("{:04x}  {:<width$}  ; os header (ram size, program size, name)\n", 0, words_text(words), width = words_width));
+            },
+            Row::Page(addr) => {
+                text.push_str(&format!("\n{}; ---- .page {} (disc {:04x}) ----\n", blank, addr, addr));
+            },
+            Row::Label(name) => {
+                text.push_str(&format!("{}{}:\n", blank, name));
+            },
+            Row::Alloc(addr, count) => {
+                text.push_str(&format!("{}; !alloc ram {:04x}..{:04x} ({} byte pairs)\n", blank, addr, addr + count, count));
+            },
+            Row::Instruction { addr, words, line, parameters } => {
+                let src = sources.line(line.file, line.line).unwrap_or("");
+                let src = src.split(';').next().unwrap_or("").trim();
+                // jumps and calls are easier to follow by name (the pc-relative ones landing at their offset from here)
+                let relative = INSTRUCTIONS.iter().find(|instruction| instruction.op_code == (words[0] >> 8) as u8)
+                    .is_some_and(|instruction| RELATIVE_FORMS.iter().any(|(_, relative)| *relative == instruction.name));
+                let targets = parameters.iter().filter_map(|parameter| match parameter {
+                    Parameter::Address(addr) => Some(label_names(symbols, *addr)),
+                    Parameter::Constant(offset) if relative => Some(label_names(symbols, (*addr as i64 + offset) as u16 as u32)),
+                    _ => None,
+                }).flatten().collect::<Vec<&str>>();
+                let targets = if targets.is_empty() { String::new() } else { format!("  -> {}", targets.join(", ")) };
+                text.push_str(format!(
+                    "{:04x}  {:<words_width$}  {:<location_width$}  {}{}",
+                    addr, words_text(words), location(line), src, targets
+                ).trim_end());
+                text.push('\n');
+            },
+            Row::Data { addr, words, line } => {
+                let src = sources.line(line.file, line.line).unwrap_or("");
+                let src = src.split(';').next().unwrap_or("").trim();
+                for (index, chunk) in words.chunks(3).enumerate() {
+                    let (location, src) = if index == 0 { (location(line), src) } else { (String::new(), "") };
+                    text.push_str(format!(
+                        "{:04x}  {:<words_width$}  {:<location_width$}  {}", addr + index * 3, words_text(chunk), location, src
+                    ).trim_end());
+                    text.push('\n');
+                }
+            },
+        }
+    } text
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::options;
+
+    #[test]
+    fn lists_each_row_with_its_src() {
+        let text = "\
+            !header main\n\
+            !alloc #0 $1 $2\n\
+            *Ldi %rda $5  ; five\n\
+            !label again\n\
+            *Jmp #again\n\
+            .page 0x20\n\
+            !label table\n\
+            .word 1 2 3 4\n";
+        let (sources, _, _, parsed) = crate::parse_source("test.cisc", Some(text.to_string()), &options(&["asm", "test.cisc"]));
+        let (instructions, labels, symbols) = parsed.unwrap();
+        let (_, _, rows) = crate::compile_script(instructions, labels, &symbols).unwrap();
+        assert_eq!(super::render(&rows, &sources, &symbols), "\
+; listing of test.cisc; disc addr, byte pairs, src line
+0000  0000 000d 0000  ; os header (ram size, program size, name)
+                      main:
+                      ; !alloc ram 0000..0002 (2 byte pairs)
+0003  0100 0500 0000  test.cisc:3  *Ldi %rda $5
+                      again:
+0006  7006 0000 0000  test.cisc:5  *Jmp #again  -> again
+
+                      ; ---- .page 32 (disc 0020) ----
+                      table:
+0020  0001 0002 0003  test.cisc:8  .word 1 2 3 4
+0023  0004
+");
+    }
+}
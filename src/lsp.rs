@@ -0,0 +1,448 @@
+// the language server; `cisc lsp` speaks json-rpc over stdio, checking the open .cisc files through the assembler itself
+//    diagnostics as the files change, go to definition & find references for labels, !define's and !const's, hover
+//    for instructions (and names), and completion of instructions & registers
+
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+use crate::diagnostics::{Diagnostic, Severity, SourceMap, Span};
+use crate::json::{self, Json};
+use crate::lexer::{self, Token, TokenKind};
+use crate::symbols::SymbolTable;
+use crate::{cli, Param, SrcFiles, INSTRUCTIONS, REGISTERS, SUDO_INSTRUCTIONS};
+
+// the lsp's enums
+const SYNC_FULL: usize = 1;
+const ERROR: usize = 1;
+const WARNING: usize = 2;
+const COMPLETION_FUNCTION: usize = 3;
+const COMPLETION_VARIABLE: usize = 6;
+const COMPLETION_CONSTANT: usize = 21;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+const MAX_MESSAGE_LEN: usize = 64 << 20;  // far past any .cisc file; a longer Content-Length is taken as a broken stream
+
+// an open file, checked as it was last sent
+struct Analysis {
+    sources: SourceMap,
+    files: SrcFiles,
+    symbols: Option<SymbolTable>,
+}
+
+struct Server {
+    options: cli::Options,
+    documents: HashMap<String, String>,  // uri, text
+    published: HashMap<String, Vec<String>>,  // the uris each document's diagnostics went to, so they can be cleared
+    shut_down: bool,
+}
+
+// the message's body; None once stdin's closed (or the stream can't be read as messages)
+fn read_message(input: &mut impl BufRead) -> Option<String> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).ok()? == 0 { return None; }
+        let header = header.trim_end();
+        if header.is_empty() { break; }
+        if let Some((name, value)) = header.split_once(':') && name.eq_ignore_ascii_case("content-length") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let mut body = vec![0; length.filter(|length| *length <= MAX_MESSAGE_LEN)?];
+    input.read_exact(&mut body).ok()?;
+    String::from_utf8(body).ok()
+}
+
+fn send(message: Json) {
+    let body = message.to_string();
+    let mut output = std::io::stdout().lock();
+    let _ = write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+    let _ = output.flush();
+}
+
+fn notify(method: &str, params: Json) {
+    send(Json::object(vec![("jsonrpc", "2.0".into()), ("method", method.into()), ("params", params)]));
+}
+
+fn uri_to_path(uri: &str) -> String {
+    let path = uri.strip_prefix("file://").unwrap_or(uri).as_bytes();
+    let mut bytes = vec![];
+    let mut index = 0;
+    while index < path.len() {
+        match (path[index], path.get(index + 1..index + 3).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok())) {
+            (b'%', Some(byte)) => {
+                bytes.push(byte);
+                index += 3;
+            },
+            (byte, _) => {
+                bytes.push(byte);
+                index += 1;
+            },
+        }
+    } String::from_utf8_lossy(&bytes).to_string()
+}
+
+fn path_to_uri(path: &std::path::Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    } uri
+}
+
+// lsp positions count the columns in utf-16 units, where the spans count bytes
+fn position(sources: &SourceMap, file: usize, line: usize, column: usize) -> Json {
+    let text = sources.line(file, line).unwrap_or("");
+    let column = text.char_indices().take_while(|(index, _)| *index < column).map(|(_, chr)| chr.len_utf16()).sum::<usize>();
+    Json::object(vec![("line", line.into()), ("character", column.into())])
+}
+
+fn range(sources: &SourceMap, span: &Span) -> Json {
+    Json::object(vec![
+        ("start", position(sources, span.file, span.line, span.columns.start)),
+        ("end", position(sources, span.file, span.line, span.columns.end)),
+    ])
+}
+
+// the uri of a file of the analysis (the command line's -D's being in no file, they're put on the document)
+fn file_uri(analysis: &Analysis, uri: &str, file: usize) -> String {
+    match analysis.files.get(file) {
+        Some((path, _)) if file > 0 => path_to_uri(path),
+        _ => uri.to_string(),
+    }
+}
+
+fn location(analysis: &Analysis, uri: &str, span: &Span) -> Json {
+    Json::object(vec![("uri", file_uri(analysis, uri, span.file).into()), ("range", range(&analysis.sources, span))])
+}
+
+fn lsp_diagnostic(analysis: &Analysis, uri: &str, diagnostic: &Diagnostic) -> Json {
+    let start = Span { file: 0, line: 0, columns: 0..0 };
+    let span = diagnostic.span.as_ref().filter(|span| span.file < analysis.files.len()).unwrap_or(&start);
+    let mut message = diagnostic.message.clone();
+    let mut related = vec![];
+    for note in &diagnostic.notes {
+        match &note.span {
+            Some(span) if span.file < analysis.files.len() => {
+                related.push(Json::object(vec![("location", location(analysis, uri, span)), ("message", note.message.as_str().into())]));
+            },
+            _ => { message.push_str(&format!("\nnote: {}", note.message)); },
+        }
+    }
+    Json::object(vec![
+        ("range", range(&analysis.sources, span)),
+        ("severity", if diagnostic.severity == Severity::Error { ERROR } else { WARNING }.into()),
+        ("code", diagnostic.code.into()),
+        ("source", "cisc".into()),
+        ("message", message.into()),
+        ("relatedInformation", related.into()),
+    ])
+}
+
+fn param_signature(param: &Param) -> &'static str {
+    match param {
+        Param::Reg => "%reg",
+        Param::Ptr => "[%reg]",
+        Param::Const8 => "$const8",
+        Param::Const16 => "$const16",
+        Param::Addr16 => "#addr16",
+        Param::Addr32 => "#addr32",
+    }
+}
+
+fn signature(name: &str, params: &[Param]) -> String {
+    params.iter().fold(name.to_string(), |text, param| format!("{} {}", text, param_signature(param)))
+}
+
+// the native forms of an instruction; their operands, op code & cycle cost
+fn native_hover(name: &str) -> Vec<String> {
+    INSTRUCTIONS.iter().filter(|instruction| instruction.name.eq_ignore_ascii_case(name)).map(|instruction| {
+        format!("    *{}\n\nop code 0x{:02x}, {} cycle(s)", signature(instruction.name, instruction.params), instruction.op_code, instruction.cycle_cost)
+    }).collect()
+}
+
+// a sudo instruction's forms and what each expands to, followed by the native instructions it expands into
+fn sudo_hover(name: &str) -> Vec<String> {
+    let Some(sudo) = SUDO_INSTRUCTIONS.iter().find(|sudo| sudo.name.eq_ignore_ascii_case(name)) else { return vec![]; };
+    let forms = sudo.conversions.iter()
+        .map(|(params, template)| format!("    {}  ->  {}", signature(sudo.name, params), template))
+        .collect::<Vec<String>>();
+    let mut natives = vec![];
+    for (_, template) in sudo.conversions {
+        for native in template.split(';').filter_map(|line| line.split_whitespace().next()) {
+            if !natives.contains(&native) { natives.push(native); }
+        }
+    }
+    std::iter::once(forms.join("\n")).chain(natives.into_iter().flat_map(native_hover)).collect()
+}
+
+// the token under the cursor (or just before it), and the tokens of its line
+fn token_at(analysis: &Analysis, line: usize, character: usize) -> Option<(Token, Vec<Token>)> {
+    let text = analysis.sources.line(0, line)?;
+    let mut column = 0;
+    let mut units = 0;
+    for chr in text.chars() {
+        if units >= character { break; }
+        units += chr.len_utf16();
+        column += chr.len_utf8();
+    }
+    let tokens = lexer::lex_line(text, 0, line, &mut vec![]);
+    let token = tokens.iter().find(|token| token.span.columns.start <= column && column <= token.span.columns.end && token.kind != TokenKind::Comment)?;
+    Some((token.clone(), tokens))
+}
+
+// the tokens naming the symbol in every file of the program (including its definition)
+fn references(analysis: &Analysis, symbols: &SymbolTable, name: &str) -> Vec<Span> {
+    let mut spans = vec![];
+    for (file, (_, namespace)) in analysis.files.iter().enumerate() {
+        for (line, text) in analysis.sources.text(file).lines().enumerate() {
+            spans.extend(lexer::lex_line(text, file, line, &mut vec![]).into_iter()
+                .filter(|token| token.kind == TokenKind::Ident && symbols.lookup(&token.text, namespace).is_some_and(|symbol| symbol.name == name))
+                .map(|token| token.span));
+        }
+    } spans
+}
+
+impl Server {
+    fn analyze(&self, uri: &str) -> Option<(Analysis, Vec<Diagnostic>)> {
+        let text = self.documents.get(uri)?;
+        let (sources, files, mut diagnostics, parsed) = crate::parse_source(&uri_to_path(uri), Some(text.clone()), &self.options);
+        let symbols = parsed.map(|(instructions, labels, symbols)| {
+            // placing it too, for the overlaps & such that check would report
+            if !diagnostics.iter().any(Diagnostic::is_error) && let Err(error) = crate::compile_script(instructions, labels, &symbols) {
+                diagnostics.push(error);
+            } symbols
+        });
+        Some((Analysis { sources, files, symbols }, diagnostics))
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str) {
+        let Some((analysis, diagnostics)) = self.analyze(uri) else { return; };
+        let mut by_uri: Vec<(String, Vec<Json>)> = self.published.remove(uri).unwrap_or_default().into_iter().map(|uri| (uri, vec![])).collect();
+        for diagnostic in &diagnostics {
+            let file = diagnostic.span.as_ref().map_or(0, |span| span.file);
+            let target = file_uri(&analysis, uri, file);
+            let json = lsp_diagnostic(&analysis, uri, diagnostic);
+            match by_uri.iter_mut().find(|(other, _)| *other == target) {
+                Some((_, list)) => list.push(json),
+                None => by_uri.push((target, vec![json])),
+            }
+        }
+        if !by_uri.iter().any(|(other, _)| other == uri) { by_uri.push((uri.to_string(), vec![])); }
+        self.published.insert(uri.to_string(), by_uri.iter().filter(|(_, list)| !list.is_empty()).map(|(uri, _)| uri.clone()).collect());
+        for (target, list) in by_uri {
+            notify("textDocument/publishDiagnostics", Json::object(vec![("uri", target.into()), ("diagnostics", list.into())]));
+        }
+    }
+
+    fn definition(&self, uri: &str, line: usize, character: usize) -> Json {
+        let Some((analysis, _)) = self.analyze(uri) else { return Json::Null; };
+        let Some((token, _)) = token_at(&analysis, line, character) else { return Json::Null; };
+        match analysis.symbols.as_ref().and_then(|symbols| symbols.lookup(&token.text, "")) {
+            Some(symbol) if token.kind == TokenKind::Ident => location(&analysis, uri, &symbol.span),
+            _ => Json::Null,
+        }
+    }
+
+    fn references(&self, uri: &str, line: usize, character: usize, include_declaration: bool) -> Json {
+        let Some((analysis, _)) = self.analyze(uri) else { return Json::Null; };
+        let Some((token, _)) = token_at(&analysis, line, character) else { return Json::Null; };
+        let Some(symbols) = &analysis.symbols else { return Json::Null; };
+        let Some(symbol) = symbols.lookup(&token.text, "").filter(|_| token.kind == TokenKind::Ident) else { return Json::Null; };
+        references(&analysis, symbols, &symbol.name).iter()
+            .filter(|span| include_declaration || **span != symbol.span)
+            .map(|span| location(&analysis, uri, span))
+            .collect::<Vec<Json>>().into()
+    }
+
+    fn hover(&self, uri: &str, line: usize, character: usize) -> Json {
+        let Some((analysis, _)) = self.analyze(uri) else { return Json::Null; };
+        let Some((token, tokens)) = token_at(&analysis, line, character) else { return Json::Null; };
+        if token.kind != TokenKind::Ident { return Json::Null; }
+        // the instruction is the line's first name, marked native by a leading *
+        let native = tokens[0].is(TokenKind::Operator, "*");
+        let sections = if tokens.get(native as usize) == Some(&token) {
+            if native { native_hover(&token.text) } else { sudo_hover(&token.text) }
+        } else {
+            analysis.symbols.as_ref().and_then(|symbols| symbols.lookup(&token.text, "")).map(|symbol| {
+                vec![format!("    {} {} = 0x{:04x}", symbol.kind.name(), symbol.name, symbol.value)]
+            }).unwrap_or_default()
+        };
+        if sections.is_empty() { return Json::Null; }
+        Json::object(vec![
+            ("contents", Json::object(vec![("kind", "markdown".into()), ("value", sections.join("\n\n---\n\n").into())])),
+            ("range", range(&analysis.sources, &token.span)),
+        ])
+    }
+
+    // registers after a %, instructions at the start of a line (natives after a *), and otherwise the program's names
+    fn completion(&self, uri: &str, line: usize, character: usize) -> Json {
+        let Some(text) = self.documents.get(uri).and_then(|text| text.lines().nth(line)) else { return Json::Array(vec![]); };
+        let before = text.chars().scan(0, |units, chr| {
+            *units += chr.len_utf16();
+            Some((*units, chr))
+        }).take_while(|(units, _)| *units <= character).map(|(_, chr)| chr).collect::<String>();
+        let mut tokens = lexer::lex_line(&before, 0, line, &mut vec![]);
+        if tokens.last().is_some_and(|token| token.kind == TokenKind::Ident && token.span.columns.end == before.len()) { tokens.pop(); }  // the name being typed
+        let item = |label: &str, kind: usize, detail: String| Json::object(vec![("label", label.into()), ("kind", kind.into()), ("detail", detail.into())]);
+        let items = match tokens.as_slice() {
+            [.., sigil] if sigil.is(TokenKind::Sigil, "%") => {
+                REGISTERS.iter().map(|register| item(register, COMPLETION_VARIABLE, String::from("register"))).collect()
+            },
+            [] => SUDO_INSTRUCTIONS.iter().map(|sudo| {
+                item(sudo.name, COMPLETION_FUNCTION, sudo.conversions.iter().map(|(params, _)| signature(sudo.name, params)).collect::<Vec<String>>().join(" | "))
+            }).collect(),
+            [star] if star.is(TokenKind::Operator, "*") => {
+                let mut names = vec![];
+                INSTRUCTIONS.iter().filter(|instruction| {
+                    let new = !names.contains(&instruction.name);
+                    names.push(instruction.name);
+                    new
+                }).map(|instruction| item(instruction.name, COMPLETION_FUNCTION, signature(instruction.name, instruction.params))).collect()
+            },
+            _ => match self.analyze(uri) {
+                Some((Analysis { symbols: Some(symbols), .. }, _)) => symbols.iter().filter(|symbol| !symbols.is_hidden(&symbol.name)).map(|symbol| {
+                    item(&symbol.name, COMPLETION_CONSTANT, format!("{} = 0x{:04x}", symbol.kind.name(), symbol.value))
+                }).collect(),
+                _ => vec![],
+            },
+        };
+        Json::Array(items)
+    }
+
+    // the result of a request (or an error's code & message); None for notifications
+    fn handle(&mut self, method: &str, params: &Json) -> Option<Result<Json, (i64, String)>> {
+        let uri = params.get("textDocument").get("uri").as_str().unwrap_or("").to_string();
+        let line = params.get("position").get("line").as_usize().unwrap_or(0);
+        let character = params.get("position").get("character").as_usize().unwrap_or(0);
+        Some(Ok(match method {
+            "initialize" => Json::object(vec![
+                ("capabilities", Json::object(vec![
+                    ("textDocumentSync", SYNC_FULL.into()),
+                    ("definitionProvider", true.into()),
+                    ("referencesProvider", true.into()),
+                    ("hoverProvider", true.into()),
+                    ("completionProvider", Json::object(vec![("triggerCharacters", vec!["%".into(), "*".into()].into())])),
+                ])),
+                ("serverInfo", Json::object(vec![("name", "cisc".into())])),
+            ]),
+            "shutdown" => {
+                self.shut_down = true;
+                Json::Null
+            },
+            "textDocument/definition" => self.definition(&uri, line, character),
+            "textDocument/references" => {
+                let include_declaration = params.get("context").get("includeDeclaration").as_bool().unwrap_or(true);
+                self.references(&uri, line, character, include_declaration)
+            },
+            "textDocument/hover" => self.hover(&uri, line, character),
+            "textDocument/completion" => self.completion(&uri, line, character),
+            "textDocument/didOpen" | "textDocument/didChange" => {
+                // the whole text is sent each time (the last change holding it)
+                let text = match params.get("contentChanges") {
+                    Json::Array(changes) => changes.last().and_then(|change| change.get("text").as_str()),
+                    _ => params.get("textDocument").get("text").as_str(),
+                };
+                if let Some(text) = text { self.documents.insert(uri.clone(), text.to_string()); }
+                self.publish_diagnostics(&uri);
+                return None;
+            },
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                for target in self.published.remove(&uri).unwrap_or_default() {
+                    notify("textDocument/publishDiagnostics", Json::object(vec![("uri", target.into()), ("diagnostics", Json::Array(vec![]))]));
+                } return None;
+            },
+            _ => { return Some(Err((METHOD_NOT_FOUND, format!("'{}' isn't supported", method)))); },
+        }))
+    }
+}
+
+// serves until the client exits; whether it shut down first
+pub fn serve(options: &cli::Options) -> bool {
+    // nothing else can write to stdout, it being the protocol
+    let options = cli::Options { verbosity: cli::Verbosity::Normal, ..options.clone() };
+    let mut server = Server { options, documents: HashMap::new(), published: HashMap::new(), shut_down: false };
+    let mut input = std::io::stdin().lock();
+    while let Some(body) = read_message(&mut input) {
+        let Ok(message) = json::parse(&body) else { continue; };
+        let method = message.get("method").as_str().unwrap_or("");
+        if method == "exit" { break; }
+        let reply = server.handle(method, message.get("params"));
+        let id = message.get("id");
+        if *id == Json::Null { continue; }  // a notification (or a reply to the server); nothing's sent back
+        let mut fields = vec![("jsonrpc", "2.0".into()), ("id", id.clone())];
+        match reply {
+            Some(Ok(result)) => fields.push(("result", result)),
+            Some(Err((code, message))) => fields.push(("error", Json::object(vec![("code", Json::Number(code as f64)), ("message", message.into())]))),
+            None => fields.push(("result", Json::Null)),
+        }
+        send(Json::object(fields));
+    } server.shut_down
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::options;
+
+    // a server with the text open as file:///test.cisc
+    fn serving(text: &str) -> Server {
+        let documents = HashMap::from([(String::from("file:///test.cisc"), text.to_string())]);
+        Server { options: options(&["lsp"]), documents, published: HashMap::new(), shut_down: false }
+    }
+
+    #[test]
+    fn reads_framed_messages() {
+        let mut input = std::io::Cursor::new("Content-Length: 7\r\n\r\n{\"a\":1}content-length:2\r\nContent-Type: x\r\n\r\n[]");
+        assert_eq!(read_message(&mut input).as_deref(), Some("{\"a\":1}"));
+        assert_eq!(read_message(&mut input).as_deref(), Some("[]"));
+        assert_eq!(read_message(&mut input), None);
+        // no length, a length that isn't one, a body cut short, and a length no client would send
+        for text in ["Content-Type: x\r\n\r\n{}", "Content-Length: x\r\n\r\n{}", "Content-Length: 9\r\n\r\n{}", "Content-Length: 99999999999999\r\n\r\n{}"] {
+            assert_eq!(read_message(&mut std::io::Cursor::new(text)), None, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn uris_and_paths_round_trip() {
+        let path = std::path::Path::new("/tmp/a b/ü%.cisc");
+        let uri = path_to_uri(path);
+        assert_eq!(uri, "file:///tmp/a%20b/%C3%BC%25.cisc");
+        assert_eq!(uri_to_path(&uri), "/tmp/a b/ü%.cisc");
+        // lowercase hex is fine, and a % that doesn't start an escape is kept
+        assert_eq!(uri_to_path("file:///x%c3%bc/100%/%zz"), "/xü/100%/%zz");
+    }
+
+    #[test]
+    fn columns_are_utf16_units() {
+        // the emoji being 4 bytes, but 2 units
+        let text = "!alloc #0 \"é😀\" $value";
+        let mut sources = SourceMap::default();
+        let file = sources.add("test.cisc", text.to_string());
+        let byte = text.find("$value").unwrap();
+        assert_eq!(position(&sources, file, 0, byte).to_string(), r#"{"line":0,"character":16}"#);
+        let analysis = Analysis { sources, files: vec![], symbols: None };
+        let (token, _) = token_at(&analysis, 0, 18).unwrap();
+        assert_eq!(token.text, "value");
+        assert_eq!(token_at(&analysis, 0, 12).unwrap().0.kind, TokenKind::String);
+    }
+
+    #[test]
+    fn completes_by_where_the_cursor_is() {
+        let labels = |server: &Server, line, character| match server.completion("file:///test.cisc", line, character) {
+            Json::Array(items) => items.iter().map(|item| item.get("label").as_str().unwrap().to_string()).collect::<Vec<_>>(),
+            other => panic!("{:?}", other),
+        };
+        let server = serving("Ad\n*Ld\n!alloc #0 \"😀\" %r");
+        assert!(labels(&server, 0, 2).contains(&String::from("Add")));
+        assert!(labels(&server, 1, 3).contains(&String::from("Ldi")));
+        // after the % (unit 16, byte 18), and the r typed after it
+        assert_eq!(labels(&server, 2, 16), REGISTERS);
+        assert_eq!(labels(&server, 2, 17), REGISTERS);
+        let server = serving("!const LIMIT 3\nAdd %rda $LIMIT %rda\n");
+        assert!(labels(&server, 1, 11).contains(&String::from("LIMIT")));
+    }
+}
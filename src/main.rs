@@ -1,11 +1,37 @@
+mod cli;
+mod compiler;
+mod diagnostics;
+mod disassembler;
+mod encoding;
+mod formatter;
+mod image;
+mod json;
+mod lexer;
+mod linker;
+mod lint;
+mod listing;
+mod lsp;
+mod object;
+mod peephole;
+mod pic;
+mod symbol_map;
+mod symbols;
+
+use diagnostics::{codes, Diagnostic, SourceMap, Span};
+use lexer::{Token, TokenKind};
+use symbol_map::SymbolMap;
+use symbols::{qualify, Symbol, SymbolKind, SymbolTable};
 use std::io::Write;
 use std::io::Read;
 
 #[derive(Clone, Debug)]
 struct SudoInstruction {
     pub name: &'static str,
-    // name, parameters, final instruction (* is a placeholder for where the parameters/operands are inserted)
-    pub conversions: &'static [(&'static str, &'static [Param], &'static str)],
+    // the parameters of each form, and the native instructions it expands to; ';' separated, with '*' taking the next
+    //    of the given operands, '*<n>' the n'th (from 1), and %registers/$numbers as they are
+    //    every form of an instruction expands to as many native instructions, so the first pass can count them
+    //    before the operands (which may name later labels) resolve
+    pub conversions: &'static [(&'static [Param], &'static str)],
 }
 
 #[derive(Clone, Debug)]
@@ -26,19 +52,33 @@ enum Param {
     Ptr,
 }
 
+// the general registers, pushed in order (and popped in reverse)
+const PSH_ALL: &str = "Psh %rda; Psh %rdb; Psh %rdc; Psh %rdd; Psh %rde; Psh %rdf; Psh %rdg; Psh %rdh; Psh %rdi; Psh %rdj; Psh %rdk; Psh %rdl; Psh %rdm; Psh %rdn; Psh %rdo; Psh %rdp; Psh %rdq; Psh %rdr; Psh %rds; Psh %rdt; Psh %rdu; Psh %rdv; Psh %rdw; Psh %rdx; Psh %rdy; Psh %rdz";
+const POP_ALL: &str = "Pop %rdz; Pop %rdy; Pop %rdx; Pop %rdw; Pop %rdv; Pop %rdu; Pop %rdt; Pop %rds; Pop %rdr; Pop %rdq; Pop %rdp; Pop %rdo; Pop %rdn; Pop %rdm; Pop %rdl; Pop %rdk; Pop %rdj; Pop %rdi; Pop %rdh; Pop %rdg; Pop %rdf; Pop %rde; Pop %rdd; Pop %rdc; Pop %rdb; Pop %rda";
+
 static SUDO_INSTRUCTIONS: &[SudoInstruction] = &[
-    SudoInstruction { name: "Add"    , conversions: &[("Add", &[Param::Reg, Param::Reg, Param::Reg], "Add * * *"), ("AddImm", &[Param::Reg, Param::Const16, Param::Reg], "AddImm * * *")] },
-    SudoInstruction { name: "Sub"    , conversions: &[("Sub", &[Param::Reg, Param::Reg, Param::Reg], "Sub * * *"), ("SubImm", &[Param::Reg, Param::Const16, Param::Reg], "SubImm * * *")] },
-    SudoInstruction { name: "Mul"    , conversions: &[("Mul", &[Param::Reg, Param::Reg, Param::Reg], "Mul * * *"), ("MulImm", &[Param::Reg, Param::Const16, Param::Reg], "MulImm * * *")] },
-    SudoInstruction { name: "Div"    , conversions: &[("Div", &[Param::Reg, Param::Reg, Param::Reg], "Div * * *"), ("DivImm", &[Param::Reg, Param::Const16, Param::Reg], "DivImm * * *")] },
-    SudoInstruction { name: "Mod"    , conversions: &[("Mod", &[Param::Reg, Param::Reg, Param::Reg], "Mod * * *"), ("ModImm", &[Param::Reg, Param::Const16, Param::Reg], "ModImm * * *")] },
-    SudoInstruction { name: "Mov"    , conversions: &[("Mov", &[Param::Reg, Param::Reg], "Mov * *"), ("MovR", &[Param::Addr16, Param::Addr16], "MovR * *")] },
-    SudoInstruction { name: "Ldi"    , conversions: &[("Ldi", &[Param::Reg, Param::Const16], "Ldi * *"), ("LdiR", &[Param::Addr16, Param::Const16], "LdiR * *"), ("LdiPtr", &[Param::Ptr, Param::Const16], "LdiPtr * * *")] },
-    SudoInstruction { name: "MemCpy" , conversions: &[("MemCpy", &[Param::Addr16, Param::Addr16, Param::Const8], "MemCpy * * *"), ("MemCpyPtr", &[Param::Ptr, Param::Ptr, Param::Reg], "MemCpyPtr * * *")] },
-    SudoInstruction { name: "MemCmp" , conversions: &[("MemCmp", &[Param::Addr16, Param::Addr16, Param::Const8], "MemCmp * * *"), ("MemCmpPtr", &[Param::Ptr, Param::Ptr, Param::Reg], "MemCmpPtr * * *")] },
-    SudoInstruction { name: "Sto"    , conversions: &[("Sto", &[Param::Addr16, Param::Reg], "Sto"), ("StoPtr", &[Param::Ptr, Param::Reg], "StoPtr * * *"), ("StoPtrOff", &[Param::Ptr, Param::Const16, Param::Reg], "StoPtrOff * * *")] },
-    SudoInstruction { name: "Get"    , conversions: &[("Get", &[Param::Addr16, Param::Reg], "Get"), ("GetPtr", &[Param::Ptr, Param::Reg], "GetPtr * * *"), ("GetPtrOff", &[Param::Ptr, Param::Const16, Param::Reg], "GetPtrOff * * *")] },
-    SudoInstruction { name: "Psh"    , conversions: &[("Psh", &[Param::Reg], "Psh"), ("PshCon", &[Param::Const16], "PshCon"), ("PshPtr", &[Param::Ptr], "PshPtr")] },
+    SudoInstruction { name: "Add"    , conversions: &[(&[Param::Reg, Param::Reg, Param::Reg], "Add * * *"), (&[Param::Reg, Param::Const16, Param::Reg], "AddImm * * *")] },
+    SudoInstruction { name: "Sub"    , conversions: &[(&[Param::Reg, Param::Reg, Param::Reg], "Sub * * *"), (&[Param::Reg, Param::Const16, Param::Reg], "SubImm * * *")] },
+    SudoInstruction { name: "Mul"    , conversions: &[(&[Param::Reg, Param::Reg, Param::Reg], "Mul * * *"), (&[Param::Reg, Param::Const16, Param::Reg], "MulImm * * *")] },
+    SudoInstruction { name: "Div"    , conversions: &[(&[Param::Reg, Param::Reg, Param::Reg], "Div * * *"), (&[Param::Reg, Param::Const16, Param::Reg], "DivImm * * *")] },
+    SudoInstruction { name: "Mod"    , conversions: &[(&[Param::Reg, Param::Reg, Param::Reg], "Mod * * *"), (&[Param::Reg, Param::Const16, Param::Reg], "ModImm * * *")] },
+    SudoInstruction { name: "Mov"    , conversions: &[(&[Param::Reg, Param::Reg], "Mov * *"), (&[Param::Addr16, Param::Addr16], "MovR * *")] },
+    SudoInstruction { name: "Ldi"    , conversions: &[(&[Param::Reg, Param::Const16], "Ldi * *"), (&[Param::Addr16, Param::Const16], "LdiR * *"), (&[Param::Ptr, Param::Const16], "LdiPtr * *")] },
+    SudoInstruction { name: "MemCpy" , conversions: &[(&[Param::Addr16, Param::Addr16, Param::Const8], "MemCpy * * *"), (&[Param::Ptr, Param::Ptr, Param::Reg], "MemCpyPtr * * *")] },
+    SudoInstruction { name: "MemCmp" , conversions: &[(&[Param::Addr16, Param::Addr16, Param::Const8], "MemCmp * * *"), (&[Param::Ptr, Param::Ptr, Param::Reg], "MemCmpPtr * * *")] },
+    SudoInstruction { name: "Sto"    , conversions: &[(&[Param::Addr16, Param::Reg], "Sto * *"), (&[Param::Ptr, Param::Reg], "StoPtr * *"), (&[Param::Ptr, Param::Const16, Param::Reg], "StoPtrOff * * *")] },
+    SudoInstruction { name: "Get"    , conversions: &[(&[Param::Addr16, Param::Reg], "Get * *"), (&[Param::Ptr, Param::Reg], "GetPtr * *"), (&[Param::Ptr, Param::Const16, Param::Reg], "GetPtrOff * * *")] },
+    SudoInstruction { name: "Psh"    , conversions: &[(&[Param::Reg], "Psh *"), (&[Param::Const16], "PshCon *"), (&[Param::Ptr], "PshPtr *")] },
+    SudoInstruction { name: "Clr"    , conversions: &[(&[Param::Reg], "Ldi * $0")] },
+    SudoInstruction { name: "Jmp"    , conversions: &[(&[Param::Addr16], "Jmp *"), (&[Param::Reg], "JmpPtr *")] },
+    SudoInstruction { name: "Jlt"    , conversions: &[(&[Param::Reg, Param::Reg, Param::Addr16], "Less * *; Jic *"), (&[Param::Reg, Param::Const16, Param::Addr16], "LessImm * *; Jic *")] },
+    SudoInstruction { name: "Jgt"    , conversions: &[(&[Param::Reg, Param::Reg, Param::Addr16], "Grtr * *; Jic *"), (&[Param::Reg, Param::Const16, Param::Addr16], "GrtrImm * *; Jic *")] },
+    SudoInstruction { name: "Jle"    , conversions: &[(&[Param::Reg, Param::Reg, Param::Addr16], "Grtr * *; Jnc *"), (&[Param::Reg, Param::Const16, Param::Addr16], "GrtrImm * *; Jnc *")] },
+    SudoInstruction { name: "Jge"    , conversions: &[(&[Param::Reg, Param::Reg, Param::Addr16], "Less * *; Jnc *"), (&[Param::Reg, Param::Const16, Param::Addr16], "LessImm * *; Jnc *")] },
+    SudoInstruction { name: "Jeq"    , conversions: &[(&[Param::Reg, Param::Reg, Param::Addr16], "Eq * *; Jic *"), (&[Param::Reg, Param::Const16, Param::Addr16], "EqImm * *; Jic *")] },
+    SudoInstruction { name: "Jne"    , conversions: &[(&[Param::Reg, Param::Reg, Param::Addr16], "Eq * *; Jnc *"), (&[Param::Reg, Param::Const16, Param::Addr16], "EqImm * *; Jnc *")] },
+    SudoInstruction { name: "PshAll" , conversions: &[(&[], PSH_ALL)] },
+    SudoInstruction { name: "PopAll" , conversions: &[(&[], POP_ALL)] },
 ];
 
 static INSTRUCTIONS: &[Instruction] = &[
@@ -52,9 +92,9 @@ static INSTRUCTIONS: &[Instruction] = &[
     Instruction { name: "LdiPtr"       , params: &[Param::Ptr, Param::Const16], op_code: 0b0001_0011, cycle_cost: 2 },
     Instruction { name: "StoPtr"       , params: &[Param::Ptr, Param::Reg], op_code: 0b0001_0100, cycle_cost: 2 },
     Instruction { name: "GetPtr"       , params: &[Param::Ptr, Param::Reg], op_code: 0b0001_0101, cycle_cost: 2 },
-    Instruction { name: "MemCpy"       , params: &[Param::Addr16, Param::Addr32, Param::Const8], op_code: 0b0001_0110, cycle_cost: 5 },
+    Instruction { name: "MemCpy"       , params: &[Param::Addr16, Param::Addr16, Param::Const8], op_code: 0b0001_0110, cycle_cost: 5 },
     Instruction { name: "MemCpyPtr"    , params: &[Param::Ptr, Param::Ptr, Param::Reg], op_code: 0b0001_0111, cycle_cost: 6 },
-    Instruction { name: "MemCmp"       , params: &[Param::Addr16, Param::Addr32, Param::Const8], op_code: 0b0001_1000, cycle_cost: 3 },
+    Instruction { name: "MemCmp"       , params: &[Param::Addr16, Param::Addr16, Param::Const8], op_code: 0b0001_1000, cycle_cost: 3 },
     Instruction { name: "MemCmpPtr"    , params: &[Param::Ptr, Param::Ptr, Param::Reg], op_code: 0b0001_1001, cycle_cost: 4 },
     Instruction { name: "StoPtrOff"    , params: &[Param::Ptr, Param::Const16, Param::Reg], op_code: 0b0001_1010, cycle_cost: 3 },
     Instruction { name: "GetPtrOff"    , params: &[Param::Ptr, Param::Const16, Param::Reg], op_code: 0b0001_1011, cycle_cost: 3 },
@@ -168,136 +208,60 @@ static INSTRUCTIONS: &[Instruction] = &[
     Instruction { name: "readInFlag"   , params: &[Param::Const8], op_code: 0b1010_0001, cycle_cost: 1 },
     Instruction { name: "writeOut"     , params: &[Param::Reg, Param::Const8], op_code: 0b1010_0010, cycle_cost: 1 },
     Instruction { name: "writeOutFlag" , params: &[Param::Reg, Param::Const8], op_code: 0b1010_0011, cycle_cost: 1 },
+    // pc-relative; the operand is the signed offset from the instruction's own addr, so the code runs wherever it's loaded
+    Instruction { name: "JmpRel"       , params: &[Param::Const16], op_code: 0b1011_0000, cycle_cost: 2 },
+    Instruction { name: "JicRel"       , params: &[Param::Const16], op_code: 0b1011_0001, cycle_cost: 2 },
+    Instruction { name: "JncRel"       , params: &[Param::Const16], op_code: 0b1011_0010, cycle_cost: 2 },
+    Instruction { name: "JizRel"       , params: &[Param::Const16], op_code: 0b1011_0011, cycle_cost: 2 },
+    Instruction { name: "JnzRel"       , params: &[Param::Const16], op_code: 0b1011_0100, cycle_cost: 2 },
+    Instruction { name: "JiErrRel"     , params: &[Param::Const16], op_code: 0b1011_0101, cycle_cost: 2 },
+    Instruction { name: "JnErrRel"     , params: &[Param::Const16], op_code: 0b1011_0110, cycle_cost: 2 },
+    Instruction { name: "JiCryRel"     , params: &[Param::Const16], op_code: 0b1011_0111, cycle_cost: 2 },
+    Instruction { name: "JnCryRel"     , params: &[Param::Const16], op_code: 0b1011_1000, cycle_cost: 2 },
+    Instruction { name: "CallRel"      , params: &[Param::Const16], op_code: 0b1011_1001, cycle_cost: 3 },
 ];
 
-fn split_line(line: &mut Vec<&str>) {
-    let mut i = 0;
-    let mut char_index = 0;
-    loop {
-        if char_index >= line[i].len() {
-            char_index = 0;
-            i += 1;
-            if i >= line.len() { break; }
-        }
-        
-        if &line[i][char_index..next_valid_index(char_index + 1, line[i])] == "¡" {
-            let next = next_valid_index(char_index + 1, line[i]);
-            let text_seg = line.remove(i);
-            line.insert(i, &text_seg[next..]);  // the end bit
-            line.insert(i, &text_seg[char_index..next]);  // the end bit
-            line.insert(i, &text_seg[..char_index]);  // the start
-            i += 2;  // the previous token is completed
-            char_index = 0;  // restart the check for this new token
-        }
-        else if BREAKS.contains(&&line[i][0..char_index]) && !part_of_large_token(line[i], last_valid_index(char_index.saturating_sub(1), line[i])) {
-            let text_seg = line.remove(i);
-            line.insert(i, &text_seg[char_index..]);
-            line.insert(i, &text_seg[..char_index]);
-            i += 1;  // the previous token is completed
-            char_index = 0;  // restart the check for this new token
-            continue;
-        }
-        else if BREAKS.contains(&&line[i][char_index..next_valid_index(char_index+1, line[i])]) {
-            // making sure it's not part of a larger token, if so breaking it up
-            if part_of_large_token(&line[i], last_valid_index(char_index.saturating_sub(1), line[i])) {
-                if char_index == 0 || BREAKS.contains(&&line[i][0..char_index]) {
-                    char_index += next_valid_index(char_index + 1, line[i]) - char_index;
-                    continue;
-                }
-                let text_seg = line.remove(i);
-                // adding the previous bit allowing the token to be looked at in its entirety
-                line.insert(i, &text_seg[char_index..]);
-                line.insert(i, &text_seg[..char_index]);
-                i += 1;  // the previous token is completed
-                char_index = 0;  // restart the check for this new token
-                continue;
-            }
-            
-            if part_of_large_token(&line[i][char_index..], 0) {
-                let text_seg = line.remove(i);
-                // adding the previous bit allowing the token to be looked at in its entirety
-                line.insert(i, &text_seg[char_index..]);
-                line.insert(i, &text_seg[..char_index]);
-                i += 1;  // the previous token is completed
-                char_index = 0;  // restart the check for this new token
-                continue;
-            }
-            
-            if char_index == 0 {
-                let text_seg = line.remove(i);
-                line.insert(i, &text_seg[1..]);
-                line.insert(i, &text_seg[0..1]);  // the token
-                i += 1;
-                char_index = 0;
-                continue;
-            }
-            
-            let text_seg = line.remove(i);
-            line.insert(i, &text_seg[char_index+1..]);  // the end bit
-            line.insert(i, &text_seg[char_index..char_index+1]);  // the end bit
-            line.insert(i, &text_seg[..char_index]);  // the start
-            i += 2;  // the previous token is completed
-            char_index = 0;  // restart the check for this new token
-        }
-        char_index += next_valid_index(char_index + 1, line[i]) - char_index;//line[i][].bytes().count();
-    }
-}
-
-fn next_valid_index(mut index: usize, text: &str) -> usize {
-    while index < text.len() && !text.is_char_boundary(index) {
-        index += 1;
-    } index
-}
-
-fn last_valid_index(index: usize, text: &str) -> usize {
-    let mut index = index;
-    while index > 0 && !text.is_char_boundary(index) {
-        index -= 1;
-    } index
-}
-
-pub fn part_of_large_token(text: &str, char_index: usize) -> bool {
-    if text.len() == char_index + 1 {
-        if text.len() == 1 { return false; }
-        for breaker in BREAKS {
-            if breaker.len() == 1 { continue; }
-            if breaker.contains(&text) {
-                return true;
-            }
-        } return false;
-    }
-    if BREAKS.contains(&&text[0..next_valid_index(1, text)]) {
-        for breaker in BREAKS {
-            if breaker.len() == 1 || breaker.len() - 1 <= char_index { continue; }
-            if breaker.contains(&&text[0..next_valid_index(usize::min(breaker.len(), text.len()), &text)]) {
-                return true;
-            } } }
-    for breaker in BREAKS {
-        if breaker.len() == 1 { continue; }
-        if breaker.contains(&&text[char_index..]) {
-            return true;
-        } } false
-}
-
-static BLANK_CHARS: &[&str] = &[
-    " ", ",",
+// the jumps & calls to an addr, and their pc-relative forms (what --pic rewrites them into)
+static RELATIVE_FORMS: &[(&str, &str)] = &[
+    ("Jmp", "JmpRel"), ("Jic", "JicRel"), ("Jnc", "JncRel"), ("Jiz", "JizRel"), ("Jnz", "JnzRel"),
+    ("JiErr", "JiErrRel"), ("JnErr", "JnErrRel"), ("JiCry", "JiCryRel"), ("JnCry", "JnCryRel"), ("Call", "CallRel"),
 ];
 
-static BREAKS: &[&str] = &[
-    " ", "\n", "\t", "!", "@", "#", "$", "%", "^", "&", "*", "(", ")", "-", "=", "+",
-    "[", "]", "{", "}", ";", ":", "'", "\"", ",", "<", ".", ">", "/", "?", "\\", "|",
-    "//",
-];
+// the characters of a "..." token, with the escapes resolved
+fn parse_string_literal(token: &Token, at: &Location) -> Result<Vec<char>, Diagnostic> {
+    let text = &token.text;
+    if lexer::string_literal_end(text) != Some(text.len()) {
+        return Err(at.error_at(codes::STRING, std::slice::from_ref(token), "unterminated string literal"));
+    }
+    let mut chars = vec![];
+    let mut escaped = false;
+    for chr in text[1..text.len() - 1].chars() {
+        if escaped {
+            chars.push(match chr {
+                'n' => '\n',
+                't' => '\t',
+                '0' => '\0',
+                '\\' | '"' => chr,
+                _ => { return Err(at.error_at(codes::STRING, std::slice::from_ref(token), format!("invalid escape sequence '\\{}' in the string literal", chr))); }
+            });
+            escaped = false;
+        } else if chr == '\\' {
+            escaped = true;
+        } else {
+            chars.push(chr);
+        }
+    } Ok(chars)
+}
 
 #[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq)]
 enum Parameter {
     Register (u8),
-    Constant (u16),
+    Constant (i64),  // as given; checked against the width of its param (negative ones being two's complement)
     Address (u32),
     Pointer (u8),
 }
 
-static REGISTERS: &'static [&'static str] = &[
+static REGISTERS: &[&str] = &[
     "rda",
     "rdb",
     "rdc",
@@ -327,300 +291,1331 @@ static REGISTERS: &'static [&'static str] = &[
     "acc",
 ];
 
-fn resolve_number(number: &str, labels: Option<&Vec<Label>>) -> u32 {
-    if let Some(label) = labels.unwrap_or(&vec![]).iter().find(|l| match l {
-        Label::Trait(s,..) | Label::Const(s,..) | Label::Variable(s,..) | Label::Header(s,..) => {
-            s == number
-        },
-        Label::Alloc(..) => false,
-    }) {
-        match label {
-            Label::Header(..,l) => {
-                return *l as u32;
-            },
-            Label::Const(..,l) | Label::Variable(..,l) => {
-                return *l as u32
-            },
-            _ => {}
-        }
-    }
-    if number.len() <= 2 { return number.parse::<u32>().unwrap(); }
+fn parse_literal(number: &str) -> Option<u32> {
+    if number.len() <= 2 { return number.parse::<u32>().ok(); }
     match &number[..2] {
-        "0b" => { u32::from_str_radix(&number[2..], 2).unwrap() },
-        "0x" => { u32::from_str_radix(&number[2..], 16).unwrap() },
-        _ => { number.parse::<u32>().unwrap() },
+        "0b" => { u32::from_str_radix(&number[2..], 2).ok() },
+        "0x" => { u32::from_str_radix(&number[2..], 16).ok() },
+        _ => { number.parse::<u32>().ok() },
     }
 }
 
-fn handle_instruction(errors: &mut Vec<String>, cont: &mut bool, line: &Vec<&str>, native_line_number: usize, parameters: Vec<Parameter>, instructions: &mut Vec<Union<(Instruction, Vec<Parameter>, usize), Label>>) {
-    // parsing normal
-    match INSTRUCTIONS.iter().position(|inst| inst.name.to_uppercase() == line[1].to_uppercase()) {
-        Some(index) => {
-            if INSTRUCTIONS[index].params.len() != parameters.len() {
-                errors.push(format!(
-                    "Invalid number of operands: found '{}' when '{}' were expected. Line: '{}'",
-                    parameters.len(), INSTRUCTIONS[index].params.len(), native_line_number)
-                );
-                *cont = true;
-            }
-            for (i, param) in INSTRUCTIONS[index].params.iter().enumerate() {
-                match_valid(errors, param, &parameters, i, native_line_number);
-            }
-            instructions.push(Union::A((INSTRUCTIONS[index].clone(), parameters, native_line_number)));
-            *cont = true;
+// a number literal, or the value of a symbol
+fn resolve_number(token: &Token, symbols: &SymbolTable, at: &Location) -> Result<i64, Diagnostic> {
+    if token.kind == TokenKind::Number {
+        return parse_literal(&token.text).map(|value| value as i64)
+            .ok_or_else(|| at.error_at(codes::EXPRESSION, std::slice::from_ref(token), format!("invalid number '{}'", token.text)));
+    }
+    match symbols.lookup(&token.text, at.namespace) {
+        Some(symbol) => Ok(symbol.value),
+        None => {
+            let suggestion = symbols.suggest(&token.text, at.namespace);
+            let mut error = at.error_at(codes::UNDEFINED_SYMBOL, std::slice::from_ref(token), match &suggestion {
+                Some((name, _)) => format!("undefined symbol '{}' (did you mean '{}'?)", token.text, name),
+                None => format!("undefined symbol '{}'", token.text),
+            });
+            if let Some((name, symbol)) = suggestion {
+                error = error.with_spanned_note(format!("the {} '{}' is defined here", symbol.kind.name(), name), symbol.span.clone());
+            } Err(error)
         },
-        None => {},
     }
 }
 
-fn match_valid(errors: &mut Vec<String>, param: &Param, parameters: &Vec<Parameter>, i: usize, native_line_number: usize) -> bool {
-    match param {
-        Param::Const8 | Param::Const16 => {
-            if !matches!(parameters[i], Parameter::Constant(_)) {
-                errors.push(format!(
-                    "Invalid operand type; found type {:?}, but expected a Constant. Line: '{}'",
-                    parameters[i], native_line_number
-                ));
-                true
-            } else { false }
+// the token at the index, or an error pointing at the end of the line when it's run out
+fn expect_token<'t>(line: &'t [Token], index: usize, at: &Location, expected: &str) -> Result<&'t Token, Diagnostic> {
+    line.get(index).ok_or_else(|| match line.last() {
+        Some(last) => at.error_at(codes::SYNTAX, std::slice::from_ref(last), format!("expected {} after this", expected)),
+        None => at.error(codes::SYNTAX, format!("expected {}", expected)),
+    })
+}
+
+// the tokens of the value starting at line[*index]; either a single number/label token or a { ... } expression, each
+//    optionally negated ('-' '5', '-' '{' ... '}'); the index is left just past the value
+fn take_value<'t>(line: &'t [Token], index: &mut usize, at: &Location) -> Result<&'t [Token], Diagnostic> {
+    let token = expect_token(line, *index, at, "a value")?;
+    match token.kind {
+        TokenKind::Open if token.text == "{" => {
+            let start = *index;
+            let mut depth = 0;
+            while *index < line.len() {
+                match line[*index].text.as_str() {
+                    "{" => { depth += 1; },
+                    "}" => { depth -= 1; },
+                    _ => {},
+                }
+                *index += 1;
+                if depth == 0 { break; }
+            }
+            if depth != 0 {
+                return Err(at.error_at(codes::SYNTAX, &line[start..start + 1], "this '{' is never closed"));
+            }
+            if *index == start + 2 {
+                return Err(at.error_at(codes::EXPRESSION, &line[start..*index], "empty expression"));
+            }
+            Ok(&line[start..*index])
         },
-        Param::Addr16 | Param::Addr32 => {
-            if !matches!(parameters[i], Parameter::Address(_)) {
-                errors.push(format!(
-                    "Invalid operand type; found type {:?}, but expected an Address. Line: '{}'",
-                    parameters[i], native_line_number
-                ));
-                true
-            } else { false }
+        TokenKind::Number | TokenKind::Ident => {
+            *index += 1;
+            Ok(std::slice::from_ref(token))
         },
-        Param::Reg => {
-            if !matches!(parameters[i], Parameter::Register(_)) {
-                errors.push(format!(
-                    "Invalid operand type; found type {:?}, but expected a Register. Line: '{}'",
-                    parameters[i], native_line_number
-                ));
-                true
-            } else { false }
+        TokenKind::Operator if token.text == "-" && line.get(*index + 1).is_some_and(|next| matches!(next.kind, TokenKind::Number | TokenKind::Ident)) => {
+            *index += 2;
+            Ok(&line[*index - 2..*index])
         },
-        Param::Ptr => {
-            if !matches!(parameters[i], Parameter::Pointer(_)) {
-                errors.push(format!(
-                    "Invalid operand type; found type {:?}, but expected a Pointer. Line: '{}'",
-                    parameters[i], native_line_number
-                ));
-                true
-            } else { false }
+        TokenKind::Operator if token.text == "-" && line.get(*index + 1).is_some_and(|next| next.is(TokenKind::Open, "{")) => {
+            let start = *index;
+            *index += 1;
+            take_value(line, index, at)?;
+            Ok(&line[start..*index])
         },
+        _ => Err(at.error_at(codes::SYNTAX, std::slice::from_ref(token), format!("expected a value, but found '{}'", token.text))),
+    }
+}
+
+// reads the value starting at line[*index] (see take_value)
+fn resolve_value(line: &[Token], index: &mut usize, symbols: &SymbolTable, at: &Location) -> Result<i64, Diagnostic> {
+    match take_value(line, index, at)? {
+        [token] => resolve_number(token, symbols, at),
+        [minus, token] if minus.kind == TokenKind::Operator => Ok(resolve_number(token, symbols, at)?.wrapping_neg()),
+        [minus, expression @ ..] if minus.kind == TokenKind::Operator => Ok(eval_expression(&expression[1..expression.len() - 1], symbols, at)?.wrapping_neg()),
+        expression => eval_expression(&expression[1..expression.len() - 1], symbols, at),
+    }
+}
+
+// the binary operators of { ... } expressions, grouped from the loosest binding to the tightest (C's ordering)
+static EXPRESSION_OPERATORS: &[&[&str]] = &[
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+// the tokens given are the inside of the braces, and never empty
+fn eval_expression(tokens: &[Token], symbols: &SymbolTable, at: &Location) -> Result<i64, Diagnostic> {
+    let mut index = 0;
+    let value = eval_binary(tokens, &mut index, 0, symbols, at)?;
+    if index < tokens.len() {
+        return Err(at.error_at(codes::EXPRESSION, &tokens[index..index + 1], format!("unexpected '{}' in the expression", tokens[index].text)));
+    } Ok(value)
+}
+
+// '%' is lexed as a sigil, but is modulo in here
+fn peek_operator(tokens: &[Token], index: usize, operators: &[&'static str]) -> Option<&'static str> {
+    let token = tokens.get(index).filter(|token| matches!(token.kind, TokenKind::Operator | TokenKind::Sigil))?;
+    operators.iter().copied().find(|operator| *operator == token.text)
+}
+
+fn eval_binary(tokens: &[Token], index: &mut usize, level: usize, symbols: &SymbolTable, at: &Location) -> Result<i64, Diagnostic> {
+    if level == EXPRESSION_OPERATORS.len() {
+        return eval_unary(tokens, index, symbols, at);
+    }
+    let mut value = eval_binary(tokens, index, level + 1, symbols, at)?;
+    while let Some(operator) = peek_operator(tokens, *index, EXPRESSION_OPERATORS[level]) {
+        let operator_token = &tokens[*index..*index + 1];
+        *index += 1;
+        let rhs = eval_binary(tokens, index, level + 1, symbols, at)?;
+        value = match operator {
+            "|" => value | rhs,
+            "^" => value ^ rhs,
+            "&" => value & rhs,
+            "<<" | ">>" => {
+                if !(0..64).contains(&rhs) {
+                    return Err(at.error_at(codes::EXPRESSION, operator_token, format!("invalid shift amount '{}'", rhs)));
+                }
+                if operator == "<<" { value.wrapping_shl(rhs as u32) } else { value.wrapping_shr(rhs as u32) }
+            },
+            "+" => value.wrapping_add(rhs),
+            "-" => value.wrapping_sub(rhs),
+            "*" => value.wrapping_mul(rhs),
+            _ => {
+                if rhs == 0 {
+                    return Err(at.error_at(codes::EXPRESSION, operator_token, "division by zero"));
+                }
+                if operator == "/" { value.wrapping_div(rhs) } else { value.wrapping_rem(rhs) }
+            },
+        };
+    } Ok(value)
+}
+
+fn eval_unary(tokens: &[Token], index: &mut usize, symbols: &SymbolTable, at: &Location) -> Result<i64, Diagnostic> {
+    let Some(token) = tokens.get(*index) else {
+        return Err(at.error_at(codes::EXPRESSION, &tokens[tokens.len() - 1..], "unexpected end of the expression after this"));
+    };
+    *index += 1;
+    match (token.kind, token.text.as_str()) {
+        (TokenKind::Operator, "-") => Ok(eval_unary(tokens, index, symbols, at)?.wrapping_neg()),
+        (TokenKind::Operator, "+") => eval_unary(tokens, index, symbols, at),
+        (TokenKind::Open, "(") => {
+            let open = *index - 1;
+            let value = eval_binary(tokens, index, 0, symbols, at)?;
+            if !tokens.get(*index).is_some_and(|token| token.is(TokenKind::Close, ")")) {
+                return Err(at.error_at(codes::SYNTAX, &tokens[open..open + 1], "this '(' is never closed"));
+            }
+            *index += 1;
+            Ok(value)
+        },
+        (TokenKind::Ident | TokenKind::Number, _) => resolve_number(token, symbols, at),
+        (_, text) => Err(at.error_at(codes::EXPRESSION, std::slice::from_ref(token), format!("unexpected '{}' in the expression", text))),
+    }
+}
+
+// the native instructions a line takes up; a sudo instruction's forms all expand to as many, so the first is counted
+fn expansion_len(line: &[Token]) -> usize {
+    if line[0].is(TokenKind::Operator, "*") { return 1; }
+    SUDO_INSTRUCTIONS.iter().find(|sudo| sudo.name.to_uppercase() == line[0].text.to_uppercase())
+        .and_then(|sudo| sudo.conversions.first())
+        .map_or(1, |(_params, template)| template.split(';').count())
+}
+
+// a sudo instruction's form expanded into its native instructions (the names and operands of each); the refs follow
+//    the operands they were given on, and each operand notes the one it was given as (None for the template's own)
+//    the error is what's wrong with the template, as the operands were already matched against its form
+fn expand_template(template: &str, parameters: &[Parameter], refs: &AddrRefs) -> Result<Vec<NativeExpansion>, String> {
+    let mut next = 0;
+    template.split(';').map(|native| {
+        let mut words = native.split_whitespace();
+        let name = words.next().ok_or("an empty instruction")?;
+        let mut native_parameters = vec![];
+        let mut native_refs = vec![];
+        let mut given = vec![];
+        for word in words {
+            let (parameter, operand) = if let Some(number) = word.strip_prefix('*') {
+                let index = match number {
+                    "" => { next += 1; next - 1 },
+                    number => number.parse::<usize>().ok().and_then(|number| number.checked_sub(1)).ok_or(format!("invalid operand '{}'", word))?,
+                };
+                native_refs.extend(refs.iter()
+                    .filter(|(operand, ..)| *operand == index)
+                    .map(|(_, name, addend)| (native_parameters.len(), name.clone(), *addend)));
+                (parameters.get(index).cloned().ok_or(format!("'{}' is past the {} operand(s) given", word, parameters.len()))?, Some(index))
+            } else if let Some(register) = word.strip_prefix('%') {
+                (Parameter::Register(REGISTERS.iter().position(|name| *name == register).ok_or(format!("unknown register '{}'", word))? as u8), None)
+            } else if let Some(number) = word.strip_prefix('$') {
+                (Parameter::Constant(parse_literal(number).ok_or(format!("invalid number '{}'", word))? as i64), None)
+            } else {
+                return Err(format!("invalid operand '{}'", word));
+            };
+            native_parameters.push(parameter);
+            given.push(operand);
+        }
+        Ok((name.to_string(), native_parameters, native_refs, given))
+    }).collect()
+}
+
+// the operands are the tokens each parameter was written as (empty for those a template gave), for the errors to point at
+#[allow(clippy::too_many_arguments)]  // the parse loop's state, threaded through as the sudo expansions share it
+fn handle_instruction(errors: &mut Vec<Diagnostic>, cont: &mut bool, name: &str, at: &Location, parameters: Vec<Parameter>, operands: &[&[Token]], refs: AddrRefs, instructions: &mut InstructionStream) {
+    // parsing normal
+    if let Some(index) = INSTRUCTIONS.iter().position(|inst| inst.name.to_uppercase() == name.to_uppercase()) {
+        if INSTRUCTIONS[index].params.len() != parameters.len() {
+            errors.push(at.error(codes::OPERAND_COUNT, format!(
+                "'{}' takes {} operand(s), but {} were given",
+                INSTRUCTIONS[index].name, INSTRUCTIONS[index].params.len(), parameters.len()
+            )));
+            *cont = true;
+            return;
+        }
+        for (i, param) in INSTRUCTIONS[index].params.iter().enumerate() {
+            if !match_valid(errors, param, &parameters, i, operands[i], at) && let Err(range) = check_range(param, &parameters[i]) {
+                errors.push(at.error_at(codes::OPERAND_RANGE, operands[i], format!("operand {} ({}) doesn't fit in {}", i + 1, operand_value(&parameters[i]), range)));
+            }
+        }
+        instructions.push(Union::A((INSTRUCTIONS[index].clone(), parameters, at.line, refs)));
+        *cont = true;
+    }
+}
+
+// the name used for an operand's kind in diagnostics
+fn operand_kind(parameter: &Parameter) -> &'static str {
+    match parameter {
+        Parameter::Register(_) => "Register",
+        Parameter::Constant(_) => "Constant",
+        Parameter::Address(_) => "Address",
+        Parameter::Pointer(_) => "Pointer",
+    }
+}
+
+fn param_kind(param: &Param) -> &'static str {
+    match param {
+        Param::Const8 | Param::Const16 => "Constant",
+        Param::Addr16 | Param::Addr32 => "Address",
+        Param::Reg => "Register",
+        Param::Ptr => "Pointer",
+    }
+}
+
+// the operand's value as written
+fn operand_value(parameter: &Parameter) -> String {
+    match parameter {
+        Parameter::Register(register) | Parameter::Pointer(register) => format!("register {}", register),
+        Parameter::Constant(value) => value.to_string(),
+        Parameter::Address(addr) => addr.to_string(),
+    }
+}
+
+// the error is what the operand should've fit in; constants can be given negative, in which case they're two's complement
+fn check_range(param: &Param, parameter: &Parameter) -> Result<(), String> {
+    let (value, range, kind) = match (param, parameter) {
+        (Param::Const8, Parameter::Constant(value)) => (*value, -0x80..=0xFF, "an 8 bit constant"),
+        (Param::Const16, Parameter::Constant(value)) => (*value, -0x8000..=0xFFFF, "a 16 bit constant"),
+        (Param::Addr16, Parameter::Address(addr)) => (*addr as i64, 0..=0xFFFF, "a 16 bit address"),
+        (Param::Addr32, Parameter::Address(addr)) => (*addr as i64, 0..=0xFFFF_FFFF, "a 32 bit address"),
+        (Param::Reg, Parameter::Register(register)) | (Param::Ptr, Parameter::Pointer(register)) => (*register as i64, 0..=REGISTERS.len() as i64 - 1, "the registers"),
+        _ => { return Ok(()); },
+    };
+    match range.contains(&value) {
+        true => Ok(()),
+        false => Err(format!("{} ({}..={})", kind, range.start(), range.end())),
     }
 }
 
+fn match_valid(errors: &mut Vec<Diagnostic>, param: &Param, parameters: &[Parameter], i: usize, operand: &[Token], at: &Location) -> bool {
+    let (valid, expected) = match param {
+        Param::Const8 | Param::Const16 => (matches!(parameters[i], Parameter::Constant(_)), "a Constant"),
+        Param::Addr16 | Param::Addr32 => (matches!(parameters[i], Parameter::Address(_)), "an Address"),
+        Param::Reg => (matches!(parameters[i], Parameter::Register(_)), "a Register"),
+        Param::Ptr => (matches!(parameters[i], Parameter::Pointer(_)), "a Pointer"),
+    };
+    if !valid {
+        errors.push(at.error_at(codes::OPERAND_TYPE, operand, format!(
+            "operand {} should be {}, but found a {}", i + 1, expected, operand_kind(&parameters[i])
+        )));
+    } !valid
+}
+
 #[derive(Debug, Clone)]
 enum Label {
-    Header (String, usize),  // name, program addr
-    Const (String, u16),  // name, value
-    Variable (String, u16),  // name, addr
+    // the values of these are in the symbol table
+    Header (String),  // name
+    Const (String),  // name
+    Variable (String),  // name
     Alloc (usize, Vec<u16>),  // ram addr, byte pairs
     Trait (String, u16),  // trait name, byte pair
+    Data (Vec<u16>, SrcLine, AddrRefs),  // .word/.fill/.ascii/.string byte pairs, placed as is; refs by byte pair index
+    Align (usize, SrcLine),  // .align; pads with zeros up to the next multiple of the boundary
     // macro expansion already happened before this
 }
 
-fn parse_macros_and_allocs(script: &mut Vec<(Vec<&str>, usize)>) {
-    // todo!   collect and extract macros, than expand (the src line numbers should be the same as the extracted macro, so no changes are needed there)    for arg replacement, just look for <...> to find operands being used
-    // todo! also expand any !alloc's (might have to be done later???? idk, &str's are just hard to work with in this context
+// a `!macro name <a> <b> ... !endmacro` definition; the body keeps its own src line numbers
+struct Macro {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Vec<(Vec<Token>, SrcLine)>,
+    pub native_line_number: SrcLine,
 }
 
-fn parse_sudo(mut script: Vec<(Vec<&str>, usize)>) -> Result<(Vec<Union<(Instruction, Vec<Parameter>, usize), Label>>, Vec<Label>), Vec<String>> {
-    let mut errors = vec![];
-    parse_macros_and_allocs(&mut script);
-    // generating the names and values of labels first
-    let mut pg_line_number = 3;  // the first three byte pairs are reserved for the os header
-    let mut labels: Vec<Label> = vec![];
-    for (line, native_line_number) in &script {
-        if line.is_empty() { continue; }  // shouldn't happen
-        match line[0] {
-            "!" => {
-                if line.len() < 2 { errors.push(format!("Invalid !Label, no specified ending/name present; line: '{}'", native_line_number + 1)); }
-                match line[1] {
-                    "function" | "header" | "loop" | "end" | "condition" | "true" | "false" | "if" | "else" | "label" => {
-                        if line.len() < 3 { errors.push(format!("No header name found for label declared on line: '{}'", native_line_number + 1)); }
-                        if labels.iter().any(|l| match l {
-                            Label::Trait(s,..) | Label::Const(s,..) | Label::Variable(s,..) | Label::Header(s,..) => {
-                                s == line[2]
-                            },
-                            Label::Alloc(..) => false,
-                        }) { errors.push(format!("Redefintion of label '{}' on line: '{}'", line[2], native_line_number + 1)); }
-                        labels.push(Label::Header(line[2].to_string(), pg_line_number));
+static MAX_MACRO_DEPTH: usize = 64;
+
+// where a script line came from; diagnostics point at it (or the tokens within it), and at the macro calls it was expanded from
+struct Location<'a> {
+    sources: &'a SourceMap,
+    namespace: &'a str,  // of the line's file
+    line: SrcLine,
+    call_sites: &'a [SrcLine],
+}
+
+impl<'a> Location<'a> {
+    fn new(sources: &'a SourceMap, files: &'a SrcFiles, line: SrcLine, call_sites: &'a [SrcLine]) -> Self {
+        Location { sources, namespace: files.get(line.file).map_or("", |(_, namespace)| namespace.as_str()), line, call_sites }
+    }
+    
+    // the whole line
+    fn span(&self) -> Span {
+        self.sources.line_span(self.line.file, self.line.line)
+    }
+    
+    fn qualify(&self, name: &str) -> String {
+        qualify(self.namespace, name)
+    }
+    
+    // from the first token through the last (just the first, if they ended up on different lines through a macro)
+    fn tokens_span(&self, tokens: &[Token]) -> Span {
+        match (tokens.first(), tokens.last()) {
+            (Some(first), Some(last)) if first.span.file == last.span.file && first.span.line == last.span.line && last.span.columns.end >= first.span.columns.start => {
+                Span { columns: first.span.columns.start..last.span.columns.end, ..first.span.clone() }
+            },
+            (Some(first), _) => first.span.clone(),
+            _ => self.span(),
+        }
+    }
+    
+    fn error(&self, code: &'static str, message: impl Into<String>) -> Diagnostic {
+        self.with_call_sites(Diagnostic::error(code, message).with_span(self.span()))
+    }
+    
+    fn error_at(&self, code: &'static str, tokens: &[Token], message: impl Into<String>) -> Diagnostic {
+        self.with_call_sites(Diagnostic::error(code, message).with_span(self.tokens_span(tokens)))
+    }
+    
+    // expanded lines note the macro call(s) they came from; deep expansions only note the innermost and the original call
+    fn with_call_sites(&self, diagnostic: Diagnostic) -> Diagnostic {
+        match self.call_sites {
+            [] => diagnostic,
+            [call_site] => diagnostic.with_spanned_note("expanded from this macro call", self.sources.line_span(call_site.file, call_site.line)),
+            [outer, middle @ .., inner] => {
+                let mut diagnostic = diagnostic.with_spanned_note("expanded from this macro call", self.sources.line_span(inner.file, inner.line));
+                if !middle.is_empty() {
+                    diagnostic = diagnostic.with_note(format!("... through {} more macro call(s)", middle.len()));
+                }
+                diagnostic.with_spanned_note("originally expanded from this macro call", self.sources.line_span(outer.file, outer.line))
+            },
+        }
+    }
+}
+
+// the name of a '<' 'name' '>' macro parameter starting at the index
+fn macro_param(tokens: &[Token], index: usize) -> Option<&str> {
+    match tokens.get(index..index + 3)? {
+        [open, name, close] if open.is(TokenKind::Open, "<") && name.kind == TokenKind::Ident && close.is(TokenKind::Close, ">") => {
+            Some(name.text.as_str())
+        },
+        _ => None,
+    }
+}
+
+// splits the operand tokens of a line into the individual operands ('%' 'rda', '[' ... ']', '$' '{' ... '}', etc.)
+fn split_operands(tokens: &[Token]) -> Vec<&[Token]> {
+    let mut operands = vec![];
+    let mut index = 0;
+    while index < tokens.len() {
+        let start = index;
+        if tokens[index].kind == TokenKind::Sigil { index += 1; }
+        match tokens.get(index) {
+            Some(token) if token.is(TokenKind::Open, "[") || token.is(TokenKind::Open, "{") => {
+                let mut depth = 0;
+                while index < tokens.len() {
+                    if matches!(tokens[index].text.as_str(), "[" | "{") { depth += 1; }
+                    if matches!(tokens[index].text.as_str(), "]" | "}") { depth -= 1; }
+                    index += 1;
+                    if depth == 0 { break; }
+                }
+            },
+            Some(_) => { index += 1; },
+            None => {},
+        }
+        operands.push(&tokens[start..index]);
+    } operands
+}
+
+// an open .if/.ifdef/.ifndef
+struct Conditional {
+    condition: bool,
+    enclosing: bool,  // whether the blocks around it are kept
+    span: Span,
+    else_span: Option<Span>,
+}
+
+impl Conditional {
+    fn keep(&self) -> bool {
+        self.enclosing && self.condition != self.else_span.is_some()
+    }
+}
+
+// .if <value>, .ifdef <name> and .ifndef <name>, through .else and .endif; the lines of the blocks not taken are dropped
+//    done before the macros (so they can be defined conditionally) and the labels, so the conditions only see the -D's
+//    and the !const's & !define's above them (their errors are left for when they're defined for real)
+fn select_conditionals(script: &mut ScriptLines, sources: &SourceMap, files: &SrcFiles, defines: &[Symbol], errors: &mut Vec<Diagnostic>) {
+    let mut symbols = SymbolTable::default();
+    for define in defines {
+        let _ = symbols.define(define.clone());
+    }
+    let mut blocks: Vec<Conditional> = vec![];
+    let mut remaining = vec![];
+    for (line, native_line_number, call_sites) in std::mem::take(script) {
+        let at = Location::new(sources, files, native_line_number, &call_sites);
+        let enclosing = blocks.last().is_none_or(Conditional::keep);
+        let first = &line[0];
+        if first.kind != TokenKind::Directive {
+            if enclosing { remaining.push((line, native_line_number, call_sites)); }
+            continue;
+        }
+        match first.text.as_str() {
+            ".if" | ".ifdef" | ".ifndef" => {
+                // the conditions of blocks that are dropped anyways aren't looked at
+                let condition = enclosing && match first.text.as_str() {
+                    ".if" => {
+                        let mut index = 1;
+                        let value = resolve_value(&line, &mut index, &symbols, &at).map_err(|error| match error.code {
+                            codes::UNDEFINED_SYMBOL => error.with_note("the conditions can only use the -D's, and the !const's & !define's above them"),
+                            _ => error,
+                        });
+                        warn_extra_tokens(&line, index, &at, errors);
+                        value.map_err(|error| errors.push(error)).is_ok_and(|value| value != 0)
                     },
-                    "define" => {
-                        if line.len() < 4 { errors.push(format!("No operands and/or name found for label declared on line: '{}'", native_line_number + 1)); }
-                        if labels.iter().any(|l| match l {
-                            Label::Trait(s,..) | Label::Const(s,..) | Label::Variable(s,..) | Label::Header(s,..) => {
-                                s == line[2]
-                            },
-                            Label::Alloc(..) => false,
-                        }) { errors.push(format!("Redefintion of label '{}' on line: '{}'", line[2], native_line_number + 1)); }
-                        labels.push(Label::Variable(line[2].to_string(), resolve_number(line[3], None) as u16));
+                    directive => match expect_name(&line, 1, &at, &format!("a name for the {}", directive)) {
+                        Ok(name) => {
+                            warn_extra_tokens(&line, 2, &at, errors);
+                            symbols.lookup(&name.text, at.namespace).is_some() == (directive == ".ifdef")
+                        },
+                        Err(error) => { errors.push(error); false },
                     },
-                    "const" => {
-                        if line.len() < 4 { errors.push(format!("No operands and/or name found for label declared on line: '{}'", native_line_number + 1)); }
-                        if labels.iter().any(|l| match l {
-                            Label::Trait(s,..) | Label::Const(s,..) | Label::Variable(s,..) | Label::Header(s,..) => {
-                                s == line[2]
-                            },
-                            Label::Alloc(..) => false,
-                        }) { errors.push(format!("Redefintion of label '{}' on line: '{}'", line[2], native_line_number + 1)); }
-                        labels.push(Label::Const(line[2].to_string(), resolve_number(line[3], None) as u16));
+                };
+                blocks.push(Conditional { condition, enclosing, span: at.span(), else_span: None });
+            },
+            ".else" => match blocks.last_mut() {
+                Some(Conditional { else_span: Some(other), .. }) => {
+                    errors.push(at.error(codes::CONDITIONAL, "a second .else for the same .if").with_spanned_note("the first .else is here", other.clone()));
+                },
+                Some(block) => { block.else_span = Some(at.span()); },
+                None => { errors.push(at.error(codes::CONDITIONAL, "found .else without a matching .if")); },
+            },
+            ".endif" => {
+                if blocks.pop().is_none() {
+                    errors.push(at.error(codes::CONDITIONAL, "found .endif without a matching .if"));
+                }
+            },
+            "!const" | "!define" if enclosing => {
+                if let Some(name) = line.get(1).filter(|name| name.kind == TokenKind::Ident)
+                    && let Ok(value) = resolve_value(&line, &mut 2, &symbols, &at) {
+                    let _ = symbols.define(Symbol { name: at.qualify(&name.text), kind: SymbolKind::Const, value, span: name.span.clone() });
+                }
+                remaining.push((line, native_line_number, call_sites));
+            },
+            _ => if enclosing { remaining.push((line, native_line_number, call_sites)); },
+        }
+    }
+    for block in blocks {
+        errors.push(Diagnostic::error(codes::CONDITIONAL, "this .if is never closed").with_span(block.span).with_note("expected a .endif for it"));
+    }
+    *script = remaining;
+}
+
+// the -D name=value's, read in as if from a file of their own so diagnostics can point at them
+fn command_line_defines(defines: &[String], sources: &mut SourceMap, errors: &mut Vec<Diagnostic>) -> Vec<Symbol> {
+    if defines.is_empty() { return vec![]; }
+    let file = sources.add("<command line>", defines.iter().map(|define| format!("-D {}", define)).collect::<Vec<String>>().join("\n"));
+    defines.iter().enumerate().filter_map(|(line, define)| {
+        let span = Span { file, line, columns: 3..3 + define.len() };
+        let (name, value) = define.split_once('=').unwrap_or((define, "1"));
+        let value = match value.strip_prefix('-') {
+            Some(magnitude) => parse_literal(magnitude).map(|value| -(value as i64)),
+            None => parse_literal(value).map(|value| value as i64),
+        };
+        if name.is_empty() || name.starts_with(|chr: char| chr.is_ascii_digit()) || !name.chars().all(|chr| chr.is_alphanumeric() || chr == '_') {
+            errors.push(Diagnostic::error(codes::CONDITIONAL, format!("invalid -D name '{}'", name)).with_span(span));
+            return None;
+        }
+        let Some(value) = value else {
+            errors.push(Diagnostic::error(codes::CONDITIONAL, format!("invalid -D value for '{}'", name)).with_span(span)
+                .with_note("the value is a number (-D NAME=0x10), or left off for 1 (-D NAME)"));
+            return None;
+        };
+        Some(Symbol { name: name.to_string(), kind: SymbolKind::Const, value, span })
+    }).collect()
+}
+
+// macros are global, whichever file they're defined in
+fn parse_macros_and_allocs(script: &mut ScriptLines, sources: &SourceMap, files: &SrcFiles, errors: &mut Vec<Diagnostic>) {
+    // collecting and extracting the macro definitions
+    let mut macros: Vec<Macro> = vec![];
+    let mut remaining = vec![];
+    let mut lines = std::mem::take(script).into_iter();
+    while let Some((line, native_line_number, call_sites)) = lines.next() {
+        let at = Location::new(sources, files, native_line_number, &call_sites);
+        if line[0].is(TokenKind::Directive, "!endmacro") {
+            errors.push(at.error(codes::MACRO, "found !endmacro without a matching !macro"));
+            continue;
+        }
+        if !line[0].is(TokenKind::Directive, "!macro") {
+            remaining.push((line, native_line_number, call_sites));
+            continue;
+        }
+        let name = match expect_name(&line, 1, &at, "the macro's name") {
+            Ok(name) => name.text.clone(),
+            Err(error) => {
+                errors.push(error);
+                String::new()
+            },
+        };
+        let name_tokens = line.get(1..2).unwrap_or(&line);
+        if let Some(other) = macros.iter().find(|m| m.name == name) {
+            errors.push(at.error_at(codes::REDEFINITION, name_tokens, format!("redefinition of the macro '{}'", name))
+                .with_spanned_note("first defined here", sources.line_span(other.native_line_number.file, other.native_line_number.line)));
+        }
+        if INSTRUCTIONS.iter().any(|inst| inst.name.to_uppercase() == name.to_uppercase()) ||
+            SUDO_INSTRUCTIONS.iter().any(|inst| inst.name.to_uppercase() == name.to_uppercase()) {
+            errors.push(at.error_at(codes::MACRO, name_tokens, format!("the macro '{}' shares its name with an instruction", name)));
+        }
+        // the parameters are written as <name>
+        let mut params = vec![];
+        let mut index = 2;
+        while index < line.len() {
+            if let Some(param) = macro_param(&line, index) {
+                params.push(param.to_string());
+                index += 3;
+            } else {
+                errors.push(at.error_at(codes::MACRO, &line[index..index + 1], format!("invalid macro parameter '{}'", line[index].text))
+                    .with_note("parameters are written as <name>"));
+                break;
+            }
+        }
+        // the body runs until the matching !endmacro
+        let mut body = vec![];
+        let mut closed = false;
+        for (body_line, body_line_number, _) in lines.by_ref() {
+            let body_at = Location::new(sources, files, body_line_number, &[]);
+            if body_line[0].is(TokenKind::Directive, "!endmacro") {
+                closed = true;
+                break;
+            }
+            if body_line[0].is(TokenKind::Directive, "!macro") {
+                errors.push(body_at.error(codes::MACRO, "macros can't be defined inside of other macros")
+                    .with_spanned_note(format!("inside of the macro '{}' defined here", name), at.span()));
+            }
+            // checking the <name> operand uses against the declared parameters
+            for index in 0..body_line.len() {
+                if let Some(param) = macro_param(&body_line, index) && !params.iter().any(|p| p == param) {
+                    errors.push(body_at.error_at(codes::MACRO, &body_line[index..index + 3], format!("unknown macro parameter '<{}>'", param))
+                        .with_spanned_note(format!("the macro '{}' is defined here", name), at.span()));
+                }
+            }
+            body.push((body_line, body_line_number));
+        }
+        if !closed {
+            errors.push(at.error(codes::MACRO, format!("no !endmacro found for the macro '{}'", name)));
+        }
+        macros.push(Macro { name, params, body, native_line_number });
+    }
+    
+    // expanding every call (the src line numbers stay as the line inside of the macro, with the call site tracked alongside)
+    for line in remaining {
+        expand_macro_line(line, &macros, 0, script, sources, files, errors);
+    }
+    
+    // !alloc's need the label values, so they're resolved in the second pass of parse_sudo instead
+}
+
+fn expand_macro_line(line: (Vec<Token>, SrcLine, Vec<SrcLine>), macros: &[Macro], depth: usize, script: &mut ScriptLines, sources: &SourceMap, files: &SrcFiles, errors: &mut Vec<Diagnostic>) {
+    let (tokens, native_line_number, call_sites) = line;
+    let Some(mac) = macros.iter().find(|m| tokens[0].kind == TokenKind::Ident && m.name == tokens[0].text) else {
+        script.push((tokens, native_line_number, call_sites));
+        return;
+    };
+    let at = Location::new(sources, files, native_line_number, &call_sites);
+    if depth >= MAX_MACRO_DEPTH {
+        errors.push(at.error_at(codes::MACRO, &tokens[..1], format!(
+            "expanding the macro '{}' exceeded the maximum depth of {}", mac.name, MAX_MACRO_DEPTH
+        )).with_note("is the macro recursive?"));
+        return;
+    }
+    let arguments = split_operands(&tokens[1..]);
+    if arguments.len() != mac.params.len() {
+        errors.push(at.error(codes::MACRO, format!(
+            "the macro '{}' takes {} argument(s), but {} were given", mac.name, mac.params.len(), arguments.len()
+        )).with_spanned_note("the macro is defined here", sources.line_span(mac.native_line_number.file, mac.native_line_number.line)));
+        return;
+    }
+    let mut inner_call_sites = call_sites.clone();
+    inner_call_sites.push(native_line_number);
+    for (body_line, body_line_number) in &mac.body {
+        let mut expanded = vec![];
+        let mut index = 0;
+        while index < body_line.len() {
+            if let Some(param) = macro_param(body_line, index) &&
+                let Some(param) = mac.params.iter().position(|p| p == param) {
+                expanded.extend_from_slice(arguments[param]);
+                index += 3;
+                continue;
+            }
+            expanded.push(body_line[index].clone());
+            index += 1;
+        }
+        if expanded.is_empty() { continue; }
+        expand_macro_line((expanded, *body_line_number, inner_call_sites.clone()), macros, depth + 1, script, sources, files, errors);
+    }
+}
+
+enum Block {
+    If { id: usize, else_span: Option<Span> },
+    While { id: usize },
+}
+
+// the flags a condition can name, and the jumps taken when it's set/clear
+static FLAG_JUMPS: &[(&str, &str, &str)] = &[("cond", "Jic", "Jnc"), ("zero", "Jiz", "Jnz"), ("err", "JiErr", "JnErr"), ("carry", "JiCry", "JnCry")];
+// the comparisons, and the sudo instructions jumping when they hold/don't
+static COMPARISON_JUMPS: &[(&str, &str, &str)] = &[("<", "Jlt", "Jge"), (">", "Jgt", "Jle"), ("<=", "Jle", "Jgt"), (">=", "Jge", "Jlt"), ("==", "Jeq", "Jne"), ("!=", "Jne", "Jeq")];
+
+// a token the assembler wrote in itself, pointing back at what it was written for
+fn generated_token(kind: TokenKind, text: &str, span: &Span) -> Token {
+    Token { kind, text: text.to_string(), span: span.clone() }
+}
+
+// the jump taken when the condition holds (or doesn't), up to its target; the condition is a flag (cond, zero, err or
+//    carry), or a comparison of a %register against a %register or $constant (< > <= >= == !=), either after an optional 'not'
+fn condition_jump(condition: &[Token], holds: bool, at: &Location) -> Result<Vec<Token>, Diagnostic> {
+    let span = at.tokens_span(condition);
+    let (holds, condition) = match condition.first() {
+        Some(token) if token.is(TokenKind::Ident, "not") => (!holds, &condition[1..]),
+        _ => (holds, condition),
+    };
+    if let [flag] = condition && let Some((_, set, clear)) = FLAG_JUMPS.iter().find(|(name, ..)| flag.is(TokenKind::Ident, name)) {
+        return Ok(vec![generated_token(TokenKind::Operator, "*", &span), generated_token(TokenKind::Ident, if holds { set } else { clear }, &span)]);
+    }
+    // the comparison outside of any brackets, so {a >> 1} stays an operand
+    let mut depth = 0i32;
+    let operator = condition.iter().position(|token| {
+        match token.text.as_str() {
+            "[" | "{" | "(" => { depth += 1; },
+            "]" | "}" | ")" => { depth -= 1; },
+            text => { return depth == 0 && COMPARISON_JUMPS.iter().any(|(comparison, ..)| *comparison == text); },
+        } false
+    });
+    match operator {
+        Some(operator) if operator > 0 && operator + 1 < condition.len() => {
+            let (_, holds_jump, fails_jump) = COMPARISON_JUMPS.iter().find(|(comparison, ..)| *comparison == condition[operator].text).unwrap();
+            let mut jump = vec![generated_token(TokenKind::Ident, if holds { holds_jump } else { fails_jump }, &span)];
+            jump.extend_from_slice(&condition[..operator]);
+            jump.extend_from_slice(&condition[operator + 1..]);
+            Ok(jump)
+        },
+        _ => Err(at.error(codes::CONTROL_FLOW, "expected a condition")
+            .with_note("a condition is a flag (cond, zero, err or carry), or a comparison like '%rda < $10' (< > <= >= == !=), either after an optional 'not'")),
+    }
+}
+
+// !if <condition>, !else & !endif, and !while [condition] & !endwhile with !break [condition] & !continue [condition] out of
+//    the innermost !while; lowered into hidden labels and the jumps between them, before the labels are counted
+//    the hidden labels' names are given back, so they can be kept out of exports and symbol maps
+fn lower_control_flow(script: &mut ScriptLines, sources: &SourceMap, files: &SrcFiles, errors: &mut Vec<Diagnostic>) -> Vec<String> {
+    let mut blocks: Vec<(Block, Span)> = vec![];
+    let mut next_id = 0;
+    let mut lowered = vec![];
+    let mut hidden = vec![];
+    for (line, native_line_number, call_sites) in std::mem::take(script) {
+        let at = Location::new(sources, files, native_line_number, &call_sites);
+        let first = &line[0];
+        let label = |name: String| vec![generated_token(TokenKind::Directive, "!label", &first.span), generated_token(TokenKind::Ident, &name, &first.span)];
+        let to = |mut jump: Vec<Token>, target: String| {
+            jump.push(generated_token(TokenKind::Sigil, "#", &first.span));
+            jump.push(generated_token(TokenKind::Ident, &target, &first.span));
+            jump
+        };
+        let always = || vec![generated_token(TokenKind::Operator, "*", &first.span), generated_token(TokenKind::Ident, "Jmp", &first.span)];
+        let mut generated = vec![];
+        match (first.kind, first.text.as_str()) {
+            (TokenKind::Directive, "!if") => {
+                match condition_jump(&line[1..], false, &at) {
+                    Ok(jump) => generated.push(to(jump, format!("__if_{}_else", next_id))),
+                    Err(error) => errors.push(error),
+                }
+                blocks.push((Block::If { id: next_id, else_span: None }, at.span()));
+                next_id += 1;
+            },
+            (TokenKind::Directive, "!else") => {
+                warn_extra_tokens(&line, 1, &at, errors);
+                match blocks.last_mut() {
+                    Some((Block::If { else_span: Some(other), .. }, _)) => {
+                        errors.push(at.error(codes::CONTROL_FLOW, "a second !else for the same !if").with_spanned_note("the first !else is here", other.clone()));
                     },
-                    // macros & allocs have been parsed out already
-                    _ => {
-                        errors.push(format!("Invalid label name: '{}' on line: '{}'", line[1], native_line_number + 1));
+                    Some((Block::If { id, else_span }, _)) => {
+                        *else_span = Some(at.span());
+                        generated.push(to(always(), format!("__if_{}_end", id)));
+                        generated.push(label(format!("__if_{}_else", id)));
                     },
+                    _ => { errors.push(at.error(codes::CONTROL_FLOW, "found !else without a matching !if")); },
                 }
             },
-            "." => {
-                if line.len() < 3 { errors.push(format!("Invalid .trait, no specified ending/name present, and no operands found; line: '{}'", native_line_number + 1)); }
-                match line[1] {
-                    "ram_size" | "name" | "program_size" | "page" => {
-                        labels.push(Label::Trait(line[1].to_string(), resolve_number(line[2], None) as u16));
-                        if line[1] == "page" {
-                            pg_line_number = resolve_number(line[2], None) as usize;
-                        }
+            (TokenKind::Directive, "!endif") => {
+                warn_extra_tokens(&line, 1, &at, errors);
+                match blocks.last() {
+                    Some((Block::If { id, else_span }, _)) => {
+                        generated.push(label(format!("__if_{}_{}", id, if else_span.is_some() { "end" } else { "else" })));
+                        blocks.pop();
+                    },
+                    Some((Block::While { .. }, span)) => {
+                        errors.push(at.error(codes::CONTROL_FLOW, "found !endif without a matching !if").with_spanned_note("the innermost open block is this !while", span.clone()));
                     },
-                    _ => {
-                        errors.push(format!("Invalid trait name: '{}' on line: '{}'", line[1], native_line_number + 1));
+                    None => { errors.push(at.error(codes::CONTROL_FLOW, "found !endif without a matching !if")); },
+                }
+            },
+            (TokenKind::Directive, "!while") => {
+                generated.push(label(format!("__while_{}", next_id)));
+                if line.len() > 1 {
+                    match condition_jump(&line[1..], false, &at) {
+                        Ok(jump) => generated.push(to(jump, format!("__while_{}_end", next_id))),
+                        Err(error) => errors.push(error),
                     }
                 }
-            }
-            _ => { pg_line_number += 3; },  // not a blank line, and not a special label line
+                blocks.push((Block::While { id: next_id }, at.span()));
+                next_id += 1;
+            },
+            (TokenKind::Directive, "!endwhile") => {
+                warn_extra_tokens(&line, 1, &at, errors);
+                match blocks.last() {
+                    Some((Block::While { id }, _)) => {
+                        generated.push(to(always(), format!("__while_{}", id)));
+                        generated.push(label(format!("__while_{}_end", id)));
+                        blocks.pop();
+                    },
+                    Some((Block::If { .. }, span)) => {
+                        errors.push(at.error(codes::CONTROL_FLOW, "found !endwhile without a matching !while").with_spanned_note("the innermost open block is this !if", span.clone()));
+                    },
+                    None => { errors.push(at.error(codes::CONTROL_FLOW, "found !endwhile without a matching !while")); },
+                }
+            },
+            (TokenKind::Directive, directive @ ("!break" | "!continue")) => {
+                let Some(id) = blocks.iter().rev().find_map(|(block, _)| match block { Block::While { id } => Some(*id), Block::If { .. } => None }) else {
+                    errors.push(at.error(codes::CONTROL_FLOW, format!("found {} outside of any !while", directive)));
+                    continue;
+                };
+                let target = if directive == "!break" { format!("__while_{}_end", id) } else { format!("__while_{}", id) };
+                match line.len() {
+                    1 => generated.push(to(always(), target)),
+                    _ => match condition_jump(&line[1..], true, &at) {
+                        Ok(jump) => generated.push(to(jump, target)),
+                        Err(error) => errors.push(error),
+                    },
+                }
+            },
+            _ => {
+                lowered.push((line, native_line_number, call_sites));
+                continue;
+            },
+        }
+        hidden.extend(generated.iter().filter(|tokens| tokens[0].is(TokenKind::Directive, "!label")).map(|tokens| tokens[1].text.clone()));
+        lowered.extend(generated.into_iter().map(|tokens| (tokens, native_line_number, call_sites.clone())));
+    }
+    for (block, span) in blocks {
+        let (kind, end, label) = match block {
+            Block::If { id, else_span } => ("!if", "!endif", format!("__if_{}_{}", id, if else_span.is_some() { "end" } else { "else" })),
+            Block::While { id } => ("!while", "!endwhile", format!("__while_{}_end", id)),
+        };
+        // still placing the label it'd have ended with, so its jumps don't add errors of their own
+        let line = SrcLine { file: span.file, line: span.line };
+        lowered.push((vec![generated_token(TokenKind::Directive, "!label", &span), generated_token(TokenKind::Ident, &label, &span)], line, vec![]));
+        hidden.push(label);
+        errors.push(Diagnostic::error(codes::CONTROL_FLOW, format!("this {} is never closed", kind)).with_span(span).with_note(format!("expected a {} for it", end)));
+    }
+    *script = lowered;
+    hidden
+}
+
+// a redefinition is reported against the earlier definition (which is the one kept)
+fn define_symbol(symbols: &mut SymbolTable, symbol: Symbol, at: &Location, diagnostics: &mut Vec<Diagnostic>) {
+    let span = symbol.span.clone();
+    let name = symbol.name.clone();
+    if let Err(earlier) = symbols.define(symbol) {
+        diagnostics.push(at.with_call_sites(
+            Diagnostic::error(codes::REDEFINITION, format!("redefinition of the symbol '{}'", name)).with_span(span)
+        ).with_spanned_note("it was first defined here", earlier.span.clone()));
+    }
+}
+
+// the name token following a directive
+fn expect_name<'t>(line: &'t [Token], index: usize, at: &Location, expected: &str) -> Result<&'t Token, Diagnostic> {
+    let token = expect_token(line, index, at, expected)?;
+    if token.kind != TokenKind::Ident {
+        return Err(at.error_at(codes::SYNTAX, std::slice::from_ref(token), format!("expected {}, but found '{}'", expected, token.text)));
+    } Ok(token)
+}
+
+// the labels a line names other than in the addrs relative to them (its refs) have their value taken as a number,
+//    so they're fixed where they were placed; the name a directive defines doesn't count
+fn fix_labels(line: &[Token], refs: &[(usize, String, i64)], symbols: &mut SymbolTable, at: &Location) {
+    let defines = line[0].kind == TokenKind::Directive && line[0].text.starts_with('!') && line[0].text != "!alloc";
+    let mut named = line.iter().enumerate()
+        .filter(|(index, token)| token.kind == TokenKind::Ident && !(defines && *index == 1))
+        .filter_map(|(_, token)| symbols.lookup(&token.text, at.namespace))
+        .filter(|symbol| symbol.kind == SymbolKind::Label)
+        .map(|symbol| symbol.name.clone())
+        .collect::<Vec<String>>();
+    for (_, name, _) in refs {
+        if let Some(index) = named.iter().position(|other| other == name) { named.remove(index); }
+    }
+    for name in named { symbols.fix(&name); }
+}
+
+// the diagnostics (errors and warnings) are pushed as they're found; None is returned if any were errors
+fn parse_sudo(mut script: ScriptLines, sources: &SourceMap, files: &SrcFiles, defines: Vec<Symbol>, diagnostics: &mut Vec<Diagnostic>) -> Option<ParsedProgram> {
+    // every stage runs regardless of earlier errors, so everything wrong gets reported in one go
+    select_conditionals(&mut script, sources, files, &defines, diagnostics);
+    parse_macros_and_allocs(&mut script, sources, files, diagnostics);
+    let hidden = lower_control_flow(&mut script, sources, files, diagnostics);
+    // generating the names and values of labels first
+    let mut pg_line_number = 3;  // the first three byte pairs are reserved for the os header
+    let mut labels: Vec<Label> = vec![];
+    let mut symbols = SymbolTable::default();
+    for define in defines {
+        let at = Location::new(sources, files, SrcLine { file: define.span.file, line: define.span.line }, &[]);
+        define_symbol(&mut symbols, define, &at, diagnostics);
+    }
+    for (line, native_line_number, call_sites) in &script {
+        let at = Location::new(sources, files, *native_line_number, call_sites);
+        let first = &line[0];
+        if first.kind != TokenKind::Directive {
+            pg_line_number += 3 * expansion_len(line);  // not a special label line
+            continue;
+        }
+        match first.text.as_str() {
+            "!function" | "!header" | "!loop" | "!end" | "!condition" | "!true" | "!false" | "!label" => {
+                let name = match expect_name(line, 1, &at, &format!("a name for the {} label", first.text)) {
+                    Ok(name) => name,
+                    Err(error) => { diagnostics.push(error); continue; },
+                };
+                let name_token = name;
+                let name = at.qualify(&name.text);
+                define_symbol(&mut symbols, Symbol { name: name.clone(), kind: SymbolKind::Label, value: pg_line_number as i64, span: name_token.span.clone() }, &at, diagnostics);
+                if hidden.contains(&name_token.text) { symbols.hide(&name); }
+                labels.push(Label::Header(name));
+            },
+            "!define" => {
+                let name = match expect_name(line, 1, &at, "a name for the !define") {
+                    Ok(name) => name,
+                    Err(error) => { diagnostics.push(error); continue; },
+                };
+                let name_token = name;
+                let name = at.qualify(&name.text);
+                let mut index = 2;
+                // a value with an error still defines the name (as 0), so its uses don't report more errors
+                let value = resolve_value(line, &mut index, &symbols, &at).unwrap_or_else(|error| { diagnostics.push(error); 0 });
+                define_symbol(&mut symbols, Symbol { name: name.clone(), kind: SymbolKind::Variable, value, span: name_token.span.clone() }, &at, diagnostics);
+                labels.push(Label::Variable(name));
+                warn_extra_tokens(line, index, &at, diagnostics);
+            },
+            "!const" => {
+                let name = match expect_name(line, 1, &at, "a name for the !const") {
+                    Ok(name) => name,
+                    Err(error) => { diagnostics.push(error); continue; },
+                };
+                let name_token = name;
+                let name = at.qualify(&name.text);
+                let mut index = 2;
+                // a value with an error still defines the name (as 0), so its uses don't report more errors
+                let value = resolve_value(line, &mut index, &symbols, &at).unwrap_or_else(|error| { diagnostics.push(error); 0 });
+                define_symbol(&mut symbols, Symbol { name: name.clone(), kind: SymbolKind::Const, value, span: name_token.span.clone() }, &at, diagnostics);
+                labels.push(Label::Const(name));
+                warn_extra_tokens(line, index, &at, diagnostics);
+            },
+            "!alloc" => {
+                // takes no program space; resolved in the second pass once the labels are known
+            },
+            "!extern" => {
+                // a label of another object, resolved when linking; the name is taken as is (no namespace)
+                let name = match expect_name(line, 1, &at, "the name of the !extern label") {
+                    Ok(name) => name,
+                    Err(error) => { diagnostics.push(error); continue; },
+                };
+                define_symbol(&mut symbols, Symbol { name: name.text.clone(), kind: SymbolKind::Extern, value: 0, span: name.span.clone() }, &at, diagnostics);
+                warn_extra_tokens(line, 2, &at, diagnostics);
+            },
+            ".word" | ".fill" | ".ascii" | ".string" => {
+                match parse_data(line, &symbols, &at, false) {
+                    Ok(Label::Data(byte_pairs, ..)) => { pg_line_number += byte_pairs.len(); },
+                    Ok(_) => {},
+                    Err(error) => diagnostics.push(error),
+                }
+            },
+            ".align" => {
+                match parse_align(line, &symbols, &at) {
+                    Ok(boundary) => { pg_line_number = pg_line_number.next_multiple_of(boundary); },
+                    Err(error) => diagnostics.push(error),
+                }
+            },
+            ".ram_size" | ".name" | ".program_size" | ".page" => {
+                let mut index = 1;
+                match resolve_value(line, &mut index, &symbols, &at) {
+                    Ok(value) if !(0..=u16::MAX as i64).contains(&value) => {
+                        diagnostics.push(at.error_at(codes::OPERAND_RANGE, &line[1..index], format!(
+                            "the {} {} doesn't fit in a byte pair (0..={})", first.text, value, u16::MAX
+                        )));
+                    },
+                    Ok(value) => {
+                        labels.push(Label::Trait(first.text[1..].to_string(), value as u16));
+                        symbols.add_trait(Symbol { name: first.text[1..].to_string(), kind: SymbolKind::Trait, value, span: first.span.clone() });
+                        if first.text == ".page" {
+                            pg_line_number = value as usize;
+                        }
+                    },
+                    Err(error) => diagnostics.push(error),
+                }
+                warn_extra_tokens(line, index, &at, diagnostics);
+            },
+            // macros have been parsed out already
+            text if text.starts_with('!') => {
+                diagnostics.push(at.error_at(codes::UNKNOWN_DIRECTIVE, &line[..1], format!("unknown label kind '{}'", text)));
+            },
+            text => {
+                diagnostics.push(at.error_at(codes::UNKNOWN_DIRECTIVE, &line[..1], format!("unknown trait '{}'", text))
+                    .with_note("the traits are .ram_size, .name, .program_size and .page, and the data .word, .fill, .ascii, .string and .align"));
+            },
         }
     }
-    println!("Generated labels: {:?}", labels);
-    if !errors.is_empty() { return Err(errors); }
     
     let mut instructions = vec![];
+    let mut alloc_spans: Vec<(std::ops::Range<usize>, Span)> = vec![];  // for catching overlapping !alloc's
     // unless the instruction starts with *, first check sudo, than normal; with a * just check normal instructions
-    for (mut line, native_line_number) in script {
-        // identifying parameters
-        let mut parameters = vec![];
-        let mut index = 0;
-        match line[0] {
-            "!" | "." => {
-                if line.len() < 3 { errors.push(format!("Invalid label declared on line: '{}'", native_line_number + 1)); }
-                let offset = if line[0] == "." { 1 } else { 2 };
-                instructions.push(Union::B(
-                    labels.iter().find(|l| match l {
-                        Label::Trait(s,..) | Label::Const(s,..) | Label::Variable(s,..) | Label::Header(s,..) => {
-                            s == line[offset]
-                        },
-                        Label::Alloc(..) => false,
-                    }).unwrap().clone()
-                ));
-                continue;
-            },
-            "*" => { index += 1; },
-            _ => {}
-        }
-        
-        while line.len() >= 2 && index < line.len() - 2 {
-            index += 1;
-            
-            match line[index] {
-                "[" => {
-                    // either: '[' '%' 'reg' ']'   or '[' '%' 'reg' '+' '$' 'num' ']'
-                    if line[index + 3] == "]" {
-                        // just a pointer to the register mentioned
-                        match REGISTERS.iter().position(|reg| reg.to_uppercase() == line[index + 2].to_uppercase()) {
-                            Some(index) => parameters.push(Parameter::Pointer(index as u8)),
-                            None => {
-                                errors.push(
-                                    format!("Invalid register name given for pointer, '{}', on line {}, token number '{}'",
-                                            line[index + 2], native_line_number + 1, index + 2)
-                                );
-                            }
-                        };
-                        index += 3;
-                    } else {
-                        // needs to become an offset instruction (should be auto done if setup right)
-                        // push an extra parameter onto it (should be as easy as that I think?)
-                        match REGISTERS.iter().position(|reg| reg.to_uppercase() == line[index + 2].to_uppercase()) {
-                            Some(index) => parameters.push(Parameter::Pointer(index as u8)),
-                            None => {
-                                errors.push(
-                                    format!("Invalid register name given for pointer, '{}', on line {}, token number '{}'",
-                                            line[index + 2], native_line_number + 1, index + 2)
-                                );
-                            }
-                        };
-                        parameters.push(Parameter::Constant(resolve_number(line[index + 5], Some(&labels)) as u16));
-                        index += 6;
-                    }
-                },
-                "{" => {
-                    // const stuff, expr needs resolution todo!    should this be done before? or will pointers not using constants? but they could subtract a constant that had arithmatic? idk
-                    index += 1;
-                },
-                "#" => {
-                    // address
-                    parameters.push(Parameter::Address(resolve_number(line[index + 1], Some(&labels))));
-                    index += 1;
-                },
-                "$" | "@" => {
-                    // constant of some sort
-                    parameters.push(Parameter::Constant(resolve_number(line[index + 1], Some(&labels)) as u16));
-                    index += 1;
-                },
-                "%" => {
-                    // register of sorts
-                    match REGISTERS.iter().position(|reg| reg.to_uppercase() == line[index + 1].to_uppercase()) {
-                        Some(index) => parameters.push(Parameter::Register(index as u8)),
-                        None => {
-                            errors.push(
-                                format!("Invalid register name, '{}', on line {}, token number '{}'",
-                                line[index + 1], native_line_number + 1, index + 1)
-                            );
+    for (line, native_line_number, call_sites) in &script {
+        let at = Location::new(sources, files, *native_line_number, call_sites);
+        let first = &line[0];
+        if first.is(TokenKind::Directive, "!alloc") {
+            fix_labels(line, &[], &mut symbols, &at);
+            match parse_alloc(line, &symbols, &at) {
+                Ok(alloc) => {
+                    if let Label::Alloc(addr, byte_pairs) = &alloc {
+                        let range = *addr..*addr + byte_pairs.len();
+                        if let Some((other, span)) = alloc_spans.iter().find(|(other, _)| range.start < other.end && other.start < range.end) {
+                            diagnostics.push(at.error(codes::ALLOC, format!(
+                                "this !alloc (ram {}..{}) overlaps another (ram {}..{})", range.start, range.end, other.start, other.end
+                            )).with_spanned_note("the other !alloc is here", span.clone()));
                         }
-                    };
-                    index += 1;
+                        alloc_spans.push((range, at.span()));
+                    }
+                    instructions.push(Union::B(alloc));
                 },
-                _ => {
-                    errors.push(
-                        format!("Invalid operand type, '{}', on line {}, token number '{}'",
-                                line[index], native_line_number + 1, index + 1)
-                    );
+                Err(error) => diagnostics.push(error),
+            }
+            continue;
+        }
+        if first.kind == TokenKind::Directive {
+            // the labels sit in the stream too, so compile_script knows where they land
+            //    traits can be given more than once (.page), so their value is re-read rather than looked up by name
+            if matches!(first.text.as_str(), ".word" | ".fill" | ".ascii" | ".string") {
+                // the first pass already reported errors in the data's shape, leaving those of the values
+                if parse_data(line, &symbols, &at, false).is_err() { continue; }
+                match parse_data(line, &symbols, &at, true) {
+                    Ok(data) => {
+                        if let Label::Data(_, _, refs) = &data { fix_labels(line, refs, &mut symbols, &at); }
+                        instructions.push(Union::B(data));
+                    },
+                    Err(error) => diagnostics.push(error),
                 }
+                continue;
             }
+            fix_labels(line, &[], &mut symbols, &at);
+            if first.text == ".align" {
+                if let Ok(boundary) = parse_align(line, &symbols, &at) {
+                    instructions.push(Union::B(Label::Align(boundary, *native_line_number)));
+                }
+                continue;
+            }
+            if first.text.starts_with('.') {
+                if let Ok(value) = resolve_value(line, &mut 1, &symbols, &at) {
+                    instructions.push(Union::B(Label::Trait(first.text[1..].to_string(), value as u16)));
+                }
+                continue;
+            }
+            let name = at.qualify(line.get(1).map_or("", |name| name.text.as_str()));
+            if let Some(label) = labels.iter().find(|l| match l {
+                Label::Trait(s, _) | Label::Const(s) | Label::Variable(s) | Label::Header(s) => {
+                    *s == name
+                },
+                Label::Alloc(..) | Label::Data(..) | Label::Align(..) => false,
+            }) { instructions.push(Union::B(label.clone())); }
+            continue;
+        }
+        
+        // identifying the instruction & its parameters
+        let native = first.is(TokenKind::Operator, "*");
+        let name = match expect_name(line, native as usize, &at, "an instruction") {
+            Ok(name) => name,
+            Err(error) => { diagnostics.push(error); continue; },
+        };
+        let mut parameters = vec![];
+        let mut operands = vec![];
+        let mut refs = vec![];
+        let mut index = native as usize + 1;
+        let mut valid = true;
+        while index < line.len() {
+            let start = index;
+            if let Err(error) = parse_operand(line, &mut index, &symbols, &at, &mut parameters, &mut refs) {
+                diagnostics.push(error);
+                valid = false;
+                break;
+            }
+            operands.resize(parameters.len(), &line[start..index]);
         }
+        if !valid { continue; }
+        fix_labels(line, &refs, &mut symbols, &at);
         
-        if line[0] == "*" {
+        if native {
             let mut cont = false;
-            handle_instruction(&mut errors, &mut cont, &line, native_line_number, parameters, &mut instructions);
+            handle_instruction(diagnostics, &mut cont, &name.text, &at, parameters, &operands, refs, &mut instructions);
             if cont { continue; }
+            diagnostics.push(at.error_at(codes::UNKNOWN_INSTRUCTION, &line[..2], format!("unknown native instruction '{}'", name.text)));
         } else {
             // parsing sudo
-            match SUDO_INSTRUCTIONS.iter().position(|inst| inst.name.to_uppercase() == line[0].to_uppercase()) {
-                Some(index) => {
-                    let sudo = &SUDO_INSTRUCTIONS[index];
-                    // finding the best match for the instruction
-                    match sudo.conversions.iter().position(|(_name, params, _replacement)| params.len() == parameters.len() && !params.iter().enumerate().any(|(i, p)| {
-                        if i >= parameters.len() { return false; }
-                        match_valid(&mut vec![], p, &parameters, i, native_line_number)
-                    })) {
-                        Some(index) => {
-                            let (name, _params, _replacement) = sudo.conversions[index].clone();
-                            let instruction = INSTRUCTIONS.iter().find(|inst| inst.name == name).unwrap();
-                            instructions.push(Union::A((instruction.clone(), parameters, native_line_number)));
-                            continue;
-                        },
-                        None => {},
+            let Some(index) = SUDO_INSTRUCTIONS.iter().position(|inst| inst.name.to_uppercase() == name.text.to_uppercase()) else {
+                let mut error = at.error_at(codes::UNKNOWN_INSTRUCTION, &line[..1], format!("unknown instruction '{}'", name.text));
+                if let Some(inst) = INSTRUCTIONS.iter().find(|inst| inst.name.to_uppercase() == name.text.to_uppercase()) {
+                    error = error.with_note(format!("'{}' is a native instruction; native instructions are written as '*{}'", inst.name, inst.name));
+                }
+                diagnostics.push(error);
+                continue;
+            };
+            let sudo = &SUDO_INSTRUCTIONS[index];
+            // finding the best match for the instruction
+            if let Some(index) = sudo.conversions.iter().position(|(params, _template)| params.len() == parameters.len() && !params.iter().enumerate().any(|(i, p)| {
+                if i >= parameters.len() { return false; }
+                match_valid(&mut vec![], p, &parameters, i, &[], &at)
+            })) {
+                let (_params, template) = sudo.conversions[index];
+                match expand_template(template, &parameters, &refs) {
+                    Ok(natives) => {
+                        for (name, parameters, refs, given) in natives {
+                            let mut cont = false;
+                            let operands = given.iter().map(|given| given.map_or(&[][..], |index| operands[index])).collect::<Vec<&[Token]>>();
+                            handle_instruction(diagnostics, &mut cont, &name, &at, parameters, &operands, refs, &mut instructions);
+                            if !cont {
+                                diagnostics.push(at.error(codes::INTERNAL, format!("the sudo instruction '{}' expands to the unknown instruction '{}'", sudo.name, name)));
+                            }
+                        }
+                    },
+                    Err(error) => diagnostics.push(at.error(codes::INTERNAL, format!("the sudo instruction '{}' has an invalid expansion: {}", sudo.name, error))),
+                }
+                continue;
+            }
+            diagnostics.push(at.error(codes::OPERAND_TYPE, format!(
+                "no form of '{}' takes the operands ({})", sudo.name, parameters.iter().map(operand_kind).collect::<Vec<&str>>().join(", ")
+            )).with_note(format!(
+                "the forms of '{}' take: {}", sudo.name,
+                sudo.conversions.iter().map(|(params, _template)| format!(
+                    "({})", params.iter().map(param_kind).collect::<Vec<&str>>().join(", ")
+                )).collect::<Vec<String>>().join(", ")
+            )));
+        }
+    }
+    if diagnostics.iter().any(Diagnostic::is_error) { return None; }
+    Some((instructions, labels, symbols))
+}
+
+// '%' 'name'; the index is left just past the name
+fn parse_register(line: &[Token], index: &mut usize, at: &Location) -> Result<u8, Diagnostic> {
+    let sigil = expect_token(line, *index, at, "a %register")?;
+    if !sigil.is(TokenKind::Sigil, "%") {
+        return Err(at.error_at(codes::OPERAND_TYPE, std::slice::from_ref(sigil), format!("expected a %register, but found '{}'", sigil.text)));
+    }
+    let name = expect_token(line, *index + 1, at, "a register name")?;
+    match REGISTERS.iter().position(|reg| reg.to_uppercase() == name.text.to_uppercase()) {
+        Some(register) => {
+            *index += 2;
+            Ok(register as u8)
+        },
+        None => Err(at.error_at(codes::UNKNOWN_REGISTER, std::slice::from_ref(name), format!("unknown register '{}'", name.text))),
+    }
+}
+
+// parses the operand starting at line[*index] into its parameter(s), leaving the index just past it
+//    a pointer with an offset ('[' '%' 'reg' '+' '$' 'num' ']') becomes two; the pointer and then the offset constant
+fn parse_operand(line: &[Token], index: &mut usize, symbols: &SymbolTable, at: &Location, parameters: &mut Vec<Parameter>, refs: &mut AddrRefs) -> Result<(), Diagnostic> {
+    let token = &line[*index];
+    match (token.kind, token.text.as_str()) {
+        (TokenKind::Open, "[") => {
+            *index += 1;
+            parameters.push(Parameter::Pointer(parse_register(line, index, at)?));
+            // the offset; '+' then either '$' 'num', or a { ... } expression
+            if line.get(*index).is_some_and(|token| token.is(TokenKind::Operator, "+")) {
+                *index += 1;
+                if line.get(*index).is_some_and(|token| token.is(TokenKind::Sigil, "$")) { *index += 1; }
+                parameters.push(Parameter::Constant(resolve_value(line, index, symbols, at)?));
+            }
+            let close = expect_token(line, *index, at, "the closing ']'")?;
+            if !close.is(TokenKind::Close, "]") {
+                return Err(at.error_at(codes::SYNTAX, std::slice::from_ref(close), format!("expected the closing ']', but found '{}'", close.text)));
+            }
+            *index += 1;
+        },
+        (TokenKind::Open, "{") => {
+            // a constant expression, resolved now that every label is known
+            parameters.push(Parameter::Constant(resolve_value(line, index, symbols, at)?));
+        },
+        (TokenKind::Sigil, "#") => {
+            // address
+            *index += 1;
+            let start = *index;
+            let value = resolve_value(line, index, symbols, at)?;
+            // relative to a single label, it gets relocated along with it (#label, #{label + 3}); the difference of two doesn't
+            let mut labels = line[start..*index].iter()
+                .filter(|token| token.kind == TokenKind::Ident)
+                .filter_map(|token| symbols.lookup(&token.text, at.namespace))
+                .filter(|symbol| matches!(symbol.kind, SymbolKind::Label | SymbolKind::Extern));
+            if let (Some(symbol), None) = (labels.next(), labels.next()) {
+                refs.push((parameters.len(), symbol.name.clone(), value - symbol.value));
+            }
+            if !(0..=u32::MAX as i64).contains(&value) {
+                return Err(at.error_at(codes::OPERAND_RANGE, &line[start..*index], format!("the address {} is out of range", value))
+                    .with_note("addresses are 0 through 0xFFFFFFFF (0xFFFF for ram and jumps)"));
+            }
+            parameters.push(Parameter::Address(value as u32));
+        },
+        (TokenKind::Sigil, "$" | "@") => {
+            // constant of some sort
+            *index += 1;
+            parameters.push(Parameter::Constant(resolve_value(line, index, symbols, at)?));
+        },
+        (TokenKind::Sigil, "%") => {
+            // register of sorts
+            parameters.push(Parameter::Register(parse_register(line, index, at)?));
+        },
+        (_, text) => {
+            return Err(at.error_at(codes::OPERAND_TYPE, std::slice::from_ref(token), format!("invalid operand '{}'", text))
+                .with_note("operands are %registers, $constants, #addresses, [%pointers] or { expressions }"));
+        },
+    } Ok(())
+}
+
+// the labels/traits only take a single value, so anything after it is ignored
+fn warn_extra_tokens(line: &[Token], index: usize, at: &Location, diagnostics: &mut Vec<Diagnostic>) {
+    if index < line.len() {
+        diagnostics.push(at.with_call_sites(
+            Diagnostic::warning(codes::EXTRA_TOKENS, "ignoring the extra tokens after the value").with_span(at.tokens_span(&line[index..]))
+        ));
+    }
+}
+
+// !alloc <ram addr> <data...>; the data being any mix of $words, "strings" (one char per byte pair), and 'zeros <count>' blocks
+fn parse_alloc(line: &[Token], symbols: &SymbolTable, at: &Location) -> Result<Label, Diagnostic> {
+    let mut index = 1;
+    if line.get(index).is_some_and(|token| token.is(TokenKind::Sigil, "#")) { index += 1; }
+    let start = index;
+    let addr = resolve_value(line, &mut index, symbols, at)?;
+    if !(0..=u16::MAX as i64).contains(&addr) {
+        return Err(at.error_at(codes::ALLOC, &line[start..index], format!("invalid !alloc addr {}", addr)));
+    }
+    let addr = addr as usize;
+    expect_token(line, index, at, "the data")?;
+    let mut byte_pairs = vec![];
+    while index < line.len() {
+        let token = &line[index];
+        match (token.kind, token.text.as_str()) {
+            (TokenKind::Sigil, "$" | "@") => {
+                index += 1;
+                let start = index;
+                let value = resolve_value(line, &mut index, symbols, at)?;
+                if !(-0x8000..=u16::MAX as i64).contains(&value) {
+                    return Err(at.error_at(codes::ALLOC, &line[start..index], format!("the value {} doesn't fit in a byte pair", value)));
+                }
+                byte_pairs.push(value as u16);
+            },
+            (TokenKind::Ident, "zeros") => {
+                index += 1;
+                let start = index;
+                let count = resolve_value(line, &mut index, symbols, at)?;
+                if !(0..=u16::MAX as i64).contains(&count) {
+                    return Err(at.error_at(codes::ALLOC, &line[start..index], format!("invalid zeros count {}", count)));
+                }
+                byte_pairs.extend(std::iter::repeat_n(0u16, count as usize));
+            },
+            (TokenKind::String, _) => {
+                for chr in parse_string_literal(token, at)? {
+                    if chr as u32 > u16::MAX as u32 {
+                        return Err(at.error_at(codes::ALLOC, std::slice::from_ref(token), format!("the character '{}' doesn't fit in a byte pair", chr)));
                     }
-                },
-                None => {},
+                    byte_pairs.push(chr as u16);
+                }
+                index += 1;
+            },
+            (_, other) => {
+                return Err(at.error_at(codes::ALLOC, std::slice::from_ref(token), format!("invalid !alloc data '{}'", other))
+                    .with_note("the data is any mix of $value, \"text\", and zeros <count>"));
+            },
+        }
+    }
+    if addr.checked_add(byte_pairs.len()).is_none_or(|end| end > u16::MAX as usize) {
+        return Err(at.error(codes::ALLOC, format!(
+            "the !alloc runs past the end of ram (addr '{}' + '{}' byte pairs)", addr, byte_pairs.len()
+        )));
+    }
+    Ok(Label::Alloc(addr, byte_pairs))
+}
+
+// the chars of a .ascii/.string, checked to fit in the given bits
+fn data_chars(token: &Token, at: &Location, bits: u32) -> Result<Vec<u16>, Diagnostic> {
+    parse_string_literal(token, at)?.into_iter().map(|chr| {
+        if (chr as u32) >> bits != 0 {
+            return Err(at.error_at(codes::DATA, std::slice::from_ref(token), format!("the character '{}' doesn't fit in {} bits", chr, bits)));
+        } Ok(chr as u16)
+    }).collect()
+}
+
+// the value of a .word/.fill as its byte pair, signed or not; the tokens are the value's
+fn data_value(value: i64, tokens: &[Token], at: &Location) -> Result<u16, Diagnostic> {
+    if !(-0x8000..=u16::MAX as i64).contains(&value) {
+        return Err(at.error_at(codes::DATA, tokens, format!("the value {} doesn't fit in a byte pair", value)));
+    } Ok(value as u16)
+}
+
+// .word <values...>        a byte pair each; a #label is an address (relocated in objects), otherwise $ is optional
+// .fill <count> [value]    count byte pairs of the value (or 0)
+// .ascii "text"...         packed two chars per byte pair (the first high), the last padded with 0
+// .string "text"...        one char per byte pair, like the strings of !alloc
+//    the .word values can name labels further on, so the first pass only counts them (resolve = false)
+fn parse_data(line: &[Token], symbols: &SymbolTable, at: &Location, resolve: bool) -> Result<Label, Diagnostic> {
+    let directive = line[0].text.as_str();
+    let mut index = 1;
+    let mut byte_pairs = vec![];
+    let mut refs = vec![];
+    expect_token(line, index, at, &format!("the {} data", directive))?;
+    match directive {
+        ".word" => {
+            while index < line.len() {
+                let address = line[index].is(TokenKind::Sigil, "#");
+                if address || line[index].is(TokenKind::Sigil, "$") { index += 1; }
+                let start = index;
+                if !resolve {
+                    take_value(line, &mut index, at)?;
+                    byte_pairs.push(0);
+                    continue;
+                }
+                let value = resolve_value(line, &mut index, symbols, at)?;
+                // the same as a # operand; relocated when relative to a single label
+                let mut labels = line[start..index].iter()
+                    .filter(|token| address && token.kind == TokenKind::Ident)
+                    .filter_map(|token| symbols.lookup(&token.text, at.namespace))
+                    .filter(|symbol| matches!(symbol.kind, SymbolKind::Label | SymbolKind::Extern));
+                if let (Some(symbol), None) = (labels.next(), labels.next()) {
+                    refs.push((byte_pairs.len(), symbol.name.clone(), value - symbol.value));
+                }
+                byte_pairs.push(data_value(value, &line[start..index], at)?);
+            }
+        },
+        ".fill" => {
+            let count = resolve_value(line, &mut index, symbols, at)?;
+            if !(0..=u16::MAX as i64).contains(&count) {
+                return Err(at.error_at(codes::DATA, &line[1..index], format!("invalid .fill count {}", count)));
+            }
+            if line.get(index).is_some_and(|token| token.is(TokenKind::Sigil, "$")) {
+                index += 1;
+                expect_token(line, index, at, "the .fill value")?;
+            }
+            let start = index;
+            let value = if index < line.len() && resolve { resolve_value(line, &mut index, symbols, at)? }
+                else if index < line.len() { take_value(line, &mut index, at)?; 0 }
+                else { 0 };
+            byte_pairs.extend(std::iter::repeat_n(data_value(value, &line[start..index], at)?, count as usize));
+            if index < line.len() {
+                return Err(at.error_at(codes::DATA, &line[index..], "unexpected tokens after the .fill value"));
+            }
+        },
+        ".ascii" | ".string" => {
+            let mut chars = vec![];
+            for token in &line[1..] {
+                if token.kind != TokenKind::String {
+                    return Err(at.error_at(codes::DATA, std::slice::from_ref(token), format!("expected a \"string\" for the {}, but found '{}'", directive, token.text)));
+                }
+                chars.extend(data_chars(token, at, if directive == ".ascii" { 8 } else { 16 })?);
             }
-        }
-        errors.push(format!("Invalid Instruction on line '{}':\n> Tokens: {:?}", native_line_number + 1, line));
+            byte_pairs = match directive {
+                ".ascii" => chars.chunks(2).map(|pair| pair[0] << 8 | pair.get(1).copied().unwrap_or(0)).collect(),
+                _ => chars,
+            };
+        },
+        _ => { return Err(at.error(codes::INTERNAL, format!("'{}' isn't a data directive", directive))); },
+    }
+    Ok(Label::Data(byte_pairs, at.line, refs))
+}
+
+// .align <boundary>; resolved in the first pass, as it moves the labels after it
+fn parse_align(line: &[Token], symbols: &SymbolTable, at: &Location) -> Result<usize, Diagnostic> {
+    let mut index = 1;
+    let boundary = resolve_value(line, &mut index, symbols, at)?;
+    if !(1..=u16::MAX as i64).contains(&boundary) {
+        return Err(at.error_at(codes::DATA, &line[1..index], format!("invalid .align boundary {}", boundary)).with_note("the boundary is a number of byte pairs, from 1"));
     }
-    if !errors.is_empty() { return Err(errors); }
-    Ok((instructions, labels))
+    if index < line.len() {
+        return Err(at.error_at(codes::DATA, &line[index..], "unexpected tokens after the .align boundary"));
+    } Ok(boundary as usize)
 }
 
 #[derive(Debug, Clone)]
@@ -629,166 +1624,598 @@ enum Union<A, B> {
     B(B),
 }
 
-fn search_for_trait (labels: &Vec<Label>, trait_name: &str, none_case: usize) -> usize {
+// the parsed program; native instructions (instruction, operands, src line, label addrs) interleaved with the labels they sit under
+type InstructionStream = Vec<Union<(Instruction, Vec<Parameter>, SrcLine, AddrRefs), Label>>;
+// what parse_sudo gives back; the instructions, the labels and the symbol table
+type ParsedProgram = (InstructionStream, Vec<Label>, SymbolTable);
+// the address operands given relative to a single label (or !extern), which objects relocate; (operand index, symbol, addend)
+type AddrRefs = Vec<(usize, String, i64)>;
+// a native instruction of a sudo's expansion; the name, operands & refs, and the sudo operand each operand was given as
+type NativeExpansion = (String, Vec<Parameter>, AddrRefs, Vec<Option<usize>>);
+// the tokenized src, minus the comments; (tokens, src line, lines of the macro calls it was expanded from)
+type ScriptLines = Vec<(Vec<Token>, SrcLine, Vec<SrcLine>)>;
+// the compiled program; (disc addr, (byte pair, src line))
+type DiscWords = Vec<(usize, (u16, SrcLine))>;
+// the src files read in, by file id; (canonical path, namespace its labels go under)
+type SrcFiles = Vec<(std::path::PathBuf, String)>;
+
+// a line (0 based) of one of the src files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct SrcLine {
+    file: usize,
+    line: usize,
+}
+// the initialized ram from the !alloc's; (ram addr, byte pairs)
+type RamAllocs = Vec<(usize, Vec<u16>)>;
+
+fn search_for_trait (labels: &[Label], trait_name: &str, none_case: usize) -> usize {
     for label in labels {
-        match label {
-            Label::Trait(name, value) => {
-                if name == trait_name { return *value as usize; }
-            },
-            _ => {}
+        if let Label::Trait(name, value) = label && name == trait_name {
+            return *value as usize;
         }
     } none_case
 }
 
-fn compile_script(instructions: Vec<Union<(Instruction, Vec<Parameter>, usize), Label>>, labels: Vec<Label>) -> Result<Vec<(usize, (u16, usize))>, String> {
+// the program's !extern's only get values when linked
+fn check_extern_refs(refs: &AddrRefs, symbols: &SymbolTable) -> Result<(), Diagnostic> {
+    if let Some((_, name, _)) = refs.iter().find(|(_, name, _)| symbols.get(name).is_some_and(|symbol| symbol.kind == SymbolKind::Extern)) {
+        return Err(Diagnostic::error(codes::LINK, format!("the !extern '{}' is only known once linked", name))
+            .with_note("assemble as an object (asm --object) and link it with the object defining it"));
+    } Ok(())
+}
+
+// the listing rows are recorded alongside, as things are placed
+fn compile_script(instructions: InstructionStream, labels: Vec<Label>, symbols: &SymbolTable) -> Result<(DiscWords, RamAllocs, Vec<listing::Row>), Diagnostic> {
     let mut bytes = vec![
         // the predefined header
-        (0, (search_for_trait(&labels, "ram_size", 0) as u16, 0)),
-        (0, (search_for_trait(&labels, "program_size", ({
-            let mut i = 0;
-            for inst in &instructions {
-                match inst {
-                    Union::A(_) => i += 1,
-                    _ => {}
-                }
-            } i
-        } + 1) * 3) as u16, 0)),
-        (0, (search_for_trait(&labels, "name", 0) as u16, 0)),
+        (0, (search_for_trait(&labels, "ram_size", 0) as u16, SrcLine::default())),
+        (1, (0, SrcLine::default())),  // the program size; filled in once everything's placed
+        (2, (search_for_trait(&labels, "name", 0) as u16, SrcLine::default())),
     ];
-    let mut raw_index = 0;
+    let mut rows = vec![listing::Row::Header(bytes.iter().map(|(_, (word, _))| *word).collect())];
+    let mut allocs: RamAllocs = vec![];
+    let mut raw_index = 3;  // just past the header, where the labels were counted from
     for instruction in instructions {
         match instruction {
-            Union::A((instruction, parameters, line_number)) => {
-                let mut whole_bytes = vec![instruction.op_code];
-                for i in 0..parameters.len() {
-                    match &instruction.params[i] {
-                        Param::Const16 => {
-                            match parameters[i] {
-                                Parameter::Constant(numeral) => {
-                                    whole_bytes.push((numeral & 0xFF) as u8);  // low byte
-                                    whole_bytes.push(((numeral & 0xFF00) >> 8) as u8);  // high byte
-                                },
-                                _ => { return Err(format!("Invalid parameter: {:?}", parameters[i])); }
-                            }
-                        },
-                        Param::Const8 => {
-                            whole_bytes.push(match parameters[i] {
-                                Parameter::Constant(numeral) => numeral as u8,
-                                _ => { return Err(format!("Invalid parameter: {:?}", parameters[i])); }
-                            })
-                        },
-                        Param::Addr32 => {
-                            match parameters[i] {
-                                Parameter::Address(numeral) => {
-                                    whole_bytes.push((numeral & 0xFF) as u8);  // low-low byte
-                                    whole_bytes.push(((numeral & 0xFF00) >> 8) as u8);  // low high byte
-                                    whole_bytes.push(((numeral & 0xFF0000) >> 16) as u8);  // high low byte
-                                    whole_bytes.push(((numeral & 0xFF000000) >> 24) as u8);  // high-high byte
-                                },
-                                _ => { return Err(format!("Invalid parameter: {:?}", parameters[i])); }
-                            }
-                        },
-                        Param::Addr16 => {
-                            match parameters[i] {
-                                Parameter::Address(numeral) => {
-                                    whole_bytes.push((numeral & 0xFF) as u8);  // low byte
-                                    whole_bytes.push(((numeral & 0xFF00) >> 8) as u8);  // high byte
-                                },
-                                _ => { return Err(format!("Invalid parameter: {:?}", parameters[i])); }
-                            }
-                        },
-                        Param::Reg => {
-                            whole_bytes.push(match parameters[i] {
-                                Parameter::Register(numeral) => numeral,
-                                _ => { return Err(format!("Invalid parameter: {:?}", parameters[i])); }
-                            })
-                        },
-                        Param::Ptr => {
-                            whole_bytes.push(match parameters[i] {
-                                Parameter::Pointer(numeral) => numeral,
-                                _ => { return Err(format!("Invalid parameter: {:?}", parameters[i])); }
-                            })
-                        },
-                    }
-                }
-                let mut count = 0;
-                while !whole_bytes.is_empty() {
-                    let low = whole_bytes.remove(0);
-                    let high = if whole_bytes.is_empty() { 0 } else { whole_bytes.remove(0) };
-                    bytes.push((raw_index, (((low as u16) << 8) | (high as u16), line_number)));
+            Union::A((instruction, parameters, line_number, refs)) => {
+                check_extern_refs(&refs, symbols)?;
+                let addr = raw_index;
+                let byte_pairs = encoding::pack_byte_pairs(&encoding::encode_instruction(&instruction, &parameters)?);
+                for byte_pair in &byte_pairs {
+                    bytes.push((raw_index, (*byte_pair, line_number)));
                     raw_index += 1;
-                    count += 1;
-                }
-                while count < 3 {
-                    bytes.push((raw_index, (0u16, line_number)));
-                    raw_index += 1;
-                    count += 1;
                 }
+                rows.push(listing::Row::Instruction { addr, words: byte_pairs, line: line_number, parameters });
             },
             Union::B(label) => {
                 match label {
-                    Label::Alloc(addr, byte_pairs) => {},
-                    Label::Variable(name, addr) => {},
-                    Label::Const(name, value) => {},
-                    Label::Header(name, program_addr) => {},
+                    Label::Alloc(addr, byte_pairs) => {
+                        // overlaps were already caught when parsing
+                        rows.push(listing::Row::Alloc(addr, byte_pairs.len()));
+                        allocs.push((addr, byte_pairs));
+                    },
+                    Label::Variable(..) => {},
+                    Label::Const(..) => {},
+                    Label::Header(name) => { rows.push(listing::Row::Label(name)); },
+                    Label::Data(byte_pairs, line_number, refs) => {
+                        check_extern_refs(&refs, symbols)?;
+                        bytes.extend(byte_pairs.iter().enumerate().map(|(index, byte_pair)| (raw_index + index, (*byte_pair, line_number))));
+                        rows.push(listing::Row::Data { addr: raw_index, words: byte_pairs.clone(), line: line_number });
+                        raw_index += byte_pairs.len();
+                    },
+                    Label::Align(boundary, line_number) => {
+                        while !raw_index.is_multiple_of(boundary) {
+                            bytes.push((raw_index, (0, line_number)));
+                            raw_index += 1;
+                        }
+                    },
                     Label::Trait(trait_name, byte_pair) => {
-                        match &*trait_name {
-                            "page" => {
-                                raw_index = byte_pair as usize;
-                            },
-                            _ => {}
+                        if trait_name == "page" {
+                            raw_index = byte_pair as usize;
+                            rows.push(listing::Row::Page(raw_index));
                         }
                     },
                 }
             },
         }
-    } Ok(bytes)
-}
-
-fn main() {
-    //let script = std::fs::read_to_string("scripts/test.cisc").unwrap();
-    let script = std::fs::read_to_string("scripts/boot.cisc").unwrap();
-    let mut original_script = script
-        .lines()
-        .map(|l| l.trim())
-        .collect::<Vec<&str>>();
-    original_script.iter_mut().for_each(|line| *line = line.split(";").collect::<Vec<&str>>()[0].trim());
-    println!("{:?}", original_script);
+    }
+    // unless given, the header and everything placed after it (counted the same as the linker does)
+    let program_size = search_for_trait(&labels, "program_size", bytes.len()) as u16;
+    bytes[1].1.0 = program_size;
+    if let Some(listing::Row::Header(words)) = rows.first_mut() { words[1] = program_size; }
+    Ok((bytes, allocs, rows))
+}
+
+// the disc addr each item of the program is placed at, counted the same as compile_script does (for the passes
+//    rewriting it)
+fn placed_addrs(items: &InstructionStream) -> Vec<usize> {
+    let mut addr: usize = 3;  // just past the header
+    items.iter().map(|item| {
+        let at = match item {
+            Union::B(Label::Align(boundary, _)) => addr.next_multiple_of(*boundary),
+            Union::B(Label::Trait(name, value)) if name == "page" => *value as usize,
+            _ => addr,
+        };
+        addr = at + match item {
+            Union::A(_) => encoding::INSTRUCTION_LEN,
+            Union::B(Label::Data(byte_pairs, ..)) => byte_pairs.len(),
+            _ => 0,
+        }; at
+    }).collect()
+}
+
+// like compile_script, but into a relocatable object; labels are placed relative to their section, with the
+//    address operands given relative to a label (or !extern) noted as relocations for the linker to patch
+fn compile_object(instructions: InstructionStream, symbols: &SymbolTable) -> Result<object::ObjectFile, Diagnostic> {
+    let mut object = object::ObjectFile { sections: vec![object::Section::default()], ..Default::default() };
+    let mut label_offsets = vec![];  // (name, section, byte pair offset)
+    let mut relocations = vec![];  // (section, byte offset, size, .word, symbol, addend); resolved once every label is placed
+    for item in instructions {
+        let section = object.sections.len() - 1;
+        match item {
+            Union::A((instruction, parameters, _line_number, refs)) => {
+                let byte_offset = object.sections[section].byte_pairs.len() * 2;
+                for (param_index, name, addend) in refs {
+                    let size = encoding::param_size(&instruction.params[param_index]);
+                    relocations.push((section, byte_offset + encoding::param_offset(instruction.params, param_index), size, false, name, addend));
+                }
+                object.sections[section].byte_pairs.extend(encoding::pack_byte_pairs(&encoding::encode_instruction(&instruction, &parameters)?));
+            },
+            Union::B(Label::Header(name)) => { label_offsets.push((name, section, object.sections[section].byte_pairs.len())); },
+            // every .page starts a section fixed at that disc addr
+            Union::B(Label::Trait(name, value)) if name == "page" => {
+                object.sections.push(object::Section { fixed: Some(value as usize), byte_pairs: vec![] });
+            },
+            Union::B(Label::Trait(name, value)) => { object.traits.push((name, value)); },
+            Union::B(Label::Alloc(addr, byte_pairs)) => { object.allocs.push((addr, byte_pairs)); },
+            Union::B(Label::Data(byte_pairs, _line_number, refs)) => {
+                let byte_offset = object.sections[section].byte_pairs.len() * 2;
+                for (index, name, addend) in refs {
+                    relocations.push((section, byte_offset + index * 2, 2, true, name, addend));
+                }
+                object.sections[section].byte_pairs.extend(byte_pairs);
+            },
+            // relative to where the section was assembled for (just past the header, for the relocatable one)
+            //    so it only holds once linked if the section is placed on the same boundary
+            Union::B(Label::Align(boundary, _line_number)) => {
+                let base = object.sections[section].fixed.unwrap_or(3);
+                let len = object.sections[section].byte_pairs.len();
+                object.sections[section].byte_pairs.resize((base + len).next_multiple_of(boundary) - base, 0);
+            },
+            Union::B(Label::Const(..) | Label::Variable(..)) => {},
+        }
+    }
+    for (section, offset, size, word, name, addend) in relocations {
+        // the addend is from the label's value, so it carries over to its placed offset
+        let (target, addend) = match label_offsets.iter().find(|(label, ..)| *label == name) {
+            Some((_, target_section, target_offset)) => (object::RelocTarget::Section(*target_section), *target_offset as i64 + addend),
+            None => (object::RelocTarget::Symbol(name), addend),
+        };
+        object.relocations.push(object::Relocation { section, offset, size, word, target, addend });
+    }
+    // the labels lowered from !if/!while are the object's own
+    object.exports = label_offsets.into_iter().filter(|(name, ..)| !symbols.is_hidden(name)).collect();
+    object.imports = symbols.iter().filter(|symbol| symbol.kind == SymbolKind::Extern).map(|symbol| symbol.name.clone()).collect();
+    Ok(object)
+}
+
+// reads and lexes a src file onto the end of the script, splicing its !include's in where they sit
+//    each file's labels go under its namespace (the root file's is empty), and a file is only ever included once
+fn load_script(path: &std::path::Path, namespace: String, include_chain: &mut Vec<(std::path::PathBuf, Option<Span>)>, files: &mut SrcFiles, sources: &mut SourceMap, script: &mut ScriptLines, diagnostics: &mut Vec<Diagnostic>) -> std::io::Result<()> {
+    let text = std::fs::read_to_string(path)?;
+    files.push((path.canonicalize()?, namespace));
+    load_source(path, text, include_chain, files, sources, script, diagnostics);
+    Ok(())
+}
+
+// the src of the file last pushed onto the files, given rather than read in (an editor's unsaved text, etc.);
+//    what it includes is still read in
+fn load_source(path: &std::path::Path, text: String, include_chain: &mut Vec<(std::path::PathBuf, Option<Span>)>, files: &mut SrcFiles, sources: &mut SourceMap, script: &mut ScriptLines, diagnostics: &mut Vec<Diagnostic>) {
+    let file = sources.add(path.display().to_string(), text.clone());
+    for (index, line) in text.lines().enumerate() {
+        let src_line = SrcLine { file, line: index };
+        let tokens = lexer::lex_line(line, file, index, diagnostics);
+        let tokens = tokens.into_iter().filter(|token| token.kind != TokenKind::Comment).collect::<Vec<Token>>();
+        if tokens.is_empty() { continue; }
+        if !tokens[0].is(TokenKind::Directive, "!include") {
+            script.push((tokens, src_line, vec![]));
+            continue;
+        }
+        
+        // !include "path" [as name]; the path is relative to this file, and the namespace defaults to the file's name
+        let at = Location::new(sources, files, src_line, &[]);
+        let target = match tokens.get(1) {
+            Some(token) if token.kind == TokenKind::String => match parse_string_literal(token, &at) {
+                Ok(chars) => path.parent().unwrap_or(std::path::Path::new("")).join(chars.into_iter().collect::<String>()),
+                Err(error) => { diagnostics.push(error); continue; },
+            },
+            _ => {
+                diagnostics.push(at.error(codes::SYNTAX, "expected the \"path\" of the file to include"));
+                continue;
+            },
+        };
+        let namespace = match &tokens[2..] {
+            [] => target.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().to_string()),
+            [keyword, name] if keyword.is(TokenKind::Ident, "as") && name.kind == TokenKind::Ident => name.text.clone(),
+            _ => {
+                diagnostics.push(at.error_at(codes::SYNTAX, &tokens[2..], "expected either nothing, or 'as name', after the included path"));
+                continue;
+            },
+        };
+        let include_span = at.tokens_span(&tokens[1..2]);
+        let canonical = match target.canonicalize() {
+            Ok(canonical) => canonical,
+            Err(error) => {
+                diagnostics.push(Diagnostic::error(codes::INCLUDE, format!("couldn't read '{}': {}", target.display(), error)).with_span(include_span));
+                continue;
+            },
+        };
+        if let Some(start) = include_chain.iter().position(|(path, _)| *path == canonical) {
+            let mut error = Diagnostic::error(codes::INCLUDE, format!("the !include of '{}' forms a cycle", target.display()))
+                .with_span(include_span);
+            for (path, span) in &include_chain[start + 1..] {
+                if let Some(span) = span {
+                    error = error.with_spanned_note(format!("'{}' was included from here", path.display()), span.clone());
+                }
+            }
+            diagnostics.push(error);
+            continue;
+        }
+        if files.iter().any(|(path, _)| *path == canonical) { continue; }  // already included
+        include_chain.push((canonical, Some(include_span.clone())));
+        if let Err(error) = load_script(&target, namespace, include_chain, files, sources, script, diagnostics) {
+            diagnostics.push(Diagnostic::error(codes::INCLUDE, format!("couldn't read '{}': {}", target.display(), error)).with_span(include_span));
+        }
+        include_chain.pop();
+    }
+}
+
+// the extra symbol maps given (--load-symbols=path), for the programs the emulated one loads, etc.
+fn load_symbol_maps(symbol_map: &mut SymbolMap, options: &cli::Options) -> Result<(), Diagnostic> {
+    for map_path in &options.load_symbols {
+        match std::fs::read_to_string(map_path).map_err(|error| error.to_string()).and_then(|text| SymbolMap::parse(&text)) {
+            Ok(map) => symbol_map.extend(map),
+            Err(error) => {
+                return Err(Diagnostic::error(codes::SYMBOL_MAP, format!("couldn't load the symbol map '{}': {}", map_path, error)));
+            },
+        }
+    } Ok(())
+}
+
+// emits the diagnostics (without the warnings, for --quiet); whether any were errors
+fn report(diagnostics: &[Diagnostic], sources: &SourceMap, options: &cli::Options) -> bool {
+    let shown = diagnostics.iter()
+        .filter(|diagnostic| diagnostic.is_error() || options.verbosity > cli::Verbosity::Quiet)
+        .cloned()
+        .collect::<Vec<Diagnostic>>();
+    diagnostics::emit(&shown, sources, options.message_format);
+    diagnostics.iter().any(Diagnostic::is_error)
+}
+
+fn write_output(path: &std::path::Path, contents: impl AsRef<[u8]>) -> Result<(), Diagnostic> {
+    std::fs::write(path, contents).map_err(|error| Diagnostic::error(codes::OUTPUT, format!("couldn't write '{}': {}", path.display(), error)))
+}
+
+// parses & lints the src (and what it includes), read in unless it's given; every diagnostic along the way is given back
+//    rather than reported, as the language server publishes them instead
+fn parse_source(path: &str, text: Option<String>, options: &cli::Options) -> (SourceMap, SrcFiles, Vec<Diagnostic>, Option<ParsedProgram>) {
+    let verbose = options.verbosity == cli::Verbosity::Verbose;
+    let mut sources = SourceMap::default();
+    let mut diagnostics = vec![];
+    let mut script = ScriptLines::new();
+    let mut files = SrcFiles::new();
+    let mut include_chain = vec![(std::path::Path::new(path).canonicalize().unwrap_or_default(), None)];
+    match text {
+        Some(text) => {
+            files.push((std::path::Path::new(path).canonicalize().unwrap_or_else(|_| std::path::PathBuf::from(path)), String::new()));
+            load_source(std::path::Path::new(path), text, &mut include_chain, &mut files, &mut sources, &mut script, &mut diagnostics);
+        },
+        None => if let Err(error) = load_script(std::path::Path::new(path), String::new(), &mut include_chain, &mut files, &mut sources, &mut script, &mut diagnostics) {
+            diagnostics.push(Diagnostic::error(codes::INCLUDE, format!("couldn't read '{}': {}", path, error)));
+        },
+    }
     
-    let mut script = script.lines().enumerate().map(|(index, e)| (vec![e], index)).collect::<Vec<(Vec<&str>, usize)>>();
-    for line in script.iter_mut() {
-        split_line(&mut line.0);
-        line.0.retain(|e| !e.is_empty() && !BLANK_CHARS.contains(e));
-        let mut commented = false;
-        line.0.retain(|s| { if *s == ";" { commented = true } !commented })
-    }
-    script.retain(|e| !e.0.is_empty() && !e.0[0].is_empty());
-    println!("Broken Tokens: \n{:?}", script);
-    let (instructions, labels) = match parse_sudo(script) {
-        Ok(instructions) => instructions,
-        Err(errors) => {
-            for error in errors {
-                println!("{}", error);
-            } return;
+    if verbose {
+        println!("Tokens: \n{:?}", script.iter().map(|(tokens, src_line, _)| {
+            (tokens.iter().map(|token| token.text.as_str()).collect::<Vec<&str>>(), src_line.file, src_line.line)
+        }).collect::<Vec<_>>());
+    }
+    let defines = command_line_defines(&options.defines, &mut sources, &mut diagnostics);
+    let parsed = parse_sudo(script, &sources, &files, defines, &mut diagnostics);
+    if let Some((instructions, _, symbols)) = &parsed {
+        diagnostics.extend(lint::check(instructions, symbols, &sources, &options.lints));
+    }
+    (sources, files, diagnostics, parsed)
+}
+
+// reads in and parses the src (and what it includes), reporting any diagnostics; None when there were errors
+fn parse_program(path: &str, options: &cli::Options) -> Option<(SourceMap, InstructionStream, Vec<Label>, SymbolTable)> {
+    let (sources, _, diagnostics, parsed) = parse_source(path, None, options);
+    if report(&diagnostics, &sources, options) { return None; }
+    let (mut instructions, labels, mut symbols) = parsed?;
+    if options.optimize { instructions = peephole::optimize(instructions, &mut symbols); }
+    if options.pic { pic::make_relative(&mut instructions, &symbols); }
+    if options.verbosity == cli::Verbosity::Verbose {
+        println!("Generated labels: {:?}", labels);
+        println!("Instructions: {:?}", instructions);
+    }
+    Some((sources, instructions, labels, symbols))
+}
+
+// parses and places the program on the disc; None when there were errors (already reported)
+fn assemble(path: &str, options: &cli::Options) -> Option<(SourceMap, SymbolTable, DiscWords, RamAllocs, Vec<listing::Row>)> {
+    let (sources, instructions, labels, symbols) = parse_program(path, options)?;
+    let (bytes, allocs, rows) = match compile_script(instructions, labels, &symbols) {
+        Ok(compiled) => compiled,
+        Err(error) => {
+            report(&[error], &sources, options);
+            return None;
         }
     };
-    println!("Instructions: {:?}", instructions);
-    let bytes = match compile_script(instructions, labels) {
-        Ok(bytes) => bytes,
+    if options.verbosity == cli::Verbosity::Verbose {
+        println!("Byte Pairs: {:0>4x?}", bytes);
+        println!("Ram Allocs: {:0>4x?}", allocs);
+    }
+    Some((sources, symbols, bytes, allocs, rows))
+}
+
+// the image the assembled program makes; with the src's lines (comments stripped) for the emulator to trace through
+fn assembled_image(sources: &SourceMap, bytes: &DiscWords, allocs: RamAllocs, debug_info: bool) -> image::DiscImage {
+    let mut disc_image = image::DiscImage::from_byte_pairs(bytes.iter().map(|(addr, (byte_pair, _))| (*addr, *byte_pair)));
+    disc_image.allocs = allocs;
+    if debug_info {
+        disc_image.debug = Some(image::DebugInfo {
+            lines: sources.lines().map(|line| line.split(';').next().unwrap_or("").trim().to_string()).collect(),
+            trace: bytes.iter().map(|(addr, (_, line))| (*addr, sources.global_line(line.file, line.line))).collect(),
+        });
+    } disc_image
+}
+
+// boots the emulator off of the image, tracing through its src when it carries it; the disc is saved back into the image once it stops
+fn emulate(disc_image: &mut image::DiscImage, symbol_map: &SymbolMap) -> Result<(), String> {
+    let mut emulation = Emulator::new(image::DISC_LEN, vec![0; u16::MAX as usize], vec![0; u16::MAX as usize], true);
+    emulation.load_image(disc_image)?;
+    emulation.boot(&disc_image.allocs);
+    let lines = disc_image.debug.as_ref().map_or(vec![String::new()], |debug| debug.lines.clone());
+    emulation.run(lines.iter().map(String::as_str).collect(), symbol_map);
+    emulation.save_image(disc_image);
+    Ok(())
+}
+
+// cisc fmt <in.cisc...> [--check]; rewrites the files formatted, or with --check only points out the ones that aren't
+fn fmt_command(options: &cli::Options) -> bool {
+    let mut sources = SourceMap::default();
+    let mut diagnostics = vec![];
+    for path in &options.inputs {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) => {
+                diagnostics.push(Diagnostic::error(codes::INCLUDE, format!("couldn't read '{}': {}", path, error)));
+                continue;
+            },
+        };
+        let formatted = formatter::format(&text);
+        if formatted == text { continue; }
+        if !options.check {
+            if let Err(error) = write_output(std::path::Path::new(path), formatted) { diagnostics.push(error); }
+            continue;
+        }
+        let file = sources.add(path.as_str(), text.clone());
+        let mut lines = text.lines().chain(std::iter::repeat("")).zip(formatted.lines().chain(std::iter::repeat("")));
+        let line = lines.position(|(line, formatted_line)| line != formatted_line).unwrap_or(0);
+        let formatted_line = formatted.lines().nth(line).unwrap_or("");
+        diagnostics.push(Diagnostic::error(codes::UNFORMATTED, format!("'{}' isn't formatted", path))
+            .with_span(sources.line_span(file, line))
+            .with_note(format!("formatted, the line would be: `{}`", formatted_line.trim_start())));
+    }
+    !report(&diagnostics, &sources, options)
+}
+
+// cisc cc <in.c> [-o out.cisc]; the c-like src compiled into assembly, for asm (or run) to take from there
+fn cc_command(options: &cli::Options) -> bool {
+    let path = &options.inputs[0];
+    let mut sources = SourceMap::default();
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
         Err(error) => {
-            println!("{}", error);
-            return;
+            return !report(&[Diagnostic::error(codes::INCLUDE, format!("couldn't read '{}': {}", path, error))], &sources, options);
+        },
+    };
+    let file = sources.add(path.as_str(), text.clone());
+    let output = options.output_or("cisc");
+    let written = compiler::compile(&text, file, path).and_then(|assembly| {
+        if std::path::Path::new(path) == output {
+            return Err(vec![Diagnostic::error(codes::OUTPUT, format!("the output would overwrite '{}'", path)).with_note("give another path with -o")]);
         }
+        write_output(&output, assembly).map_err(|error| vec![error])
+    });
+    match written {
+        Ok(()) => true,
+        Err(diagnostics) => !report(&diagnostics, &sources, options),
+    }
+}
+
+// cisc check <in.cisc>; everything short of writing the image
+fn check_command(options: &cli::Options) -> bool {
+    assemble(&options.inputs[0], options).is_some()
+}
+
+// cisc asm <in.cisc> [-o out.img]; the image (or object), plus the listing & symbol map next to it when asked for
+fn asm_command(options: &cli::Options) -> bool {
+    let path = &options.inputs[0];
+    if options.object {
+        let Some((sources, instructions, _, symbols)) = parse_program(path, options) else { return false; };
+        let object_path = options.output_or("obj");
+        let written = compile_object(instructions, &symbols).and_then(|object| write_output(&object_path, object.to_text()));
+        return written.map_err(|error| report(&[error], &sources, options)).is_ok();
+    }
+    let Some((sources, symbols, bytes, allocs, rows)) = assemble(path, options) else { return false; };
+    let image_path = options.output_or("img");
+    let mut diagnostics = vec![];
+    if let Err(error) = write_output(&image_path, assembled_image(&sources, &bytes, allocs, options.debug_info).to_bytes()) {
+        diagnostics.push(error);
+    }
+    if options.listing && let Err(error) = write_output(&image_path.with_extension("lst"), listing::render(&rows, &sources, &symbols)) {
+        diagnostics.push(error);
+    }
+    if options.symbols && let Err(error) = write_output(&image_path.with_extension("sym"), SymbolMap::from_table(&symbols, &sources).to_text()) {
+        diagnostics.push(error);
+    }
+    !report(&diagnostics, &sources, options)
+}
+
+// cisc run <in.img | in.cisc> [--save]; src is assembled first (with its debug info), and --save writes the disc back out once it stops
+fn run_command(options: &cli::Options) -> bool {
+    let path = &options.inputs[0];
+    let (sources, mut symbol_map, mut disc_image, image_path) = if path.ends_with(".cisc") {
+        let Some((sources, symbols, bytes, allocs, _)) = assemble(path, options) else { return false; };
+        // the emulator names addrs through the program's symbols, plus those of any other maps given (programs it'll load, etc.)
+        let symbol_map = SymbolMap::from_table(&symbols, &sources);
+        let disc_image = assembled_image(&sources, &bytes, allocs, true);
+        (sources, symbol_map, disc_image, options.output_or("img"))
+    } else {
+        let sources = SourceMap::default();
+        let disc_image = match std::fs::read(path).map_err(|error| error.to_string()).and_then(|data| image::DiscImage::from_bytes(&data)) {
+            Ok(disc_image) => disc_image,
+            Err(error) => { return !report(&[Diagnostic::error(codes::IMAGE, format!("couldn't read the disc image '{}': {}", path, error))], &sources, options); },
+        };
+        let image_path = options.output.as_ref().map_or_else(|| std::path::PathBuf::from(path), std::path::PathBuf::from);
+        (sources, SymbolMap::default(), disc_image, image_path)
+    };
+    if let Err(error) = load_symbol_maps(&mut symbol_map, options) { return !report(&[error], &sources, options); }
+    if options.verbosity == cli::Verbosity::Verbose && let Some(debug) = &disc_image.debug { println!("{:?}", debug.lines); }
+    if let Err(error) = emulate(&mut disc_image, &symbol_map) {
+        return !report(&[Diagnostic::error(codes::IMAGE, format!("couldn't load the disc image: {}", error))], &sources, options);
+    }
+    if options.save && let Err(error) = write_output(&image_path, disc_image.to_bytes()) {
+        return !report(&[error], &sources, options);
+    } true
+}
+
+// cisc disasm <in.img | in.obj>
+fn disasm_command(options: &cli::Options) -> bool {
+    let sources = SourceMap::default();
+    let fail = |error: Diagnostic| !report(&[error], &sources, options);
+    let path = &options.inputs[0];
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(error) => { return fail(Diagnostic::error(codes::IMAGE, format!("couldn't read '{}': {}", path, error))); },
     };
-    println!("Byte Pairs: {:0>4x?}", bytes);
+    let mut symbol_map = SymbolMap::default();
+    if let Err(error) = load_symbol_maps(&mut symbol_map, options) { return fail(error); }
     
-    let mut emulation = Emulator::new(vec![0; u16::MAX as usize], vec![0; u16::MAX as usize]);
-    for byte in bytes.into_iter() {
-        emulation.disc[byte.0] = byte.1.0;  // writing in the bytes
-        emulation.trace_disc[byte.0] = byte.1.1;
+    let mut text = format!("; disassembly of {}\n", path);
+    if data.starts_with(b"cisc-object") {
+        let object = match object::ObjectFile::parse(&String::from_utf8_lossy(&data)) {
+            Ok(object) => object,
+            Err(error) => { return fail(Diagnostic::error(codes::IMAGE, format!("couldn't read the object '{}': {}", path, error))); },
+        };
+        // the relocatable section is shown where it was assembled for (just past the header), matching its unrelocated addrs
+        let bases = object.sections.iter().map(|section| section.fixed.unwrap_or(3)).collect::<Vec<usize>>();
+        for (name, section, offset) in &object.exports {
+            symbol_map.push(symbol_map::MapSymbol { kind: SymbolKind::Label, name: name.clone(), value: (bases[*section] + offset) as i64, src: path.clone() });
+        }
+        for (index, section) in object.sections.iter().enumerate() {
+            match section.fixed {
+                Some(addr) => text.push_str(&format!("; section {}, fixed at {:04x}\n", index, addr)),
+                None => text.push_str(&format!("; section {}, relocatable (as assembled; the !extern's are still 0)\n", index)),
+            }
+            text.push_str(&disassembler::disassemble(&section.byte_pairs, bases[index], Some(&symbol_map)));
+        }
+    } else {
+        let disc_image = match image::DiscImage::from_bytes(&data) {
+            Ok(disc_image) => disc_image,
+            Err(error) => { return fail(Diagnostic::error(codes::IMAGE, format!("couldn't read the disc image '{}': {}", path, error))); },
+        };
+        let header = (0..3).map(|addr| {
+            disc_image.byte_pairs().find(|(at, _)| *at == addr).map_or(0, |(_, byte_pair)| byte_pair)
+        }).map(|byte_pair| format!("{:04x}", byte_pair)).collect::<Vec<String>>().join(" ");
+        text.push_str(&format!("0000  {:<14}  ; os header (ram size, program size, name)\n", header));
+        for (addr, byte_pairs) in &disc_image.allocs {
+            text.push_str(&format!("; !alloc ram {:04x}..{:04x} ({} byte pairs)\n", addr, addr + byte_pairs.len(), byte_pairs.len()));
+        }
+        // the header isn't code
+        for (start, byte_pairs) in &disc_image.extents {
+            let skipped = 3usize.saturating_sub(*start).min(byte_pairs.len());
+            if skipped == byte_pairs.len() { continue; }
+            text.push_str(&format!("; extent {:04x}..{:04x}\n", start + skipped, start + byte_pairs.len()));
+            text.push_str(&disassembler::disassemble(&byte_pairs[skipped..], start + skipped, Some(&symbol_map)));
+        }
+    }
+    print!("{}", text);
+    true
+}
+
+// cisc link a.obj kernel.obj@1600 ... [-o out.img]; links the objects (each optionally placed at a disc addr) into an image
+fn link_command(options: &cli::Options) -> bool {
+    let sources = SourceMap::default();
+    let mut inputs = vec![];
+    let mut diagnostics = vec![];
+    for arg in &options.inputs {
+        let (path, placement) = match arg.rsplit_once('@') {
+            Some((path, addr)) => match parse_literal(addr) {
+                Some(addr) => (path.to_string(), Some(addr as usize)),
+                None => {
+                    diagnostics.push(Diagnostic::error(codes::LINK, format!("invalid placement '{}' for '{}'", addr, path)));
+                    continue;
+                },
+            },
+            None => (arg.clone(), None),
+        };
+        match std::fs::read_to_string(&path).map_err(|error| error.to_string()).and_then(|text| object::ObjectFile::parse(&text)) {
+            Ok(object) => inputs.push(linker::LinkInput { path, object, placement }),
+            Err(error) => diagnostics.push(Diagnostic::error(codes::LINK, format!("couldn't read the object '{}': {}", path, error))),
+        }
+    }
+    if !diagnostics.is_empty() { return !report(&diagnostics, &sources, options); }
+    let linked = match linker::link(&inputs) {
+        Ok(linked) => linked,
+        Err(errors) => { return !report(&errors, &sources, options); },
+    };
+    if options.verbosity == cli::Verbosity::Verbose {
+        println!("Byte Pairs: {:0>4x?}", linked.byte_pairs);
+        println!("Ram Allocs: {:0>4x?}", linked.allocs);
     }
-    emulation.boot();
-    emulation.run(original_script);
+    // named after the first object (the one booted), unless given; kernel.obj@1600 -> kernel.img
+    let image_path = match &options.output {
+        Some(output) => std::path::PathBuf::from(output),
+        None => std::path::Path::new(inputs[0].path.as_str()).with_extension("img"),
+    };
+    let mut disc_image = image::DiscImage::from_byte_pairs(linked.byte_pairs.into_iter());
+    disc_image.allocs = linked.allocs;
+    if let Err(error) = write_output(&image_path, disc_image.to_bytes()) {
+        diagnostics.push(error);
+    }
+    if options.symbols && let Err(error) = write_output(&image_path.with_extension("sym"), linked.symbols.to_text()) {
+        diagnostics.push(error);
+    }
+    !report(&diagnostics, &sources, options)
+}
+
+// exits with 0 on success, 1 when the input had errors, and 2 for invalid arguments
+fn main() -> std::process::ExitCode {
+    let options = match cli::parse(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", cli::USAGE);
+            return std::process::ExitCode::SUCCESS;
+        },
+        Err(error) => {
+            eprintln!("error: {}\n\n{}", error, cli::USAGE);
+            return std::process::ExitCode::from(2);
+        },
+    };
+    let succeeded = match options.command {
+        cli::Command::Asm => asm_command(&options),
+        cli::Command::Run => run_command(&options),
+        cli::Command::Disasm => disasm_command(&options),
+        cli::Command::Check => check_command(&options),
+        cli::Command::Link => link_command(&options),
+        cli::Command::Lsp => lsp::serve(&options),
+        cli::Command::Fmt => fmt_command(&options),
+        cli::Command::Cc => cc_command(&options),
+    };
+    if succeeded { std::process::ExitCode::SUCCESS } else { std::process::ExitCode::FAILURE }
 }
 
 struct Emulator {
@@ -806,7 +2233,9 @@ struct Emulator {
     _io_handler: std::thread::JoinHandle<()>,
     trace_disc: Vec<usize>,  // the line number of the program
     trace_ram: Vec<usize>,  // the line number of the program
+    disc_end: usize,  // past the last disc addr loaded or written; all that save_image has to look through
     frame_buffer_ptr: std::sync::Arc<parking_lot::RwLock<usize>>,
+    interactive: bool,  // on a terminal; otherwise nothing's drawn, no keys are read, and debug builds don't step on keypresses
 }
 
 #[repr(u8)]
@@ -834,9 +2263,12 @@ enum Register {
 
 impl Emulator {
     pub fn new(
+        disc_len: usize,
         trace_disc: Vec<usize>,
         trace_ram: Vec<usize>,
+        interactive: bool,
     ) -> Self {
+        #[allow(clippy::unusual_byte_groupings)]  // grouped as the color channels
         let v_ram = std::sync::Arc::new(parking_lot::RwLock::new(vec![0b11000_00101_11010_0u16; const {   480 * 320 * 2   }]));
         let frame_buffer_ptr = std::sync::Arc::new(parking_lot::RwLock::new(0));
         let frame_buffer_ptr_clone = frame_buffer_ptr.clone();
@@ -844,6 +2276,7 @@ impl Emulator {
         let (kill_signal, kill_receiver) = crossbeam::channel::bounded(0);
         let (sender, display_v_blank) = crossbeam::channel::bounded(0);
         let display = std::thread::spawn(move || {
+            if !interactive { return; }
             #[cfg(debug_assertions)]
             {
                 println!("{}", "\n".repeat(50));
@@ -875,7 +2308,7 @@ impl Emulator {
                         let display_locked = v_ram_clone.read();
                         let r = ((display_locked[x + y * 480 + *frame_buffer_ptr_clone.read()] >> 10) & 0b11111) << 3;
                         let g = ((display_locked[x + y * 480 + *frame_buffer_ptr_clone.read()] >> 5 ) & 0b11111) << 3;
-                        let b = ((display_locked[x + y * 480 + *frame_buffer_ptr_clone.read()] >> 0 ) & 0b11111) << 3;
+                        let b = (display_locked[x + y * 480 + *frame_buffer_ptr_clone.read()] & 0b11111) << 3;
                         text.push_str(&format!("\x1b[{};{}H\x1B[48;2;{};{};{}m   \x1B[0m", y + 1, x * 3 + 1, r, g, b));
                     }
                 }
@@ -887,6 +2320,7 @@ impl Emulator {
         let io_in_ports_clone = io_in_ports.clone();
         let (kill_send, kill_recv) = crossbeam::channel::bounded(0);
         let _io_handler = std::thread::spawn(move || {
+            if !interactive { return; }
             crossterm::terminal::enable_raw_mode().unwrap();
             let mut stdin = std::io::stdin();
             loop {
@@ -908,7 +2342,7 @@ impl Emulator {
         Self {
             // display: 480 x 320   * 2 (active and back buffers)    24 htz
             v_ram,
-            disc     : vec![0u16; const { u32::MAX as usize }],
+            disc     : vec![0u16; disc_len],
             ram      : vec![0u16; const { u16::MAX as usize }],
             registers: vec![0u16; const {  u8::MAX as usize }],
             stack    : vec![0u16; const { u16::MAX as usize }],
@@ -921,16 +2355,64 @@ impl Emulator {
             _io_handler,
             trace_disc,
             trace_ram,
+            disc_end: 0,
             frame_buffer_ptr,
+            interactive,
+        }
+    }
+    
+    // places the image's extents on the disc, along with where each byte pair came from in its src (if it carries it)
+    //    the error names an extent that doesn't fit on the disc
+    pub fn load_image(&mut self, image: &image::DiscImage) -> Result<(), String> {
+        for (addr, byte_pairs) in &image.extents {
+            let Some(extent) = addr.checked_add(byte_pairs.len()).and_then(|end| self.disc.get_mut(*addr..end)) else {
+                return Err(format!("the extent at disc {:04x} runs past the end of the disc", addr));
+            };
+            extent.copy_from_slice(byte_pairs);
+            self.disc_end = self.disc_end.max(addr + byte_pairs.len());
+        }
+        for (addr, line) in image.debug.iter().flat_map(|debug| &debug.trace) {
+            if let Some(traced) = self.trace_disc.get_mut(*addr) { *traced = *line; }
+        } Ok(())
+    }
+    
+    // the disc (with whatever the guest wrote to it) back into the image; runs of more than 16 empty byte pairs split the extents
+    //    the image's allocs & src lines are kept as they were
+    pub fn save_image(&self, image: &mut image::DiscImage) {
+        let traced = image.debug.iter().flat_map(|debug| debug.trace.iter().map(|(addr, _)| *addr)).collect::<std::collections::HashSet<usize>>();
+        let mut extents: Vec<(usize, Vec<u16>)> = vec![];
+        let mut empty_run = 0;
+        for (addr, byte_pair) in self.disc[..self.disc_end].iter().enumerate() {
+            empty_run = if *byte_pair == 0 { empty_run + 1 } else { 0 };
+            match extents.last_mut() {
+                Some((start, byte_pairs)) if *start + byte_pairs.len() == addr && empty_run <= 16 => byte_pairs.push(*byte_pair),
+                _ if *byte_pair != 0 => extents.push((addr, vec![*byte_pair])),
+                _ => {},
+            }
+        }
+        for (_, byte_pairs) in &mut extents {
+            while byte_pairs.last() == Some(&0) { byte_pairs.pop(); }
         }
+        if let Some(debug) = &mut image.debug {
+            debug.trace = extents.iter()
+                .flat_map(|(start, byte_pairs)| *start..*start + byte_pairs.len())
+                .filter_map(|addr| self.trace_disc.get(addr).map(|line| (addr, *line)))
+                .filter(|(addr, line)| (*line != 0 || traced.contains(addr)) && *line < debug.lines.len())
+                .collect();
+        }
+        image.extents = extents;
     }
     
-    pub fn boot(&mut self) {
+    pub fn boot(&mut self, ram_allocs: &[(usize, Vec<u16>)]) {
         // reading in the first 256 byte pairs (512 bytes) into ram to begin the bootloader
         for booter_index in 0..256 {
             self.ram[booter_index] = self.disc[booter_index];
             self.trace_ram[booter_index] = self.trace_disc[booter_index];
         }
+        // the boot image's initialized ram (!alloc's) is placed at its absolute addresses
+        for (addr, byte_pairs) in ram_allocs {
+            self.ram[*addr..*addr + byte_pairs.len()].copy_from_slice(byte_pairs);
+        }
         // the program counter is being set to start just past the initial header
         //    (which would get ignored from booting; only necessary for os operated files, but still is present in all files)
         self.registers[const { Register::ProgramCounter as usize }] = 3;
@@ -938,45 +2420,47 @@ impl Emulator {
     }
     
     #[inline(always)]
-    fn get_protected_ram_offset(registers: &Vec<u16>) -> u16 {
+    fn get_protected_ram_offset(registers: &[u16]) -> u16 {
         (1 - registers[const { Register::Protected as usize }]) * registers[const { Register::RamFrameStart as usize }]
     }
     
     #[inline(always)]
-    fn get_protected_stack_offset(registers: &Vec<u16>) -> u16 {
+    fn get_protected_stack_offset(registers: &[u16]) -> u16 {
         (1 - registers[const { Register::Protected as usize }]) * registers[const { Register::StackFrameStart as usize }]
     }
     
     #[inline(always)]
-    fn get_protected_pgc_offset(registers: &Vec<u16>) -> u16 {
+    fn get_protected_pgc_offset(registers: &[u16]) -> u16 {
         (1 - registers[const { Register::Protected as usize }]) * registers[const { Register::ProgramStart as usize }]
     }
     
-    pub fn run(&mut self, original_code: Vec<&str>) {
+    pub fn run(&mut self, original_code: Vec<&str>, symbols: &SymbolMap) {
         let mut instruction_cycle_cost = [1u16; 256];
+        let mut instruction_params: [&[Param]; 256] = [&[]; 256];
         for instruction in INSTRUCTIONS {
             instruction_cycle_cost[instruction.op_code as usize] = instruction.cycle_cost as u16;
+            instruction_params[instruction.op_code as usize] = instruction.params;
         }
         
-        let mut stack_trace: Vec<usize> = vec![];
+        let mut stack_trace: Vec<(usize, usize)> = vec![];  // the src line and addr of each call
         let mut held_cycle_count = 0;  // the last recorded cycle count for timeout purposes
         
-        let mut edited_registers: Option<Vec<usize>> = None;
-        let mut read_registers: Option<Vec<usize>> = None;
-        let mut edited_ram: Option<Vec<usize>> = None;
-        let mut read_ram: Option<Vec<usize>> = None;
-        
         let emulation_start = std::time::Instant::now();
         let mut iterations = 0;
         'em_loop: loop {
             #[cfg(debug_assertions)]
             {
                 if self.kill_recv.try_recv().is_ok() { break; }
-                edited_registers = None;
-                read_registers = None;
-                edited_ram = None;
-                read_ram = None;
             }
+            // the highlighted registers/ram for the debug view (reset every step)
+            #[cfg(debug_assertions)]
+            let mut edited_registers: Option<Vec<usize>> = None;
+            #[cfg(debug_assertions)]
+            let mut read_registers: Option<Vec<usize>> = None;
+            #[cfg(debug_assertions)]
+            let mut edited_ram: Option<Vec<usize>> = None;
+            #[cfg(debug_assertions)]
+            let mut read_ram: Option<Vec<usize>> = None;
             iterations += 1;
             let pgc = self.registers[const { Register::ProgramCounter as usize }];
             let mut next_line = pgc + 3;
@@ -988,120 +2472,123 @@ impl Emulator {
             
             let op_code = bytes[0] >> 8;
             self.registers[const { Register::Cycles as usize }] += instruction_cycle_cost[op_code as usize];
+            // each operand read from where the op code's params place it
+            let operands = encoding::decode_operands(instruction_params[op_code as usize], &bytes);
+            let operand = |index: usize| operands[index] as u16;
             
             match op_code as u8 {
                 0b0000_0000 => {},  // Nop
                 0b0000_0001 => {
                     #[cfg(debug_assertions)]
                     {
-                        edited_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        edited_registers = Some(vec![operand(0) as usize]);
                     }
-                    self.registers[(bytes[0] & 0xFF) as usize] = (bytes[1] >> 8) | (bytes[1] << 8);
+                    self.registers[operand(0) as usize] = operand(1);
                 },  // Ldi
                 0b0000_0010 => {
                     #[cfg(debug_assertions)]
                     {
-                        edited_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[1] >> 8) as usize]);
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        edited_registers = Some(vec![operand(0) as usize, operand(1) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
-                    self.registers[(bytes[1] >> 8) as usize] = self.registers[(bytes[0] & 0xFF) as usize];
+                    self.registers[operand(1) as usize] = self.registers[operand(0) as usize];
                 },  // Mov
                 0b0000_0011 => {
                     #[cfg(debug_assertions)]
                     {
-                        edited_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[1] >> 8) as usize]);
+                        edited_registers = Some(vec![operand(0) as usize, operand(1) as usize]);
                     }
-                    (self.registers[(bytes[1] >> 8) as usize], self.registers[(bytes[0] & 0xFF) as usize]) = (self.registers[(bytes[0] & 0xFF) as usize], self.registers[(bytes[1] >> 8) as usize])
+                    (self.registers[operand(1) as usize], self.registers[operand(0) as usize]) = (self.registers[operand(0) as usize], self.registers[operand(1) as usize])
                 },  // Swp
                 0b0001_0000 => {
                     #[cfg(debug_assertions)]
                     {
-                        edited_ram = Some(vec![(((bytes[0] & 0xFF) | (bytes[1] & 0xFF00)) + Self::get_protected_ram_offset(&self.registers)) as usize]);
+                        edited_ram = Some(vec![(operand(0) + Self::get_protected_ram_offset(&self.registers)) as usize]);
                     }
-                    let index = ((bytes[0] & 0xFF) | (bytes[1] & 0xFF00));
+                    let index = operand(0);
                     if self.registers[const { Register::Protected as usize }] == 0 && index > self.registers[const { Register::RamSize as usize }] {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     }
-                    self.ram[(index + Self::get_protected_ram_offset(&self.registers)) as usize] = (bytes[1] & 0xFF) | (bytes[2] & 0xFF00);
+                    self.ram[(index + Self::get_protected_ram_offset(&self.registers)) as usize] = operand(1);
                 },  // LdiR
                 0b0001_0001 => {
                     #[cfg(debug_assertions)]
                     {
-                        edited_ram = Some(vec![(((bytes[0] & 0xFF) | (bytes[1] & 0xFF00)) + Self::get_protected_ram_offset(&self.registers)) as usize]);
-                        read_registers = Some(vec![(bytes[1] & 0xFF) as usize]);
+                        edited_ram = Some(vec![(operand(0) + Self::get_protected_ram_offset(&self.registers)) as usize]);
+                        read_registers = Some(vec![operand(1) as usize]);
                     }
-                    let index = ((bytes[0] & 0xFF) | (bytes[1] & 0xFF00));
+                    let index = operand(0);
                     if self.registers[const { Register::Protected as usize }] == 0 && index > self.registers[const { Register::RamSize as usize }] {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     }
-                    self.ram[(index + Self::get_protected_ram_offset(&self.registers)) as usize] = self.registers[(bytes[1] & 0xFF) as usize];
+                    self.ram[(index + Self::get_protected_ram_offset(&self.registers)) as usize] = self.registers[operand(1) as usize];
                 },  // Sto
                 0b0001_0010 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_ram = Some(vec![(((bytes[0] & 0xFF) | (bytes[1] & 0xFF00)) + Self::get_protected_ram_offset(&self.registers)) as usize]);
-                        edited_registers = Some(vec![(bytes[1] & 0xFF) as usize]);
+                        read_ram = Some(vec![(operand(0) + Self::get_protected_ram_offset(&self.registers)) as usize]);
+                        edited_registers = Some(vec![operand(1) as usize]);
                     }
-                    let index = ((bytes[1] & 0xFF) | (bytes[1] & 0xFF00));
+                    let index = operand(0);
                     if self.registers[const { Register::Protected as usize }] == 0 && index > self.registers[const { Register::RamSize as usize }] {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     }
-                    self.registers[(bytes[0] & 0xFF) as usize] = self.ram[(index + Self::get_protected_ram_offset(&self.registers)) as usize];
+                    self.registers[operand(1) as usize] = self.ram[(index + Self::get_protected_ram_offset(&self.registers)) as usize];
                 },  // Get
                 0b0001_0011 => {
                     #[cfg(debug_assertions)]
                     {
-                        edited_ram = Some(vec![(self.registers[(bytes[0] & 0xFF) as usize] + Self::get_protected_ram_offset(&self.registers)) as usize]);
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        edited_ram = Some(vec![(self.registers[operand(0) as usize] + Self::get_protected_ram_offset(&self.registers)) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
-                    let index = self.registers[(bytes[0] & 0xFF) as usize];
+                    let index = self.registers[operand(0) as usize];
                     if self.registers[const { Register::Protected as usize }] == 0 && index > self.registers[const { Register::RamSize as usize }] {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     }
-                    self.ram[(index + Self::get_protected_ram_offset(&self.registers)) as usize] = (bytes[1] >> 8) | (bytes[1] << 8);
+                    self.ram[(index + Self::get_protected_ram_offset(&self.registers)) as usize] = operand(1);
                 },  // LdiPtr
                 0b0001_0100 => {
                     #[cfg(debug_assertions)]
                     {
-                        edited_ram = Some(vec![(self.registers[(bytes[0] & 0xFF) as usize] + Self::get_protected_ram_offset(&self.registers)) as usize]);
-                        read_registers = Some(vec![(bytes[1] >> 8) as usize, (bytes[0] & 0xFF) as usize]);
+                        edited_ram = Some(vec![(self.registers[operand(0) as usize] + Self::get_protected_ram_offset(&self.registers)) as usize]);
+                        read_registers = Some(vec![operand(1) as usize, operand(0) as usize]);
                     }
-                    let index = self.registers[(bytes[0] & 0xFF) as usize];
+                    let index = self.registers[operand(0) as usize];
                     if self.registers[const { Register::Protected as usize }] == 0 && index > self.registers[const { Register::RamSize as usize }] {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     }
-                    self.ram[(index + Self::get_protected_ram_offset(&self.registers)) as usize] = self.registers[(bytes[1] >> 8) as usize];
+                    self.ram[(index + Self::get_protected_ram_offset(&self.registers)) as usize] = self.registers[operand(1) as usize];
                 },  // StoPtr
                 0b0001_0101 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_ram = Some(vec![(self.registers[(bytes[0] & 0xFF) as usize] + Self::get_protected_ram_offset(&self.registers)) as usize]);
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
-                        edited_registers = Some(vec![(bytes[1] >> 8) as usize]);
+                        read_ram = Some(vec![(self.registers[operand(0) as usize] + Self::get_protected_ram_offset(&self.registers)) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
+                        edited_registers = Some(vec![operand(1) as usize]);
                     }
-                    let index = self.registers[(bytes[0] & 0xFF) as usize];
+                    let index = self.registers[operand(0) as usize];
                     if self.registers[const { Register::Protected as usize }] == 0 && index > self.registers[const { Register::RamSize as usize }] {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     }
-                    self.registers[(bytes[1] >> 8) as usize] = self.ram[(index + Self::get_protected_ram_offset(&self.registers)) as usize];
+                    self.registers[operand(1) as usize] = self.ram[(index + Self::get_protected_ram_offset(&self.registers)) as usize];
                 },  // GetPtr
                 0b0001_0110 => {
                     let offset = Self::get_protected_ram_offset(&self.registers) as usize;
-                    let src_addr = ((bytes[0] & 0xFF) | (bytes[1] & 0xFF00)) as usize + offset;
-                    let dest_addr = ((bytes[1] & 0xFF) | (bytes[2] & 0xFF00)) as usize + offset;
-                    let slice_size = (bytes[2] & 0xFF) as usize;
+                    let src_addr = operand(0) as usize + offset;
+                    let dest_addr = operand(1) as usize + offset;
+                    let slice_size = operand(2) as usize;
                     #[cfg(debug_assertions)]
                     {
                         edited_ram = Some((dest_addr..dest_addr + slice_size).collect());
@@ -1116,9 +2603,9 @@ impl Emulator {
                 },  // MemCpy
                 0b0001_0111 => {
                     let offset = Self::get_protected_ram_offset(&self.registers) as usize;
-                    let src_addr = self.registers[(bytes[0] & 0xFF) as usize] as usize + offset;
-                    let dest_addr = self.registers[(bytes[1] >> 8) as usize] as usize + offset;
-                    let slice_size = self.registers[(bytes[1] & 0xFF) as usize] as usize;
+                    let src_addr = self.registers[operand(0) as usize] as usize + offset;
+                    let dest_addr = self.registers[operand(1) as usize] as usize + offset;
+                    let slice_size = self.registers[operand(2) as usize] as usize;
                     #[cfg(debug_assertions)]
                     {
                         edited_ram = Some((dest_addr..dest_addr + slice_size).collect());
@@ -1133,12 +2620,12 @@ impl Emulator {
                 },  // MemCpyPtr
                 0b0001_1000 => {
                     let offset = Self::get_protected_ram_offset(&self.registers) as usize;
-                    let src_addr = ((bytes[0] & 0xFF) | (bytes[1] & 0xFF00)) as usize + offset;
-                    let comp_addr = ((bytes[1] & 0xFF) | (bytes[2] & 0xFF00)) as usize + offset;
-                    let slice_size = (bytes[2] & 0xFF) as usize;
+                    let src_addr = operand(0) as usize + offset;
+                    let comp_addr = operand(1) as usize + offset;
+                    let slice_size = operand(2) as usize;
                     #[cfg(debug_assertions)]
                     {
-                        read_ram = Some(vec![(src_addr..src_addr + slice_size).collect::<Vec<usize>>(), (comp_addr..comp_addr + slice_size).collect::<Vec<usize>>()].concat());
+                        read_ram = Some([(src_addr..src_addr + slice_size).collect::<Vec<usize>>(), (comp_addr..comp_addr + slice_size).collect::<Vec<usize>>()].concat());
                     }
                     if self.registers[const { Register::Protected as usize }] == 0 && (src_addr + slice_size > self.registers[const { Register::RamSize as usize }] as usize || comp_addr + slice_size > self.registers[const { Register::RamSize as usize }] as usize) {
                         // calling the fault callback
@@ -1150,12 +2637,12 @@ impl Emulator {
                 },  // MemCmp
                 0b0001_1001 => {
                     let offset = Self::get_protected_ram_offset(&self.registers) as usize;
-                    let src_addr = self.registers[(bytes[0] & 0xFF) as usize] as usize + offset;
-                    let comp_addr = self.registers[(bytes[1] >> 8) as usize] as usize + offset;
-                    let slice_size = self.registers[(bytes[1] & 0xFF) as usize] as usize;
+                    let src_addr = self.registers[operand(0) as usize] as usize + offset;
+                    let comp_addr = self.registers[operand(1) as usize] as usize + offset;
+                    let slice_size = self.registers[operand(2) as usize] as usize;
                     #[cfg(debug_assertions)]
                     {
-                        read_ram = Some(vec![(src_addr..src_addr + slice_size).collect::<Vec<usize>>(), (comp_addr..comp_addr + slice_size).collect::<Vec<usize>>()].concat());
+                        read_ram = Some([(src_addr..src_addr + slice_size).collect::<Vec<usize>>(), (comp_addr..comp_addr + slice_size).collect::<Vec<usize>>()].concat());
                     }
                     if self.registers[const { Register::Protected as usize }] == 0 && (src_addr + slice_size > self.registers[const { Register::RamSize as usize }] as usize || comp_addr + slice_size > self.registers[const { Register::RamSize as usize }] as usize) {
                         // calling the fault callback
@@ -1166,7 +2653,7 @@ impl Emulator {
                     self.registers[const { Register::ConditionFlag as usize }] = comparison as u16;
                 },  // MemCmpPtr
                 0b0001_1010 => {
-                    let offset = (bytes[1] >> 8) | (bytes[1] << 8) + self.registers[(bytes[0] & 0xFF) as usize];
+                    let offset = operand(1) + self.registers[operand(0) as usize];
                     if self.registers[const { Register::Protected as usize }] == 0 && offset > self.registers[const { Register::RamSize as usize }] {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
@@ -1174,13 +2661,13 @@ impl Emulator {
                     }
                     #[cfg(debug_assertions)]
                     {
-                        edited_ram = Some(vec![(self.registers[(bytes[0] & 0xFF) as usize] + Self::get_protected_ram_offset(&self.registers) + offset) as usize]);
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[2] >> 8) as usize]);
+                        edited_ram = Some(vec![(self.registers[operand(0) as usize] + Self::get_protected_ram_offset(&self.registers) + offset) as usize]);
+                        read_registers = Some(vec![operand(0) as usize, operand(2) as usize]);
                     }
-                    self.ram[(Self::get_protected_ram_offset(&self.registers) + offset) as usize] = self.registers[(bytes[2] >> 8) as usize];
+                    self.ram[(Self::get_protected_ram_offset(&self.registers) + offset) as usize] = self.registers[operand(2) as usize];
                 },  // StoPtrOff
                 0b0001_1011 => {
-                    let offset = (bytes[1] >> 8) | (bytes[1] << 8) + self.registers[(bytes[0] & 0xFF) as usize];
+                    let offset = operand(1) + self.registers[operand(0) as usize];
                     if self.registers[const { Register::Protected as usize }] == 0 && offset > self.registers[const { Register::RamSize as usize }] {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
@@ -1188,15 +2675,15 @@ impl Emulator {
                     }
                     #[cfg(debug_assertions)]
                     {
-                        read_ram = Some(vec![(self.registers[(bytes[0] & 0xFF) as usize] + Self::get_protected_ram_offset(&self.registers) + offset) as usize]);
-                        edited_registers = Some(vec![(bytes[2] >> 8) as usize]);
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        read_ram = Some(vec![(self.registers[operand(0) as usize] + Self::get_protected_ram_offset(&self.registers) + offset) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
-                    self.registers[(bytes[2] >> 8) as usize] = self.ram[(Self::get_protected_ram_offset(&self.registers) + offset) as usize];
+                    self.registers[operand(2) as usize] = self.ram[(Self::get_protected_ram_offset(&self.registers) + offset) as usize];
                 },  // GetPtrOff
                 0b0001_1100 => {
-                    let start = self.registers[(bytes[0] & 0xFF) as usize] as usize + Self::get_protected_ram_offset(&self.registers) as usize;
-                    let size = ((bytes[1] >> 8) | (bytes[1] << 8)) as usize;
+                    let start = self.registers[operand(0) as usize] as usize + Self::get_protected_ram_offset(&self.registers) as usize;
+                    let size = operand(1) as usize;
                     if self.registers[const { Register::Protected as usize }] == 0 && start + size > self.registers[const { Register::RamSize as usize }] as usize {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
@@ -1206,72 +2693,72 @@ impl Emulator {
                     {
                         edited_ram = Some((start..start + size).collect());
                     }
-                    self.ram[start..start + size].fill((bytes[2] >> 8) | (bytes[2] << 8));
+                    self.ram[start..start + size].fill(operand(2));
                 },  // MemFill
                 0b0001_1101 => {
                     #[cfg(debug_assertions)]
                     {
                         edited_ram = Some(vec![]);
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[1] & 0xFF) as usize, (bytes[1] >> 8) as usize]);
+                        read_registers = Some(vec![operand(0) as usize, operand(2) as usize, operand(1) as usize]);
                     }
-                    let index = self.registers[(bytes[0] & 0xFF) as usize] + self.registers[(bytes[1] & 0xFF) as usize];
+                    let index = self.registers[operand(0) as usize] + self.registers[operand(2) as usize];
                     if self.registers[const { Register::Protected as usize }] == 0 && index > self.registers[const { Register::RamSize as usize }] {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     }
-                    self.ram[(index + Self::get_protected_ram_offset(&self.registers)) as usize] = self.registers[(bytes[1] >> 8) as usize];
+                    self.ram[(index + Self::get_protected_ram_offset(&self.registers)) as usize] = self.registers[operand(1) as usize];
                 },  // StoPtrOffPtr
                 0b0001_1110 => {
                     #[cfg(debug_assertions)]
                     {
                         read_ram = Some(vec![]);
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[1] & 0xFF) as usize]);
-                        edited_registers = Some(vec![(bytes[1] >> 8) as usize]);
+                        read_registers = Some(vec![operand(0) as usize, operand(2) as usize]);
+                        edited_registers = Some(vec![operand(1) as usize]);
                     }
-                    let index = self.registers[(bytes[0] & 0xFF) as usize] + self.registers[(bytes[1] & 0xFF) as usize];
+                    let index = self.registers[operand(0) as usize] + self.registers[operand(2) as usize];
                     if self.registers[const { Register::Protected as usize }] == 0 && index > self.registers[const { Register::RamSize as usize }] {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     }
-                    self.registers[(bytes[1] >> 8) as usize] = self.ram[(index + Self::get_protected_ram_offset(&self.registers)) as usize];
+                    self.registers[operand(1) as usize] = self.ram[(index + Self::get_protected_ram_offset(&self.registers)) as usize];
                 },  // GetPtrOffPtr
                 0b0001_1111 => {
                     #[cfg(debug_assertions)]
                     {
-                        edited_ram = Some(vec![((bytes[0] & 0xFF) | (bytes[1] & 0xFF00) + Self::get_protected_ram_offset(&self.registers)) as usize]);
-                        read_ram = Some(vec![((bytes[1] & 0xFF) | (bytes[2] & 0xFF00) + Self::get_protected_ram_offset(&self.registers)) as usize]);
+                        edited_ram = Some(vec![(operand(0) + Self::get_protected_ram_offset(&self.registers)) as usize]);
+                        read_ram = Some(vec![(operand(1) + Self::get_protected_ram_offset(&self.registers)) as usize]);
                     }
-                    let index = (bytes[0] & 0xFF) | (bytes[1] & 0xFF00);
+                    let index = operand(0);
                     if self.registers[const { Register::Protected as usize }] == 0 && index > self.registers[const { Register::RamSize as usize }] {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     }
-                    self.ram[(index + Self::get_protected_ram_offset(&self.registers)) as usize] = self.ram[((bytes[1] & 0xFF) | (bytes[2] & 0xFF00) + Self::get_protected_ram_offset(&self.registers)) as usize];
+                    self.ram[(index + Self::get_protected_ram_offset(&self.registers)) as usize] = self.ram[(operand(1) + Self::get_protected_ram_offset(&self.registers)) as usize];
                 },  // MovR
                 0b0010_0000 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
                     if self.registers[const { Register::Protected as usize }] == 0 {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
-                    } else { self.registers[const { Register::RamFrameStart as usize }] = self.registers[(bytes[0] & 0xFF) as usize]; }
+                    } else { self.registers[const { Register::RamFrameStart as usize }] = self.registers[operand(0) as usize]; }
                 },  // SetRamFrame
                 0b0010_0001 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
                     if self.registers[const { Register::Protected as usize }] == 0 {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
-                    } else { self.registers[const { Register::StackFrameStart as usize }] = self.registers[(bytes[0] & 0xFF) as usize]; }
+                    } else { self.registers[const { Register::StackFrameStart as usize }] = self.registers[operand(0) as usize]; }
                 },  // SetStackFrame
                 0b0010_0010 => {
                     if self.registers[const { Register::Protected as usize }] == 0 {
@@ -1279,7 +2766,7 @@ impl Emulator {
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     } else {
-                        let ptr = self.registers[(bytes[0] & 0xFF) as usize] as usize;
+                        let ptr = self.registers[operand(0) as usize] as usize;
                         #[cfg(debug_assertions)]
                         {
                             read_registers = Some((0..64).collect());
@@ -1295,7 +2782,7 @@ impl Emulator {
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     } else {
-                        let ptr = self.registers[(bytes[0] & 0xFF) as usize] as usize;
+                        let ptr = self.registers[operand(0) as usize] as usize;
                         #[cfg(debug_assertions)]
                         {
                             edited_registers = Some((0..64).collect());
@@ -1308,27 +2795,27 @@ impl Emulator {
                 0b0010_0100 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
                     if self.registers[const { Register::Protected as usize }] == 0 {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
-                    } else { self.registers[const { Register::TimeoutDuration as usize }] = self.registers[(bytes[0] & 0xFF) as usize]; }
+                    } else { self.registers[const { Register::TimeoutDuration as usize }] = self.registers[operand(0) as usize]; }
                 },  // SetTimeout
                 0b0010_0101 => {
                     if self.registers[const { Register::Protected as usize }] == 0 {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
-                    } else { self.registers[const { Register::TimeOutCallbackAddr as usize }] = (bytes[0] & 0xFF) | (bytes[1] & 0xFF00); }
+                    } else { self.registers[const { Register::TimeOutCallbackAddr as usize }] = operand(0); }
                 },  // SetTimeoutAdd
                 0b0010_0110 => {
                     if self.registers[const { Register::Protected as usize }] == 0 {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
-                    } else { self.registers[const { Register::InterruptCallbackAddr as usize }] = (bytes[0] & 0xFF) | (bytes[1] & 0xFF00); }
+                    } else { self.registers[const { Register::InterruptCallbackAddr as usize }] = operand(0); }
                 },  // SetIntAddr
                 0b0010_0111 => {
                     if self.registers[const { Register::Protected as usize }] == 0 {
@@ -1343,14 +2830,14 @@ impl Emulator {
                 0b0010_1000 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
                     if self.registers[const { Register::Protected as usize }] == 0 {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     } else {
-                        next_line = self.registers[(bytes[0] & 0xFF) as usize];  // +3 to skip the header
+                        next_line = self.registers[operand(0) as usize];  // +3 to skip the header
                         self.registers[const { Register::Protected as usize }] = 0;
                         held_cycle_count = self.registers[const { Register::Cycles as usize }];
                     }
@@ -1368,53 +2855,53 @@ impl Emulator {
                 0b0010_1010 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
                     if self.registers[const { Register::Protected as usize }] == 0 {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
-                    } else { self.registers[const { Register::ProgramStart as usize }] = self.registers[(bytes[0] & 0xFF) as usize]; }
+                    } else { self.registers[const { Register::ProgramStart as usize }] = self.registers[operand(0) as usize]; }
                 },  // SetPgrmStart
                 0b0010_1011 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
                     if self.registers[const { Register::Protected as usize }] == 0 {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
-                    } else { self.registers[const { Register::RamSize as usize }] = self.registers[(bytes[0] & 0xFF) as usize]; }
+                    } else { self.registers[const { Register::RamSize as usize }] = self.registers[operand(0) as usize]; }
                 },  // SetRamSize
                 0b0010_1100 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
                     if self.registers[const { Register::Protected as usize }] == 0 {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
-                    } else { self.registers[const { Register::ProgramSize as usize }] = self.registers[(bytes[0] & 0xFF) as usize]; }
+                    } else { self.registers[const { Register::ProgramSize as usize }] = self.registers[operand(0) as usize]; }
                 },  // SetPgrmSize
                 0b0010_1101 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
                     if self.registers[const { Register::Protected as usize }] == 0 {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
-                    } else { self.registers[const { Register::StackSize as usize }] = self.registers[(bytes[0] & 0xFF) as usize]; }
+                    } else { self.registers[const { Register::StackSize as usize }] = self.registers[operand(0) as usize]; }
                 },  // SetStackSize
                 0b0010_1110 => {
                     if self.registers[const { Register::Protected as usize }] == 0 {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
-                    } else { self.registers[const { Register::FaultCallbackAddr as usize }] = (bytes[0] & 0xFF) | (bytes[1] & 0xFF00); }
+                    } else { self.registers[const { Register::FaultCallbackAddr as usize }] = operand(0); }
                 },  // SetFaultAddr
                 0b0010_1111 => {
                     if self.registers[const { Register::Protected as usize }] == 0 {
@@ -1430,9 +2917,9 @@ impl Emulator {
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     } else {
                         let dead_zone = 153600 - *self.frame_buffer_ptr.read();  // copy to here
-                        let v_ram_base = ((bytes[1] & 0xFF) | (bytes[2] & 0xFF00)) as usize;
-                        let size = (bytes[2] & 0xFF) as usize;
-                        let ram_base = ((bytes[0] & 0xFF) | (bytes[1] & 0xFF00)) as usize;
+                        let v_ram_base = operand(1) as usize;
+                        let size = operand(2) as usize;
+                        let ram_base = operand(0) as usize;
                         #[cfg(debug_assertions)]
                         {
                             read_ram = Some((ram_base..ram_base + size).collect());
@@ -1448,12 +2935,12 @@ impl Emulator {
                     } else {
                         #[cfg(debug_assertions)]
                         {
-                            read_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[1] >> 8) as usize, (bytes[1] & 0xFF) as usize]);
+                            read_registers = Some(vec![operand(0) as usize, operand(1) as usize, operand(2) as usize]);
                         }
                         let dead_zone = 153600 - *self.frame_buffer_ptr.read();  // copy to here
-                        let c = self.registers[(bytes[0] & 0xFF) as usize];
-                        let x = self.registers[(bytes[1] >> 8) as usize] as usize;
-                        let y = self.registers[(bytes[1] & 0xFF) as usize] as usize;
+                        let c = self.registers[operand(0) as usize];
+                        let x = self.registers[operand(1) as usize] as usize;
+                        let y = self.registers[operand(2) as usize] as usize;
                         self.v_ram.write()[dead_zone + x + y * 480] = c;
                     }
                 },  // Plot
@@ -1464,12 +2951,12 @@ impl Emulator {
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     } else {
                         let dead_zone = 153600 - *self.frame_buffer_ptr.read();  // copy to here
-                        let v_ram_base = self.registers[(bytes[1] >> 8) as usize] as usize;
-                        let size = self.registers[(bytes[1] & 0xFF) as usize] as usize;
-                        let ram_base = self.registers[(bytes[0] & 0xFF) as usize] as usize;
+                        let v_ram_base = self.registers[operand(1) as usize] as usize;
+                        let size = self.registers[operand(2) as usize] as usize;
+                        let ram_base = self.registers[operand(0) as usize] as usize;
                         #[cfg(debug_assertions)]
                         {
-                            read_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[1] >> 8) as usize, (bytes[1] & 0xFF) as usize]);
+                            read_registers = Some(vec![operand(0) as usize, operand(1) as usize, operand(2) as usize]);
                             read_ram = Some((ram_base..ram_base + size).collect());
                         }
                         self.v_ram.write()[dead_zone + v_ram_base..dead_zone + v_ram_base + size].copy_from_slice(&self.ram[ram_base..ram_base + size]);
@@ -1501,9 +2988,9 @@ impl Emulator {
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     } else {
                         let active_zone = *self.frame_buffer_ptr.read();
-                        let x_pos = ((bytes[0] & 0xFF) | (bytes[1] & 0xFF00)) as usize;
-                        let y_pos = ((bytes[1] & 0xFF) | (bytes[2] & 0xFF00)) as usize;
-                        self.registers[(bytes[2] & 0xFF) as usize] = self.v_ram.read()[active_zone + x_pos + y_pos * 480];
+                        let x_pos = operand(0) as usize;
+                        let y_pos = operand(1) as usize;
+                        self.registers[operand(2) as usize] = self.v_ram.read()[active_zone + x_pos + y_pos * 480];
                     }
                 },  // ColorAt
                 0b0011_0110 => {
@@ -1513,14 +3000,14 @@ impl Emulator {
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     } else {
                         let active_zone = *self.frame_buffer_ptr.read();
-                        let x_pos = self.registers[(bytes[0] & 0xFF) as usize] as usize;
-                        let y_pos = self.registers[(bytes[1] >> 8) as usize] as usize;
+                        let x_pos = self.registers[operand(0) as usize] as usize;
+                        let y_pos = self.registers[operand(1) as usize] as usize;
                         #[cfg(debug_assertions)]
                         {
-                            read_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[1] >> 8) as usize]);
-                            edited_registers = Some(vec![(bytes[1] & 0xFF) as usize]);
+                            read_registers = Some(vec![operand(0) as usize, operand(1) as usize]);
+                            edited_registers = Some(vec![operand(2) as usize]);
                         }
-                        self.registers[(bytes[1] & 0xFF) as usize] = self.v_ram.read()[active_zone + x_pos + y_pos * 480];
+                        self.registers[operand(2) as usize] = self.v_ram.read()[active_zone + x_pos + y_pos * 480];
                     }
                 },  // ColorPtr
                 0b0011_0111 => {
@@ -1530,11 +3017,11 @@ impl Emulator {
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     } else {
                         let dead_zone = 153600 - *self.frame_buffer_ptr.read();
-                        let ram_pos = self.registers[(bytes[0] & 0xFF) as usize] as usize;
-                        let x_pos = self.registers[(bytes[1] >> 8) as usize] as usize;
-                        let y_pos = self.registers[(bytes[1] & 0xFF) as usize] as usize;
-                        let sprite_size_x = self.registers[(bytes[2] >> 8) as usize] as usize;
-                        let sprite_size_y = self.registers[(bytes[2] & 0xFF) as usize] as usize;
+                        let ram_pos = self.registers[operand(0) as usize] as usize;
+                        let x_pos = self.registers[operand(1) as usize] as usize;
+                        let y_pos = self.registers[operand(2) as usize] as usize;
+                        let sprite_size_x = self.registers[operand(3) as usize] as usize;
+                        let sprite_size_y = self.registers[operand(4) as usize] as usize;
                         let mut guard = self.v_ram.write();
                         for y in y_pos..y_pos + sprite_size_y {
                             for x in x_pos..x_pos + sprite_size_x {
@@ -1560,14 +3047,14 @@ impl Emulator {
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     } else {
                         let dead_zone = 153600 - *self.frame_buffer_ptr.read();
-                        let x_pos = self.registers[(bytes[0] & 0xFF) as usize] as usize;
-                        let y_pos = self.registers[(bytes[1] >> 8) as usize] as usize;
-                        let size_x = self.registers[(bytes[1] & 0xFF) as usize] as usize;
-                        let size_y = self.registers[(bytes[2] >> 8) as usize] as usize;
-                        let color = self.registers[(bytes[2] & 0xFF) as usize];
+                        let x_pos = self.registers[operand(0) as usize] as usize;
+                        let y_pos = self.registers[operand(1) as usize] as usize;
+                        let size_x = self.registers[operand(2) as usize] as usize;
+                        let size_y = self.registers[operand(3) as usize] as usize;
+                        let color = self.registers[operand(4) as usize];
                         #[cfg(debug_assertions)]
                         {
-                            read_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[1] >> 8) as usize, (bytes[1] & 0xFF) as usize, (bytes[2] >> 8) as usize, (bytes[2] & 0xFF) as usize])
+                            read_registers = Some(vec![operand(0) as usize, operand(1) as usize, operand(2) as usize, operand(3) as usize, operand(4) as usize])
                         }
                         let mut guard = self.v_ram.write();
                         for y in y_pos..y_pos + size_y {
@@ -1580,273 +3067,281 @@ impl Emulator {
                 0b0100_0000 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[1] >> 8) as usize]);
+                        read_registers = Some(vec![operand(0) as usize, operand(1) as usize]);
                     }
-                    self.registers[(bytes[1] & 0xFF) as usize] = self.registers[(bytes[0] & 0xFF) as usize] + self.registers[(bytes[1] >> 8) as usize];
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize] + self.registers[operand(1) as usize];
                 },  // Add
                 0b0100_0001 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[1] >> 8) as usize]);
-                        edited_registers = Some(vec![(bytes[1] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize, operand(1) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[1] & 0xFF) as usize] = self.registers[(bytes[0] & 0xFF) as usize] - self.registers[(bytes[1] >> 8) as usize];
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize] - self.registers[operand(1) as usize];
                 },  // Sub
                 0b0100_0010 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[1] >> 8) as usize]);
-                        edited_registers = Some(vec![(bytes[1] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize, operand(1) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[1] & 0xFF) as usize] = self.registers[(bytes[0] & 0xFF) as usize] - self.registers[(bytes[1] >> 8) as usize];
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize] - self.registers[operand(1) as usize];
                 },  // SubRev
                 0b0100_0011 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[1] >> 8) as usize]);
-                        edited_registers = Some(vec![(bytes[1] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize, operand(1) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[1] & 0xFF) as usize] = self.registers[(bytes[0] & 0xFF) as usize] * self.registers[(bytes[1] >> 8) as usize];
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize] * self.registers[operand(1) as usize];
                 },  // Mul
                 0b0100_0100 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[1] >> 8) as usize]);
-                        edited_registers = Some(vec![(bytes[1] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize, operand(1) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[1] & 0xFF) as usize] = self.registers[(bytes[0] & 0xFF) as usize] / self.registers[(bytes[1] >> 8) as usize];
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize] / self.registers[operand(1) as usize];
                 },  // Div
                 0b0100_0101 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[1] >> 8) as usize]);
-                        edited_registers = Some(vec![(bytes[1] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize, operand(1) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[1] & 0xFF) as usize] = self.registers[(bytes[0] & 0xFF) as usize] % self.registers[(bytes[1] >> 8) as usize];
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize] % self.registers[operand(1) as usize];
                 },  // Mod
                 0b0100_0110 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[1] >> 8) as usize]);
-                        edited_registers = Some(vec![(bytes[1] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize, operand(1) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[1] & 0xFF) as usize] = self.registers[(bytes[0] & 0xFF) as usize] & self.registers[(bytes[1] >> 8) as usize];
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize] & self.registers[operand(1) as usize];
                 },  // And
                 0b0100_0111 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[1] >> 8) as usize]);
-                        edited_registers = Some(vec![(bytes[1] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize, operand(1) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[1] & 0xFF) as usize] = self.registers[(bytes[0] & 0xFF) as usize] | self.registers[(bytes[1] >> 8) as usize];
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize] | self.registers[operand(1) as usize];
                 },  // Or
                 0b0100_1000 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
-                        edited_registers = Some(vec![(bytes[1] >> 8) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
+                        edited_registers = Some(vec![operand(1) as usize]);
                     }
-                    self.registers[(bytes[1] >> 8) as usize] = !self.registers[(bytes[0] & 0xFF) as usize];
+                    self.registers[operand(1) as usize] = !self.registers[operand(0) as usize];
                 },  // Not
                 0b0100_1001 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[1] >> 8) as usize]);
-                        edited_registers = Some(vec![(bytes[1] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize, operand(1) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[1] & 0xFF) as usize] = self.registers[(bytes[0] & 0xFF) as usize] ^ self.registers[(bytes[1] >> 8) as usize];
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize] ^ self.registers[operand(1) as usize];
                 },  // Xor
                 0b0100_1010 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[1] >> 8) as usize]);
-                        edited_registers = Some(vec![(bytes[1] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize, operand(1) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[1] & 0xFF) as usize] = self.registers[(bytes[0] & 0xFF) as usize].pow(self.registers[(bytes[1] >> 8) as usize] as u32);
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize].pow(self.registers[operand(1) as usize] as u32);
                 },  // Pow
                 0b0100_1011 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[1] >> 8) as usize]);
-                        edited_registers = Some(vec![(bytes[1] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize, operand(1) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[1] & 0xFF) as usize] = self.registers[(bytes[0] & 0xFF) as usize] << self.registers[(bytes[1] >> 8) as usize];
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize] << self.registers[operand(1) as usize];
                 },  // Left
                 0b0100_1100 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[1] >> 8) as usize]);
-                        edited_registers = Some(vec![(bytes[1] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize, operand(1) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[1] & 0xFF) as usize] = self.registers[(bytes[0] & 0xFF) as usize] >> self.registers[(bytes[1] >> 8) as usize];
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize] >> self.registers[operand(1) as usize];
                 },  // Right
                 0b0100_1101 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[1] >> 8) as usize]);
-                        edited_registers = Some(vec![(bytes[1] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize, operand(1) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[1] & 0xFF) as usize] = self.registers[(bytes[0] & 0xFF) as usize].rotate_left(self.registers[(bytes[1] >> 8) as usize] as u32);
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize].rotate_left(self.registers[operand(1) as usize] as u32);
                 },  // RotLeft
                 0b0100_1110 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[1] >> 8) as usize]);
-                        edited_registers = Some(vec![(bytes[1] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize, operand(1) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[1] & 0xFF) as usize] = self.registers[(bytes[0] & 0xFF) as usize].rotate_right(self.registers[(bytes[1] >> 8) as usize] as u32);
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize].rotate_right(self.registers[operand(1) as usize] as u32);
                 },  // RotRight
                 0b0101_0000 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
-                        edited_registers = Some(vec![(bytes[2] >> 8) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[2] >> 8) as usize] = self.registers[(bytes[0] & 0xFF) as usize] + ((bytes[1] >> 8) | (bytes[1] << 8));
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize] + operand(1);
                 },  // AddImm
                 0b0101_0001 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
-                        edited_registers = Some(vec![(bytes[2] >> 8) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[2] >> 8) as usize] = self.registers[(bytes[0] & 0xFF) as usize] - ((bytes[1] >> 8) | (bytes[1] << 8));
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize] - operand(1);
                 },  // SubImm
                 0b0101_0010 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
-                        edited_registers = Some(vec![(bytes[2] >> 8) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[2] >> 8) as usize] = ((bytes[1] >> 8) | (bytes[1] << 8)) - self.registers[(bytes[0] & 0xFF) as usize];
+                    self.registers[operand(2) as usize] = operand(1) - self.registers[operand(0) as usize];
                 },  // SubRevImm
                 0b0101_0011 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
-                        edited_registers = Some(vec![(bytes[2] >> 8) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[2] >> 8) as usize] = self.registers[(bytes[0] & 0xFF) as usize] * ((bytes[1] >> 8) | (bytes[1] << 8));
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize] * operand(1);
                 },  // MulImm
                 0b0101_0100 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
-                        edited_registers = Some(vec![(bytes[2] >> 8) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[2] >> 8) as usize] = self.registers[(bytes[0] & 0xFF) as usize] / ((bytes[1] >> 8) | (bytes[1] << 8));
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize] / operand(1);
                 },  // DivImm
                 0b0101_0101 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
-                        edited_registers = Some(vec![(bytes[2] >> 8) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[2] >> 8) as usize] = self.registers[(bytes[0] & 0xFF) as usize] % ((bytes[1] >> 8) | (bytes[1] << 8));
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize] % operand(1);
                 },  // ModImm
                 0b0101_0110 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
-                        edited_registers = Some(vec![(bytes[2] >> 8) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[2] >> 8) as usize] = self.registers[(bytes[0] & 0xFF) as usize] & ((bytes[1] >> 8) | (bytes[1] << 8));
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize] & operand(1);
                 },  // AndImm
                 0b0101_0111 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
-                        edited_registers = Some(vec![(bytes[2] >> 8) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[2] >> 8) as usize] = self.registers[(bytes[0] & 0xFF) as usize] | ((bytes[1] >> 8) | (bytes[1] << 8));
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize] | operand(1);
                 },  // OrImm
                 0b0101_1001 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
-                        edited_registers = Some(vec![(bytes[2] >> 8) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[2] >> 8) as usize] = self.registers[(bytes[0] & 0xFF) as usize].pow(((bytes[1] >> 8) | (bytes[1] << 8)) as u32);
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize] ^ operand(1);
                 },  // XorImm
                 0b0101_1010 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
-                        edited_registers = Some(vec![(bytes[2] >> 8) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[2] >> 8) as usize] = self.registers[(bytes[0] & 0xFF) as usize] << ((bytes[1] >> 8) | (bytes[1] << 8));
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize].pow(operand(1) as u32);
                 },  // PowImm
                 0b0101_1011 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
-                        edited_registers = Some(vec![(bytes[2] >> 8) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[2] >> 8) as usize] = self.registers[(bytes[0] & 0xFF) as usize] >> ((bytes[1] >> 8) | (bytes[1] << 8));
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize] << operand(1);
                 },  // LeftImm
                 0b0101_1100 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
-                        edited_registers = Some(vec![(bytes[2] >> 8) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[2] >> 8) as usize] = self.registers[(bytes[0] & 0xFF) as usize].rotate_left(((bytes[1] >> 8) | (bytes[1] << 8)) as u32);
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize] >> operand(1);
                 },  // RightImm
                 0b0101_1101 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
-                        edited_registers = Some(vec![(bytes[2] >> 8) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
                     }
-                    self.registers[(bytes[2] >> 8) as usize] = self.registers[(bytes[0] & 0xFF) as usize].rotate_right(((bytes[1] >> 8) | (bytes[1] << 8)) as u32);
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize].rotate_left(operand(1) as u32);
                 },  // RotLeftImm
                 0b0101_1110 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[1] >> 8) as usize]);
-                        edited_registers = Some(vec![(bytes[1] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
+                        edited_registers = Some(vec![operand(2) as usize]);
+                    }
+                    self.registers[operand(2) as usize] = self.registers[operand(0) as usize].rotate_right(operand(1) as u32);
+                },  // RotRightImm
+                0b0110_0000 => {
+                    #[cfg(debug_assertions)]
+                    {
+                        read_registers = Some(vec![operand(0) as usize, operand(1) as usize]);
+                        edited_registers = Some(vec![const { Register::ConditionFlag as usize }]);
                     }
-                    self.registers[const { Register::ConditionFlag as usize }] = (self.registers[(bytes[0] & 0xFF) as usize] < self.registers[(bytes[1] >> 8) as usize]) as u16;
+                    self.registers[const { Register::ConditionFlag as usize }] = (self.registers[operand(0) as usize] < self.registers[operand(1) as usize]) as u16;
                 },  // Less
                 0b0110_0001 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[1] >> 8) as usize]);
-                        edited_registers = Some(vec![(bytes[1] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize, operand(1) as usize]);
+                        edited_registers = Some(vec![const { Register::ConditionFlag as usize }]);
                     }
-                    self.registers[const { Register::ConditionFlag as usize }] = (self.registers[(bytes[0] & 0xFF) as usize] > self.registers[(bytes[1] >> 8) as usize]) as u16;
+                    self.registers[const { Register::ConditionFlag as usize }] = (self.registers[operand(0) as usize] > self.registers[operand(1) as usize]) as u16;
                 },  // Grtr
                 0b0110_0010 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize, (bytes[1] >> 8) as usize]);
-                        edited_registers = Some(vec![(bytes[1] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize, operand(1) as usize]);
+                        edited_registers = Some(vec![const { Register::ConditionFlag as usize }]);
                     }
-                    self.registers[const { Register::ConditionFlag as usize }] = (self.registers[(bytes[0] & 0xFF) as usize] == self.registers[(bytes[1] >> 8) as usize]) as u16;
+                    self.registers[const { Register::ConditionFlag as usize }] = (self.registers[operand(0) as usize] == self.registers[operand(1) as usize]) as u16;
                 },  // Eq
                 0b0110_0011 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
-                        edited_registers = Some(vec![(bytes[2] >> 8) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
+                        edited_registers = Some(vec![const { Register::ConditionFlag as usize }]);
                     }
-                    self.registers[const { Register::ConditionFlag as usize }] = (self.registers[(bytes[0] & 0xFF) as usize] < ((bytes[1] >> 8) | (bytes[1] << 8))) as u16;
+                    self.registers[const { Register::ConditionFlag as usize }] = (self.registers[operand(0) as usize] < operand(1)) as u16;
                 },  // LessImm
                 0b0110_0100 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
-                        edited_registers = Some(vec![(bytes[2] >> 8) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
+                        edited_registers = Some(vec![const { Register::ConditionFlag as usize }]);
                     }
-                    self.registers[const { Register::ConditionFlag as usize }] = (self.registers[(bytes[0] & 0xFF) as usize] > ((bytes[1] >> 8) | (bytes[1] << 8))) as u16;
+                    self.registers[const { Register::ConditionFlag as usize }] = (self.registers[operand(0) as usize] > operand(1)) as u16;
                 },  // GrtrImm
                 0b0110_0101 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
-                        edited_registers = Some(vec![(bytes[2] >> 8) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
+                        edited_registers = Some(vec![const { Register::ConditionFlag as usize }]);
                     }
-                    self.registers[const { Register::ConditionFlag as usize }] = (self.registers[(bytes[0] & 0xFF) as usize] == ((bytes[1] >> 8) | (bytes[1] << 8))) as u16;
+                    self.registers[const { Register::ConditionFlag as usize }] = (self.registers[operand(0) as usize] == operand(1)) as u16;
                 },  // EqImm
                 0b0110_0110 => {
                     self.registers[const { Register::ConditionFlag as usize }] = 0;
@@ -1857,28 +3352,28 @@ impl Emulator {
                 0b0110_0111 => {
                     #[cfg(debug_assertions)]
                     {
-                        edited_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        edited_registers = Some(vec![operand(0) as usize]);
                     }
                     let value =
                         self.registers[const { Register::ConditionFlag as usize }] |
                         (self.registers[const { Register::FaultFlag as usize }] << 3) |
                         (self.registers[const { Register::ZeroFlag as usize }] << 1) |
                         (self.registers[const { Register::OverflowFlag as usize }] << 2);
-                    self.registers[(bytes[0] & 0xFF) as usize] = value;
+                    self.registers[operand(0) as usize] = value;
                 },  // SaveFlags
                 0b0110_1000 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
-                    self.registers[const { Register::ConditionFlag as usize }] = (self.registers[(bytes[0] & 0xFF) as usize] == 0) as u16;
+                    self.registers[const { Register::ConditionFlag as usize }] = (self.registers[operand(0) as usize] == 0) as u16;
                 },  // Zero
                 0b0110_1001 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
-                    let value = self.registers[(bytes[0] & 0xFF) as usize];
+                    let value = self.registers[operand(0) as usize];
                     self.registers[const { Register::ConditionFlag as usize }] = value & 0b1;
                     self.registers[const { Register::FaultFlag as usize }] = (value >> 3) & 0b1;
                     self.registers[const { Register::ZeroFlag as usize }] = (value >> 1) & 0b1;
@@ -1886,103 +3381,103 @@ impl Emulator {
                 },  // loadFlags
                 
                 0b0111_0000 => {
-                    next_line = (bytes[0] & 0xFF) | (bytes[1] & 0xFF00) + Self::get_protected_pgc_offset(&self.registers);
+                    next_line = operand(0) + Self::get_protected_pgc_offset(&self.registers);
                 },  // Jmp
                 0b0111_0001 => {
                     if self.registers[const { Register::ConditionFlag as usize }] > 0 {
-                        next_line = (bytes[0] & 0xFF) | (bytes[1] & 0xFF00) + Self::get_protected_pgc_offset(&self.registers);
+                        next_line = operand(0) + Self::get_protected_pgc_offset(&self.registers);
                     }
                 },  // Jic
                 0b0111_0010 => {
                     if self.registers[const { Register::ConditionFlag as usize }] == 0 {
-                        next_line = (bytes[0] & 0xFF) | (bytes[1] & 0xFF00) + Self::get_protected_pgc_offset(&self.registers);
+                        next_line = operand(0) + Self::get_protected_pgc_offset(&self.registers);
                     }
                 },  // Jnc
                 0b0111_0011 => {
                     if self.registers[const { Register::ZeroFlag as usize }] > 0 {
-                        next_line = (bytes[0] & 0xFF) | (bytes[1] & 0xFF00) + Self::get_protected_pgc_offset(&self.registers);
+                        next_line = operand(0) + Self::get_protected_pgc_offset(&self.registers);
                     }
                 },  // Jiz
                 0b0111_0100 => {
                     if self.registers[const { Register::ZeroFlag as usize }] == 0 {
-                        next_line = (bytes[0] & 0xFF) | (bytes[1] & 0xFF00) + Self::get_protected_pgc_offset(&self.registers);
+                        next_line = operand(0) + Self::get_protected_pgc_offset(&self.registers);
                     }
                 },  // Jnz
                 0b0111_0101 => {
                     if self.registers[const { Register::FaultFlag as usize }] > 0 {
-                        next_line = (bytes[0] & 0xFF) | (bytes[1] & 0xFF00) + Self::get_protected_pgc_offset(&self.registers);
+                        next_line = operand(0) + Self::get_protected_pgc_offset(&self.registers);
                     }
                 },  // JiErr
                 0b0111_0110 => {
                     if self.registers[const { Register::FaultFlag as usize }] == 0 {
-                        next_line = (bytes[0] & 0xFF) | (bytes[1] & 0xFF00) + Self::get_protected_pgc_offset(&self.registers);
+                        next_line = operand(0) + Self::get_protected_pgc_offset(&self.registers);
                     }
                 },  // JnErr
                 0b0111_0111 => {
                     if self.registers[const { Register::OverflowFlag as usize }] > 0 {
-                        next_line = (bytes[0] & 0xFF) | (bytes[1] & 0xFF00) + Self::get_protected_pgc_offset(&self.registers);
+                        next_line = operand(0) + Self::get_protected_pgc_offset(&self.registers);
                     }
                 },  // JiCry
                 0b0111_1000 => {
                     if self.registers[const { Register::OverflowFlag as usize }] == 0 {
-                        next_line = (bytes[0] & 0xFF) | (bytes[1] & 0xFF00) + Self::get_protected_pgc_offset(&self.registers);
+                        next_line = operand(0) + Self::get_protected_pgc_offset(&self.registers);
                     }
                 },  // JnCry
                 0b0111_1001 => {
-                    next_line = self.registers[(bytes[0] & 0xFF) as usize] + Self::get_protected_pgc_offset(&self.registers);
+                    next_line = self.registers[operand(0) as usize] + Self::get_protected_pgc_offset(&self.registers);
                 },  // JmpPtr
                 0b0111_1010 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
                     if self.registers[const { Register::ConditionFlag as usize }] > 0 {
-                        next_line = self.registers[(bytes[0] & 0xFF) as usize] + Self::get_protected_pgc_offset(&self.registers);
+                        next_line = self.registers[operand(0) as usize] + Self::get_protected_pgc_offset(&self.registers);
                     }
                 },  // JicPtr
                 0b0111_1011 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
                     if self.registers[const { Register::ConditionFlag as usize }] == 0 {
-                        next_line = self.registers[(bytes[0] & 0xFF) as usize] + Self::get_protected_pgc_offset(&self.registers);
+                        next_line = self.registers[operand(0) as usize] + Self::get_protected_pgc_offset(&self.registers);
                     }
                 },  // JncPtr
                 0b0111_1100 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
                     if self.registers[const { Register::ZeroFlag as usize }] > 0 {
-                        next_line = self.registers[(bytes[0] & 0xFF) as usize] + Self::get_protected_pgc_offset(&self.registers);
+                        next_line = self.registers[operand(0) as usize] + Self::get_protected_pgc_offset(&self.registers);
                     }
                 },  // JizPtr
                 0b0111_1101 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
                     if self.registers[const { Register::ZeroFlag as usize }] == 0 {
-                        next_line = self.registers[(bytes[0] & 0xFF) as usize] + Self::get_protected_pgc_offset(&self.registers);
+                        next_line = self.registers[operand(0) as usize] + Self::get_protected_pgc_offset(&self.registers);
                     }
                 },  // JnzPtr
                 0b0111_1110 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
                     if self.registers[const { Register::OverflowFlag as usize }] > 0 {
-                        next_line = self.registers[(bytes[0] & 0xFF) as usize] + Self::get_protected_pgc_offset(&self.registers);
+                        next_line = self.registers[operand(0) as usize] + Self::get_protected_pgc_offset(&self.registers);
                     }
                 },  // JiCryPtr
                 0b0111_1111 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
                     if self.registers[const { Register::OverflowFlag as usize }] == 0 {
-                        next_line = self.registers[(bytes[0] & 0xFF) as usize] + Self::get_protected_pgc_offset(&self.registers);
+                        next_line = self.registers[operand(0) as usize] + Self::get_protected_pgc_offset(&self.registers);
                     }
                 },  // JnCryPtr
                 
@@ -1994,9 +3489,9 @@ impl Emulator {
                     }
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
-                    self.stack[(self.registers[const { Register::StackTopPtr as usize }] + Self::get_protected_stack_offset(&self.registers)) as usize] = self.registers[(bytes[0] & 0xFF) as usize];
+                    self.stack[(self.registers[const { Register::StackTopPtr as usize }] + Self::get_protected_stack_offset(&self.registers)) as usize] = self.registers[operand(0) as usize];
                     self.registers[const { Register::StackTopPtr as usize }] += 1;
                     
                 },  // Psh
@@ -2006,7 +3501,7 @@ impl Emulator {
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     }
-                    self.stack[(self.registers[const { Register::StackTopPtr as usize }] + Self::get_protected_stack_offset(&self.registers)) as usize] = (bytes[0] & 0xFF) | (bytes[1] & 0xFF00);
+                    self.stack[(self.registers[const { Register::StackTopPtr as usize }] + Self::get_protected_stack_offset(&self.registers)) as usize] = operand(0);
                     self.registers[const { Register::StackTopPtr as usize }] += 1;
                 },  // PshCon
                 0b1000_0010 => {
@@ -2017,36 +3512,37 @@ impl Emulator {
                     }
                     #[cfg(debug_assertions)]
                     {
-                        edited_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        edited_registers = Some(vec![operand(0) as usize]);
                     }
-                    self.registers[(bytes[0] & 0xFF) as usize] = self.stack[(self.registers[const { Register::StackTopPtr as usize }] + Self::get_protected_stack_offset(&self.registers)) as usize];
+                    // the top points just past the last pushed value
                     self.registers[const { Register::StackTopPtr as usize }] -= 1;
+                    self.registers[operand(0) as usize] = self.stack[(self.registers[const { Register::StackTopPtr as usize }] + Self::get_protected_stack_offset(&self.registers)) as usize];
                 },  // Pop
                 0b1000_0011 => {
                     #[cfg(debug_assertions)]
                     {
-                        edited_registers = Some(vec![(bytes[1] & 0xFF) as usize]);
+                        edited_registers = Some(vec![operand(1) as usize]);
                     }
-                    let index = (bytes[0] & 0xFF) | (bytes[1] & 0xFF00);
+                    let index = operand(0);
                     if self.registers[const { Register::Protected as usize }] == 0 && index > self.registers[const { Register::StackSize as usize }] {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     }
-                    self.registers[(bytes[1] & 0xFF) as usize] = self.stack[(index + Self::get_protected_stack_offset(&self.registers)) as usize];
+                    self.registers[operand(1) as usize] = self.stack[(index + Self::get_protected_stack_offset(&self.registers)) as usize];
                 },  // Index
                 0b1000_0100 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[1] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(1) as usize]);
                     }
-                    let index = ((bytes[0] & 0xFF) | (bytes[1] & 0xFF00));
+                    let index = operand(0);
                     if self.registers[const { Register::Protected as usize }] == 0 && index > self.registers[const { Register::StackSize as usize }] {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     }
-                    self.stack[(index + Self::get_protected_stack_offset(&self.registers)) as usize] = self.registers[(bytes[1] & 0xFF) as usize];
+                    self.stack[(index + Self::get_protected_stack_offset(&self.registers)) as usize] = self.registers[operand(1) as usize];
                 },  // Edit
                 0b1000_0101 => {
                     if self.registers[const { Register::Protected as usize }] == 0 && self.registers[const { Register::StackTopPtr as usize }] > self.registers[const { Register::StackSize as usize }] {
@@ -2056,11 +3552,12 @@ impl Emulator {
                     } else {
                         #[cfg(debug_assertions)]
                         {
-                            stack_trace.push(self.trace_ram[(self.registers[const { Register::ProgramCounter as usize }] + Self::get_protected_ram_offset(&self.registers)) as usize]);
+                            let call_addr = (self.registers[const { Register::ProgramCounter as usize }] + Self::get_protected_ram_offset(&self.registers)) as usize;
+                            stack_trace.push((self.trace_ram[call_addr], call_addr));
                         }
                         self.stack[(self.registers[const { Register::StackTopPtr as usize }] + Self::get_protected_stack_offset(&self.registers)) as usize] = self.registers[const { Register::ProgramCounter as usize }] + 3;
                         self.registers[const { Register::StackTopPtr as usize }] += 1;
-                        next_line = ((bytes[0] & 0xFF) | (bytes[1] & 0xFF00)) + Self::get_protected_pgc_offset(&self.registers);
+                        next_line = operand(0) + Self::get_protected_pgc_offset(&self.registers);
                     }
                 },  // Call
                 0b1000_0110 => {
@@ -2078,7 +3575,7 @@ impl Emulator {
                     }
                 },  // Ret
                 0b1000_0111 => {
-                    let index = self.registers[(bytes[0] & 0xFF) as usize];
+                    let index = self.registers[operand(0) as usize];
                     if self.registers[const { Register::Protected as usize }] == 0 && index > self.registers[const { Register::StackSize as usize }] {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
@@ -2094,9 +3591,9 @@ impl Emulator {
                 0b1000_1000 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
-                    self.registers[const { Register::StackTopPtr as usize }] = self.registers[(bytes[0] & 0xFF) as usize];
+                    self.registers[const { Register::StackTopPtr as usize }] = self.registers[operand(0) as usize];
                 },  // SetStackPtr
                 0b1000_1001 => {
                     let constant = 0;
@@ -2116,44 +3613,44 @@ impl Emulator {
                 0b1000_1010 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
-                    let index = self.registers[(bytes[0] & 0xFF) as usize];
+                    let index = self.registers[operand(0) as usize];
                     if self.registers[const { Register::Protected as usize }] == 0 && index > self.registers[const { Register::StackSize as usize }] {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     }
-                    self.registers[(bytes[1] >> 8) as usize] = self.stack[(index + Self::get_protected_stack_offset(&self.registers)) as usize];
+                    self.registers[operand(1) as usize] = self.stack[(index + Self::get_protected_stack_offset(&self.registers)) as usize];
                 },  // IndexPtr
                 0b1000_1011 => {
                     #[cfg(debug_assertions)]
                     {
-                        edited_registers = Some(vec![(bytes[1] & 0xFF) as usize]);
+                        edited_registers = Some(vec![operand(1) as usize]);
                     }
-                    let index = self.registers[const { Register::StackTopPtr as usize }] - self.registers[(bytes[0] & 0xFF) as usize];
+                    let index = self.registers[const { Register::StackTopPtr as usize }] - self.registers[operand(0) as usize];
                     if self.registers[const { Register::Protected as usize }] == 0 && index > self.registers[const { Register::StackSize as usize }] {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     }
-                    self.registers[(bytes[1] >> 8) as usize] = self.stack[(index + Self::get_protected_stack_offset(&self.registers)) as usize];
+                    self.registers[operand(1) as usize] = self.stack[(index + Self::get_protected_stack_offset(&self.registers)) as usize];
                 },  // IndexOff
                 0b1000_1100 => {
                     #[cfg(debug_assertions)]
                     {
-                        edited_registers = Some(vec![(bytes[1] & 0xFF) as usize]);
+                        edited_registers = Some(vec![operand(1) as usize]);
                     }
-                    let index = self.registers[const { Register::StackTopPtr as usize }] - ((bytes[0] & 0xFF) | (bytes[1] & 0xFF00));
+                    let index = self.registers[const { Register::StackTopPtr as usize }] - operand(0);
                     if self.registers[const { Register::Protected as usize }] == 0 && index > self.registers[const { Register::StackSize as usize }] {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     }
-                    self.registers[(bytes[1] & 0xFF) as usize] = self.stack[(index + Self::get_protected_stack_offset(&self.registers)) as usize];
+                    self.registers[operand(1) as usize] = self.stack[(index + Self::get_protected_stack_offset(&self.registers)) as usize];
                 },  // IndexOffConst
                 0b1000_1101 => {
-                    let index = self.registers[(bytes[0] & 0xFF) as usize];
+                    let index = self.registers[operand(0) as usize];
                     if self.registers[const { Register::Protected as usize }] == 0 && index > self.registers[const { Register::StackSize as usize }] {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
@@ -2161,9 +3658,9 @@ impl Emulator {
                     }
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
-                    let value = self.ram[(self.registers[(bytes[0] & 0xFF) as usize] + Self::get_protected_ram_offset(&self.registers)) as usize];
+                    let value = self.ram[(self.registers[operand(0) as usize] + Self::get_protected_ram_offset(&self.registers)) as usize];
                     self.stack[(index + Self::get_protected_stack_offset(&self.registers)) as usize] = value;
                 },  // EditPtr
                 0b1000_1110 => {
@@ -2174,9 +3671,9 @@ impl Emulator {
                     }
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
-                    let value = self.ram[(self.registers[(bytes[0] & 0xFF) as usize] + Self::get_protected_ram_offset(&self.registers)) as usize];
+                    let value = self.ram[(self.registers[operand(0) as usize] + Self::get_protected_ram_offset(&self.registers)) as usize];
                     self.stack[(self.registers[const { Register::StackTopPtr as usize }] + Self::get_protected_stack_offset(&self.registers)) as usize] = value;
                     self.registers[const { Register::StackTopPtr as usize }] += 1;
                 },  // PshPtr
@@ -2186,9 +3683,9 @@ impl Emulator {
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     } else {
-                        let disc_addr_low = (bytes[0] & 0xFF) | (bytes[1] & 0xFF00);
-                        let disc_addr_high = (bytes[1] & 0xFF) | (bytes[2] & 0xFF00);
-                        self.disc[((disc_addr_low as u32) | ((disc_addr_high as u32) << 16)) as usize] = self.registers[(bytes[2] & 0xFF) as usize];
+                        let disc_addr = operands[0] as usize;
+                        self.disc[disc_addr] = self.registers[operand(1) as usize];
+                        self.disc_end = self.disc_end.max(disc_addr + 1);
                     }
                 },  // Write
                 0b1001_0001 => {
@@ -2197,9 +3694,7 @@ impl Emulator {
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     } else {
-                        let disc_addr_low = (bytes[0] & 0xFF) | (bytes[1] & 0xFF00);
-                        let disc_addr_high = (bytes[1] & 0xFF) | (bytes[2] & 0xFF00);
-                        self.registers[(bytes[2] & 0xFF) as usize] = self.disc[((disc_addr_low as u32) | ((disc_addr_high as u32) << 16)) as usize];
+                        self.registers[operand(1) as usize] = self.disc[operands[0] as usize];
                     }
                 },  // Load
                 0b1001_0010 => {
@@ -2208,9 +3703,11 @@ impl Emulator {
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     } else {
-                        let disc_addr_low = self.registers[(bytes[0] & 0xFF) as usize];
-                        let disc_addr_high = self.registers[(bytes[1] >> 8) as usize];
-                        self.disc[((disc_addr_low as u32) | ((disc_addr_high as u32) << 16)) as usize] = self.registers[(bytes[1] & 0xFF) as usize];
+                        let disc_addr_low = self.registers[operand(0) as usize];
+                        let disc_addr_high = self.registers[operand(1) as usize];
+                        let disc_addr = ((disc_addr_low as u32) | ((disc_addr_high as u32) << 16)) as usize;
+                        self.disc[disc_addr] = self.registers[operand(2) as usize];
+                        self.disc_end = self.disc_end.max(disc_addr + 1);
                     }
                 },  // WritePtr
                 0b1001_0011 => {
@@ -2219,9 +3716,9 @@ impl Emulator {
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     } else {
-                        let disc_addr_low = self.registers[(bytes[0] & 0xFF) as usize];
-                        let disc_addr_high = self.registers[(bytes[1] >> 8) as usize];
-                        self.registers[(bytes[1] & 0xFF) as usize] = self.disc[((disc_addr_low as u32) | ((disc_addr_high as u32) << 16)) as usize];
+                        let disc_addr_low = self.registers[operand(0) as usize];
+                        let disc_addr_high = self.registers[operand(1) as usize];
+                        self.registers[operand(2) as usize] = self.disc[((disc_addr_low as u32) | ((disc_addr_high as u32) << 16)) as usize];
                     }
                 },  // LoadPtr
                 0b1001_0100 => {
@@ -2230,12 +3727,13 @@ impl Emulator {
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     } else {
-                        let disc_addr_low = self.registers[(bytes[0] & 0xFF) as usize];
-                        let disc_addr_high = self.registers[(bytes[1] >> 8) as usize];
-                        let ram_start = self.registers[(bytes[1] & 0xFF) as usize] as usize;
-                        let size = self.registers[(bytes[2] >> 8) as usize] as usize;
+                        let disc_addr_low = self.registers[operand(0) as usize];
+                        let disc_addr_high = self.registers[operand(1) as usize];
+                        let ram_start = self.registers[operand(2) as usize] as usize;
+                        let size = self.registers[operand(3) as usize] as usize;
                         let disc_addr = ((disc_addr_low as u32) | ((disc_addr_high as u32) << 16)) as usize;
                         self.disc[disc_addr..disc_addr + size].copy_from_slice(&self.ram[ram_start..ram_start + size]);
+                        self.disc_end = self.disc_end.max(disc_addr + size);
                         #[cfg(debug_assertions)]
                         {
                             self.trace_disc[disc_addr..disc_addr + size].copy_from_slice(&self.trace_ram[ram_start..ram_start + size]);
@@ -2248,10 +3746,10 @@ impl Emulator {
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
                     } else {
-                        let disc_addr_low = self.registers[(bytes[0] & 0xFF) as usize];
-                        let disc_addr_high = self.registers[(bytes[1] >> 8) as usize];
-                        let ram_start = self.registers[(bytes[1] & 0xFF) as usize] as usize;
-                        let size = self.registers[(bytes[2] >> 8) as usize] as usize;
+                        let disc_addr_low = self.registers[operand(0) as usize];
+                        let disc_addr_high = self.registers[operand(1) as usize];
+                        let ram_start = self.registers[operand(2) as usize] as usize;
+                        let size = self.registers[operand(3) as usize] as usize;
                         let disc_addr = ((disc_addr_low as u32) | ((disc_addr_high as u32) << 16)) as usize;
                         self.ram[ram_start..ram_start + size].copy_from_slice(&self.disc[disc_addr..disc_addr + size]);
                         #[cfg(debug_assertions)]
@@ -2263,43 +3761,103 @@ impl Emulator {
                 0b1010_0000 => {
                     #[cfg(debug_assertions)]
                     {
-                        edited_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        edited_registers = Some(vec![operand(0) as usize]);
                     }
                     if self.registers[const { Register::Protected as usize }] == 0 {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
-                    } else { self.registers[(bytes[0] & 0xFF) as usize] = self.io_in_ports.read()[(bytes[1] >> 8) as usize].0; }
+                    } else { self.registers[operand(0) as usize] = self.io_in_ports.read()[operand(1) as usize].0; }
                 },  // readIn
                 0b1010_0001 => {
                     if self.registers[const { Register::Protected as usize }] == 0 {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
-                    } else { self.registers[const { Register::ConditionFlag as usize }] = self.io_in_ports.read()[(bytes[0] & 0xFF) as usize].1 as u16; }
+                    } else { self.registers[const { Register::ConditionFlag as usize }] = self.io_in_ports.read()[operand(0) as usize].1 as u16; }
                 },  // readInFlag
                 0b1010_0010 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
                     if self.registers[const { Register::Protected as usize }] == 0 {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
-                    } else { self.io_out_ports[(bytes[1] >> 8) as usize].0 = self.registers[(bytes[0] & 0xFF) as usize]; }
+                    } else { self.io_out_ports[operand(1) as usize].0 = self.registers[operand(0) as usize]; }
                 },  // writeOut
                 0b1010_0011 => {
                     #[cfg(debug_assertions)]
                     {
-                        read_registers = Some(vec![(bytes[0] & 0xFF) as usize]);
+                        read_registers = Some(vec![operand(0) as usize]);
                     }
                     if self.registers[const { Register::Protected as usize }] == 0 {
                         // calling the fault callback
                         next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
                         self.registers[const { Register::Protected as usize }] = 1;  // protected
-                    } else { self.io_out_ports[(bytes[1] >> 8) as usize].1 = self.registers[(bytes[0] & 0xFF) as usize] > 0; }
+                    } else { self.io_out_ports[operand(1) as usize].1 = self.registers[operand(0) as usize] > 0; }
                 },  // writeOutFlag
+                0b1011_0000 => {
+                    next_line = pgc.wrapping_add(operand(0));
+                },  // JmpRel
+                0b1011_0001 => {
+                    if self.registers[const { Register::ConditionFlag as usize }] > 0 {
+                        next_line = pgc.wrapping_add(operand(0));
+                    }
+                },  // JicRel
+                0b1011_0010 => {
+                    if self.registers[const { Register::ConditionFlag as usize }] == 0 {
+                        next_line = pgc.wrapping_add(operand(0));
+                    }
+                },  // JncRel
+                0b1011_0011 => {
+                    if self.registers[const { Register::ZeroFlag as usize }] > 0 {
+                        next_line = pgc.wrapping_add(operand(0));
+                    }
+                },  // JizRel
+                0b1011_0100 => {
+                    if self.registers[const { Register::ZeroFlag as usize }] == 0 {
+                        next_line = pgc.wrapping_add(operand(0));
+                    }
+                },  // JnzRel
+                0b1011_0101 => {
+                    if self.registers[const { Register::FaultFlag as usize }] > 0 {
+                        next_line = pgc.wrapping_add(operand(0));
+                    }
+                },  // JiErrRel
+                0b1011_0110 => {
+                    if self.registers[const { Register::FaultFlag as usize }] == 0 {
+                        next_line = pgc.wrapping_add(operand(0));
+                    }
+                },  // JnErrRel
+                0b1011_0111 => {
+                    if self.registers[const { Register::OverflowFlag as usize }] > 0 {
+                        next_line = pgc.wrapping_add(operand(0));
+                    }
+                },  // JiCryRel
+                0b1011_1000 => {
+                    if self.registers[const { Register::OverflowFlag as usize }] == 0 {
+                        next_line = pgc.wrapping_add(operand(0));
+                    }
+                },  // JnCryRel
+                0b1011_1001 => {
+                    if self.registers[const { Register::Protected as usize }] == 0 && self.registers[const { Register::StackTopPtr as usize }] > self.registers[const { Register::StackSize as usize }] {
+                        // calling the fault callback
+                        next_line = self.registers[const { Register::FaultCallbackAddr as usize }];
+                        self.registers[const { Register::Protected as usize }] = 1;  // protected
+                    } else {
+                        #[cfg(debug_assertions)]
+                        {
+                            let call_addr = (self.registers[const { Register::ProgramCounter as usize }] + Self::get_protected_ram_offset(&self.registers)) as usize;
+                            stack_trace.push((self.trace_ram[call_addr], call_addr));
+                        }
+                        // the return addr is absolute, the same as *Call's
+                        self.stack[(self.registers[const { Register::StackTopPtr as usize }] + Self::get_protected_stack_offset(&self.registers)) as usize] = self.registers[const { Register::ProgramCounter as usize }] + 3;
+                        self.registers[const { Register::StackTopPtr as usize }] += 1;
+                        next_line = pgc.wrapping_add(operand(0));
+                    }
+                },  // CallRel
                 _ => {
                     println!("Invalid instruction found");
                     break;
@@ -2307,19 +3865,20 @@ impl Emulator {
             }
             
             #[cfg(debug_assertions)]
-            {
+            if self.interactive {
                 // // highlight_col: \x1B[48;2;55;55;55m
-                println!("\x1b[1;1H{:0>4x} | {:0>4x} | {:0>4x}", bytes[0], bytes[1], bytes[2]);
-                println!("\x1b[2;1H Stack Trace: {}{} [{}]                                ", stack_trace.iter().map(|e| format!("{} [{}] -> ", original_code[*e], e + 1)).collect::<Vec<String>>().join(""), original_code[self.trace_ram[pgc as usize]], self.trace_ram[pgc as usize] + 1);
+                let decoded = disassembler::decode(&self.ram[pgc as usize..(pgc as usize + 4).min(self.ram.len())], Some(symbols)).map_or(String::from("?"), |(text, _)| text);
+                println!("\x1b[1;1H{:0>4x} | {:0>4x} | {:0>4x} at {}: {}                                ", bytes[0], bytes[1], bytes[2], symbols.symbolize(pgc as usize), decoded);
+                println!("\x1b[2;1H Stack Trace: {}{} [{}]                                ", stack_trace.iter().map(|(line, addr)| format!("{} [{}] ({}) -> ", original_code[*line], line + 1, symbols.symbolize(*addr))).collect::<Vec<String>>().join(""), original_code[self.trace_ram[pgc as usize]], self.trace_ram[pgc as usize] + 1);
                 println!("\x1b[4;1HRegisters[0..16]: {}", { self.registers[0..16].iter().enumerate().map(|(i, v)| format!("{}{:0>4x}\x1B[0m", {
                     if match &read_registers {
-                        Some(vector) => { vector.iter().any(|v| *v == i) },
+                        Some(vector) => { vector.contains(&i) },
                         _ => false,
                     } {
                         "\x1B[48;2;55;55;55m"
                     } else {
                         if match &edited_registers {
-                        Some(vector) => { vector.iter().any(|v| *v == i) },
+                        Some(vector) => { vector.contains(&i) },
                         _ => false,
                     } {
                         "\x1B[48;2;95;55;55m"
@@ -2328,13 +3887,13 @@ impl Emulator {
                 }, v)).collect::<Vec<String>>().join(", ") });
                 println!("\x1b[5;1HRam[0 ..16]     : {}", { self.ram[0..16].iter().enumerate().map(|(i, v)| format!("{}{:0>4x}\x1B[0m", {
                     if match &read_ram {
-                        Some(vector) => { vector.iter().any(|v| *v == i) },
+                        Some(vector) => { vector.contains(&i) },
                         _ => false,
                     } {
                         "\x1B[48;2;55;55;55m"
                     } else {
                         if match &edited_ram {
-                        Some(vector) => { vector.iter().any(|v| *v == i) },
+                        Some(vector) => { vector.contains(&i) },
                         _ => false,
                     } {
                         "\x1B[48;2;95;55;55m"
@@ -2347,13 +3906,13 @@ impl Emulator {
                 }, v)).collect::<Vec<String>>().join(", ") });
                 println!("\x1b[6;1HRam[16..32]     : {}", { self.ram[16..32].iter().enumerate().map(|(i, v)| format!("{}{:0>4x}\x1B[0m", {
                     if match &read_ram {
-                        Some(vector) => { vector.iter().any(|v| *v == i + 16) },
+                        Some(vector) => { vector.contains(&(i + 16)) },
                         _ => false,
                     } {
                         "\x1B[48;2;55;55;55m"
                     } else {
                         if match &edited_ram {
-                            Some(vector) => { vector.iter().any(|v| *v == i + 16) },
+                            Some(vector) => { vector.contains(&(i + 16)) },
                             _ => false,
                         } {
                             "\x1B[48;2;95;55;55m"
@@ -2366,13 +3925,13 @@ impl Emulator {
                 }, v)).collect::<Vec<String>>().join(", ") });
                 println!("\x1b[7;1HRam[32..48]     : {}", { self.ram[32..48].iter().enumerate().map(|(i, v)| format!("{}{:0>4x}\x1B[0m", {
                     if match &read_ram {
-                        Some(vector) => { vector.iter().any(|v| *v == i + 32) },
+                        Some(vector) => { vector.contains(&(i + 32)) },
                         _ => false,
                     } {
                         "\x1B[48;2;55;55;55m"
                     } else {
                             if match &edited_ram {
-                            Some(vector) => { vector.iter().any(|v| *v == i + 32) },
+                            Some(vector) => { vector.contains(&(i + 32)) },
                             _ => false,
                         } {
                                 "\x1B[48;2;95;55;55m"
@@ -2385,13 +3944,13 @@ impl Emulator {
                 }, v)).collect::<Vec<String>>().join(", ") });
                 println!("\x1b[8;1HRam[48..64]     : {}", { self.ram[48..64].iter().enumerate().map(|(i, v)| format!("{}{:0>4x}\x1B[0m", {
                     if match &read_ram {
-                        Some(vector) => { vector.iter().any(|v| *v == i + 48) },
+                        Some(vector) => { vector.contains(&(i + 48)) },
                         _ => false,
                     } {
                         "\x1B[48;2;55;55;55m"
                     } else {
                         if match &edited_ram {
-                            Some(vector) => { vector.iter().any(|v| *v == i + 48) },
+                            Some(vector) => { vector.contains(&(i + 48)) },
                             _ => false,
                         } {
                             "\x1B[48;2;95;55;55m"
@@ -2404,13 +3963,13 @@ impl Emulator {
                 }, v)).collect::<Vec<String>>().join(", ") });
                 println!("\x1b[9;1HRam[64..80]     : {}", { self.ram[64..80].iter().enumerate().map(|(i, v)| format!("{}{:0>4x}\x1B[0m", {
                     if match &read_ram {
-                        Some(vector) => { vector.iter().any(|v| *v == i + 64) },
+                        Some(vector) => { vector.contains(&(i + 64)) },
                         _ => false,
                     } {
                         "\x1B[48;2;55;55;55m"
                     } else {
                         if match &edited_ram {
-                            Some(vector) => { vector.iter().any(|v| *v == i + 64) },
+                            Some(vector) => { vector.contains(&(i + 64)) },
                             _ => false,
                         } {
                             "\x1B[48;2;95;55;55m"
@@ -2423,13 +3982,13 @@ impl Emulator {
                 }, v)).collect::<Vec<String>>().join(", ") });
                 println!("\x1b[10;1HRam[80..96]     : {}", { self.ram[80..96].iter().enumerate().map(|(i, v)| format!("{}{:0>4x}\x1B[0m", {
                     if match &read_ram {
-                        Some(vector) => { vector.iter().any(|v| *v == i + 80) },
+                        Some(vector) => { vector.contains(&(i + 80)) },
                         _ => false,
                     } {
                         "\x1B[48;2;55;55;55m"
                     } else {
                         if match &edited_ram {
-                            Some(vector) => { vector.iter().any(|v| *v == i + 80) },
+                            Some(vector) => { vector.contains(&(i + 80)) },
                             _ => false,
                         } {
                             "\x1B[48;2;95;55;55m"
@@ -2482,17 +4041,20 @@ impl Emulator {
         let avg_cycle_duration = std::time::Duration::from_secs_f64(avg_cycle_duration);
         let iters_per_sec = self.registers[Register::Cycles as usize] as f64 / end.as_secs_f64();
         let avg_iter_duration = std::time::Duration::from_secs_f64(1f64 / iters_per_sec);
-        print!("\x1b[14;1HTotal time    : {:?}\x1b[15;1HAvg Iteration : {:?}\x1b[16;1HAvg Ittrs/Sec : {:.0}\x1b[17;1HIterations    : {}\x1b[18;1HCycles        : {}\x1b[19;1HAvg Cycles/Sec: {:.0}\x1b[20;1HAvg Cycle     : {:?}", end, avg_cycle_duration, avg_cps, iterations, self.registers[Register::Cycles as usize], iters_per_sec, avg_iter_duration);
-        
-        crossterm::terminal::disable_raw_mode().unwrap();
-        print!("\x1b[?25h");
+        if self.interactive {
+            print!("\x1b[14;1HTotal time    : {:?}\x1b[15;1HAvg Iteration : {:?}\x1b[16;1HAvg Ittrs/Sec : {:.0}\x1b[17;1HIterations    : {}\x1b[18;1HCycles        : {}\x1b[19;1HAvg Cycles/Sec: {:.0}\x1b[20;1HAvg Cycle     : {:?}", end, avg_cycle_duration, avg_cps, iterations, self.registers[Register::Cycles as usize], iters_per_sec, avg_iter_duration);
+            crossterm::terminal::disable_raw_mode().unwrap();
+            print!("\x1b[?25h");
+        }
     }
 }
 
 impl Drop for Emulator {
     fn drop(&mut self) {
-        crossterm::terminal::disable_raw_mode().unwrap();
-        print!("\x1b[?30h\x1b[23;1H");
+        if self.interactive {
+            crossterm::terminal::disable_raw_mode().unwrap();
+            print!("\x1b[?30h\x1b[23;1H");
+        }
     }
 }
 
@@ -2504,3 +4066,395 @@ impl Drop for Emulator {
 1001, 0000, ff00,  Instruction { name: "LdiR", params: [Addr16, Const16], op_code: 16 }, [Address (1), Constant(65280)], 11
 */
 
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // where the programs run, as the kernel would place them; ram 0 holds the protected *Kill they fault back into
+    const PROGRAM_START: u16 = 0x1000;
+    const RAM_FRAME_START: u16 = 0x8000;
+
+    // the options the args would give (those after 'cisc' on the command line)
+    pub(crate) fn options(args: &[&str]) -> cli::Options {
+        cli::parse(args.iter().map(|arg| arg.to_string())).unwrap().unwrap()
+    }
+
+    // the src parsed as test.cisc, with the -O/--pic passes the args ask for; the errors otherwise
+    pub(crate) fn parse_text(text: &str, args: &[&str]) -> Result<ParsedProgram, Vec<Diagnostic>> {
+        let options = options(&[&["asm", "test.cisc"], args].concat());
+        let (_, _, diagnostics, parsed) = parse_source("test.cisc", Some(text.to_string()), &options);
+        let errors = diagnostics.into_iter().filter(Diagnostic::is_error).collect::<Vec<Diagnostic>>();
+        let Some((mut instructions, labels, mut symbols)) = parsed.filter(|_| errors.is_empty()) else { return Err(errors); };
+        if options.optimize { instructions = peephole::optimize(instructions, &mut symbols); }
+        if options.pic { pic::make_relative(&mut instructions, &symbols); }
+        Ok((instructions, labels, symbols))
+    }
+
+    // the disc words (in addr order) & ram allocs of the src
+    pub(crate) fn assemble_text(text: &str, args: &[&str]) -> (Vec<u16>, RamAllocs) {
+        let (instructions, labels, symbols) = parse_text(text, args)
+            .unwrap_or_else(|errors| panic!("{:?}", errors.iter().map(|error| &error.message).collect::<Vec<_>>()));
+        let (bytes, allocs, _) = compile_script(instructions, labels, &symbols).unwrap_or_else(|error| panic!("{}", error.message));
+        let mut words = vec![0; bytes.iter().map(|(addr, _)| addr + 1).max().unwrap_or(0)];
+        for (addr, (byte_pair, _)) in bytes { words[addr] = byte_pair; }
+        (words, allocs)
+    }
+
+    // runs the src unprotected in a frame of its own, until its *Kill faults back into the protected one
+    pub(crate) fn run_text(text: &str, args: &[&str]) -> Emulator {
+        let (words, allocs) = assemble_text(text, args);
+        let mut emulator = Emulator::new(0x1_0000, vec![0; u16::MAX as usize], vec![0; u16::MAX as usize], false);
+        let kill = INSTRUCTIONS.iter().find(|instruction| instruction.name == "Kill").unwrap();
+        emulator.ram[0] = (kill.op_code as u16) << 8;
+        emulator.ram[PROGRAM_START as usize..][..words.len()].copy_from_slice(&words);
+        for (addr, byte_pairs) in &allocs {
+            emulator.ram[RAM_FRAME_START as usize + addr..][..byte_pairs.len()].copy_from_slice(byte_pairs);
+        }
+        for (register, value) in [
+            (Register::ProgramStart, PROGRAM_START), (Register::ProgramSize, words.len() as u16), (Register::RamFrameStart, RAM_FRAME_START),
+            (Register::RamSize, 0x4000), (Register::StackSize, 0x1000), (Register::FaultCallbackAddr, 0), (Register::ProgramCounter, PROGRAM_START + 3),
+        ] {
+            emulator.registers[register as usize] = value;
+        }
+        emulator.run(vec![""], &SymbolMap::default());
+        emulator
+    }
+
+    // the value the emulator stopped with in the named register (%rda etc.)
+    pub(crate) fn register(emulator: &Emulator, name: &str) -> u16 {
+        emulator.registers[REGISTERS.iter().position(|register| *register == name.trim_start_matches('%')).unwrap()]
+    }
+
+    fn errors(text: &str, args: &[&str]) -> Vec<Diagnostic> {
+        parse_text(text, args).err().unwrap_or_default()
+    }
+
+    #[test]
+    fn lowered_labels_stay_hidden() {
+        let text = "\
+            !header main\n\
+            !while %rda < $10\n\
+            !if %rda == $4\n\
+            Add %rdb $1 %rdb\n\
+            !endif\n\
+            Add %rda $1 %rda\n\
+            !endwhile\n\
+            *Kill\n";
+        let (instructions, _, symbols) = parse_text(text, &[]).unwrap();
+        assert!(symbols.is_hidden("__while_0") && symbols.is_hidden("__if_1_else") && !symbols.is_hidden("main"));
+        let object = compile_object(instructions, &symbols).unwrap();
+        assert_eq!(object.exports.iter().map(|(name, ..)| name.as_str()).collect::<Vec<_>>(), ["main"]);
+        let symbol_map = SymbolMap::from_table(&symbols, &SourceMap::default());
+        assert!(!symbol_map.to_text().contains("__"));
+        let emulator = run_text(text, &[]);
+        assert_eq!((register(&emulator, "rda"), register(&emulator, "rdb")), (10, 1));
+    }
+
+    #[test]
+    fn pop_takes_the_last_pushed_value() {
+        let emulator = run_text("\
+            *Ldi %rda $7\n\
+            *Ldi %rdb $9\n\
+            *Psh %rda\n\
+            *Psh %rdb\n\
+            *Pop %rdc\n\
+            *Pop %rdd\n\
+            *Kill\n", &[]);
+        assert_eq!((register(&emulator, "rdc"), register(&emulator, "rdd")), (9, 7));
+        assert_eq!(emulator.registers[Register::StackTopPtr as usize], 0);
+    }
+
+    #[test]
+    fn push_all_round_trips() {
+        let emulator = run_text("\
+            *Ldi %rda $1\n\
+            *Ldi %rdm $13\n\
+            *Ldi %rdz $26\n\
+            PshAll\n\
+            *Ldi %rda $0\n\
+            *Ldi %rdm $0\n\
+            *Ldi %rdz $0\n\
+            PopAll\n\
+            *Kill\n", &[]);
+        assert_eq!([register(&emulator, "rda"), register(&emulator, "rdm"), register(&emulator, "rdz")], [1, 13, 26]);
+    }
+
+    #[test]
+    fn macros_keep_the_line_numbers_of_their_body() {
+        let text = "\
+            !macro set_twice <reg> <value>\n\
+            *Ldi <reg> $<value>\n\
+            Add <reg> <reg> <reg>\n\
+            !endmacro\n\
+            set_twice %rda 3\n\
+            *Kill\n";
+        let (instructions, ..) = parse_text(text, &[]).unwrap();
+        let expanded = instructions.into_iter().filter_map(|item| match item {
+            Union::A((instruction, parameters, line, _)) => Some((instruction.name, parameters, line.line)),
+            Union::B(_) => None,
+        }).collect::<Vec<_>>();
+        assert_eq!(expanded, [
+            ("Ldi", vec![Parameter::Register(0), Parameter::Constant(3)], 1),
+            ("Add", vec![Parameter::Register(0); 3], 2),
+            ("Kill", vec![], 5),
+        ]);
+
+        // an error in the body points into it, and at the call it was expanded from
+        //    while the arguments are where they were written
+        let errors = errors(&text.replace("Add <reg> <reg> <reg>", "Add <reg> <reg> %nope").replace("%rda 3", "%rda missing"), &[]);
+        let at = errors.iter().map(|error| {
+            let notes = error.notes.iter().map(|note| (note.message.as_str(), note.span.as_ref().map(|span| span.line))).collect::<Vec<_>>();
+            (error.span.as_ref().map(|span| span.line), notes)
+        }).collect::<Vec<_>>();
+        assert_eq!(at, [
+            (Some(4), vec![("expanded from this macro call", Some(4))]),
+            (Some(2), vec![("expanded from this macro call", Some(4))]),
+        ], "{:?}", errors);
+        assert_eq!(errors[0].message, "undefined symbol 'missing'");
+    }
+
+    // the value of the { ... } expression, through a !const; the error messages otherwise
+    fn evaluate(expression: &str) -> Result<i64, Vec<String>> {
+        parse_text(&format!("!const value {{{}}}\n", expression), &[])
+            .map(|(_, _, symbols)| symbols.get("value").unwrap().value)
+            .map_err(|errors| errors.into_iter().map(|error| error.message).collect())
+    }
+
+    #[test]
+    fn expressions_bind_like_c() {
+        for (expression, value) in [
+            ("1 + 2 * 3", 7), ("(1 + 2) * 3", 9), ("10 - 4 - 3", 3), ("2 * 3 % 4", 2), ("1 | 2 ^ 3 & 6", 1),
+            ("1 << 2 + 1", 8), ("0x100 >> 4 | 1", 0x11), ("-16 >> 2", -4), ("- -3", 3), ("+5 - -5", 10),
+            ("7 / 2", 3), ("-7 / 2", -3), ("-7 % 3", -1), ("0b101 & 0x1C", 4), ("1 << 63 >> 63", -1),
+        ] {
+            assert_eq!(evaluate(expression), Ok(value), "{{{}}}", expression);
+        }
+    }
+
+    #[test]
+    fn expressions_report_what_cant_be_evaluated() {
+        for (expression, message) in [
+            ("1 / 0", "division by zero"), ("5 % (2 - 2)", "division by zero"),
+            ("1 << 64", "invalid shift amount '64'"), ("1 >> -1", "invalid shift amount '-1'"),
+            ("1 +", "unexpected end of the expression after this"), ("(1 + 2", "this '(' is never closed"),
+            ("1 2", "unexpected '2' in the expression"), ("1 + * 2", "unexpected '*' in the expression"),
+        ] {
+            assert_eq!(evaluate(expression), Err(vec![message.to_string()]), "{{{}}}", expression);
+        }
+    }
+
+    // the files written out to a directory of the test's own, and the main.cisc among them read in from there & parsed
+    fn parse_files(test: &str, files: &[(&str, &str)]) -> (SourceMap, Vec<Diagnostic>, Option<ParsedProgram>) {
+        let dir = std::env::temp_dir().join(format!("cisc-{}-{}", test, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, text) in files {
+            std::fs::write(dir.join(name), text).unwrap();
+        }
+        let root = dir.join("main.cisc");
+        let mut sources = SourceMap::default();
+        let mut diagnostics = vec![];
+        let mut script = ScriptLines::new();
+        let mut src_files = SrcFiles::new();
+        let mut include_chain = vec![(root.canonicalize().unwrap(), None)];
+        load_script(&root, String::new(), &mut include_chain, &mut src_files, &mut sources, &mut script, &mut diagnostics).unwrap();
+        let parsed = parse_sudo(script, &sources, &src_files, vec![], &mut diagnostics);
+        std::fs::remove_dir_all(&dir).unwrap();
+        (sources, diagnostics.into_iter().filter(Diagnostic::is_error).collect(), parsed)
+    }
+
+    #[test]
+    fn includes_go_under_their_namespace() {
+        let (_, errors, parsed) = parse_files("namespaces", &[
+            ("main.cisc", "!include \"lib.cisc\"\n!include \"lib.cisc\" as again\n!header start\n*Call #lib::double\n*Kill\n"),
+            ("lib.cisc", "!header double\nAdd %rda %rda %rda\n*Ret\n"),
+        ]);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let (_, _, symbols) = parsed.unwrap();
+        assert!(symbols.get("lib::double").is_some() && symbols.get("double").is_none());
+        assert!(symbols.get("again::double").is_none(), "a file is only ever included once");
+    }
+
+    #[test]
+    fn include_cycles_are_reported() {
+        let (sources, errors, _) = parse_files("cycle", &[
+            ("main.cisc", "!include \"a.cisc\"\n*Kill\n"),
+            ("a.cisc", "!include \"b.cisc\"\n"),
+            ("b.cisc", "\n!include \"main.cisc\"\n"),
+        ]);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(errors[0].message.starts_with("the !include of '") && errors[0].message.ends_with("main.cisc' forms a cycle"));
+        let span = errors[0].span.clone().unwrap();
+        assert!(sources.path(span.file).ends_with("b.cisc") && span.line == 1);
+        let notes = errors[0].notes.iter().map(|note| note.message.rsplit(['/', '\\']).next().unwrap()).collect::<Vec<_>>();
+        assert_eq!(notes, ["a.cisc' was included from here", "b.cisc' was included from here"]);
+    }
+
+    #[test]
+    fn undefined_symbols_are_reported() {
+        let errors = errors("\
+            !const LIMIT 8\n\
+            !header start\n\
+            *Jmp #strt\n\
+            Add %rda $LIMT %rda\n\
+            .word #nowhere\n\
+            !alloc #0 ${LIMIT * unknown}\n\
+            *Kill\n", &[]);
+        let found = errors.iter().map(|error| {
+            (error.message.as_str(), error.span.as_ref().map(|span| span.line), error.notes.iter().map(|note| note.message.as_str()).collect::<Vec<_>>())
+        }).collect::<Vec<_>>();
+        assert_eq!(found, [
+            ("undefined symbol 'strt' (did you mean 'start'?)", Some(2), vec!["the label 'start' is defined here"]),
+            ("undefined symbol 'LIMT' (did you mean 'LIMIT'?)", Some(3), vec!["the !const 'LIMIT' is defined here"]),
+            ("undefined symbol 'nowhere'", Some(4), vec![]),
+            ("undefined symbol 'unknown'", Some(5), vec![]),
+        ]);
+    }
+
+    #[test]
+    fn conditionals_nest() {
+        let text = "\
+            .if A\n\
+            .if {A - 1}\n\
+            *Ldi %rda $1\n\
+            .else\n\
+            *Ldi %rda $2\n\
+            .ifdef B\n\
+            *Ldi %rda $3\n\
+            .endif\n\
+            .endif\n\
+            .else\n\
+            *Ldi %rda $4\n\
+            .ifdef C\n\
+            .if {1 / 0}\n\
+            .endif\n\
+            .endif\n\
+            .endif\n\
+            *Kill\n";
+        // the constants of the *Ldis that were kept
+        for (args, kept) in [(&["-DA=2"][..], &[1][..]), (&["-DA"], &[2]), (&["-DA", "-DB"], &[2, 3]), (&["-DA=0", "-DB"], &[4])] {
+            let (instructions, ..) = parse_text(text, args).unwrap();
+            let loaded = instructions.into_iter().filter_map(|instruction| match instruction {
+                Union::A((instruction, parameters, ..)) if instruction.name == "Ldi" => Some(parameters[1].clone()),
+                _ => None,
+            }).collect::<Vec<_>>();
+            assert_eq!(loaded, kept.iter().map(|&value| Parameter::Constant(value)).collect::<Vec<_>>(), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn unmatched_conditionals_are_reported() {
+        for (text, message) in [
+            (".else\n", "found .else without a matching .if"),
+            (".endif\n", "found .endif without a matching .if"),
+            (".ifdef A\n.else\n.else\n.endif\n", "a second .else for the same .if"),
+            (".if 1\n.if 0\n.endif\n", "this .if is never closed"),
+        ] {
+            assert_eq!(errors(text, &[]).into_iter().map(|error| error.message).collect::<Vec<_>>(), [message], "{}", text);
+        }
+    }
+
+    // the byte pairs & refs of each data directive
+    fn data(text: &str) -> Vec<(Vec<u16>, AddrRefs)> {
+        let (instructions, ..) = parse_text(text, &[]).unwrap_or_else(|errors| panic!("{:?}", errors.iter().map(|error| &error.message).collect::<Vec<_>>()));
+        instructions.into_iter().filter_map(|item| match item {
+            Union::B(Label::Data(byte_pairs, _, refs)) => Some((byte_pairs, refs)),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn data_directives_place_their_byte_pairs() {
+        let text = "\
+            !header start\n\
+            *Kill\n\
+            !label table\n\
+            .word 1 $-1 0xFFFF {2 * 3} #table #{table + 2}\n\
+            .fill 3 $7\n\
+            .fill 2\n\
+            .ascii \"abc\" \"d\"\n\
+            .string \"hi\"\n";
+        let table = parse_text(text, &[]).unwrap().2.get("table").unwrap().value;
+        assert_eq!(data(text), [
+            (vec![1, 0xFFFF, 0xFFFF, 6, table as u16, table as u16 + 2], vec![(4, String::from("table"), 0), (5, String::from("table"), 2)]),
+            (vec![7; 3], vec![]),
+            (vec![0; 2], vec![]),
+            (vec![0x6162, 0x6364], vec![]),
+            (vec!['h' as u16, 'i' as u16], vec![]),
+        ]);
+        assert_eq!(data(".ascii \"abc\"\n")[0].0, [0x6162, 0x6300]);
+    }
+
+    #[test]
+    fn align_pads_up_to_the_boundary() {
+        let text = "\
+            !header start\n\
+            *Kill\n\
+            .word 5\n\
+            .align 8\n\
+            !label aligned\n\
+            .word #aligned\n";
+        let (words, _) = assemble_text(text, &[]);
+        let aligned = words.len() - 1;
+        assert_eq!(aligned % 8, 0);
+        assert_eq!(words[aligned], aligned as u16);
+        // the .word 5, then the zeros of padding
+        let padding = words[..aligned].iter().rev().take_while(|word| **word == 0).count();
+        assert!((1..8).contains(&padding));
+        assert_eq!(words[aligned - padding - 1], 5);
+    }
+
+    #[test]
+    fn data_out_of_range_is_reported() {
+        for (text, message) in [
+            (".word 70000\n", "the value 70000 doesn't fit in a byte pair"),
+            (".word 1 $-70000\n", "the value -70000 doesn't fit in a byte pair"),
+            (".fill 2 $99999\n", "the value 99999 doesn't fit in a byte pair"),
+            (".fill 3 $\n", "expected the .fill value after this"),
+            (".fill -1\n", "invalid .fill count -1"),
+            (".align 0\n", "invalid .align boundary 0"),
+            (".ascii \"\u{100}\"\n", "the character '\u{100}' doesn't fit in 8 bits"),
+        ] {
+            assert_eq!(errors(text, &[]).into_iter().map(|error| error.message).collect::<Vec<_>>(), [message], "{}", text);
+        }
+        // the error points at the value
+        let errors = errors(".word 1 $-70000\n", &[]);
+        let span = errors[0].span.as_ref().unwrap();
+        assert_eq!(span.columns, 9..15);
+    }
+
+    #[test]
+    fn operand_errors_point_at_the_operand() {
+        for (text, message, columns) in [
+            ("*Ldi %rda $70000\n", "operand 2 (70000) doesn't fit in a 16 bit constant (-32768..=65535)", 10..16),
+            ("Add %rda {0x10000 + 1} %rda\n", "operand 2 (65537) doesn't fit in a 16 bit constant (-32768..=65535)", 9..22),
+            ("*Ldi $1 %rda\n", "operand 1 should be a Register, but found a Constant", 5..7),
+            ("*Jmp [%rda]\n", "operand 1 should be an Address, but found a Pointer", 5..11),
+        ] {
+            let errors = errors(text, &[]);
+            assert_eq!(errors[0].message, message, "{}", text);
+            assert_eq!(errors[0].span.as_ref().unwrap().columns, columns, "{}", text);
+        }
+    }
+
+    #[test]
+    fn values_can_be_negated() {
+        let text = "\
+            !const FIVE 5\n\
+            !const NEGATED -{FIVE * 2}\n\
+            *Ldi %rda $-5\n\
+            *Ldi %rda ${-5}\n\
+            *Ldi %rda $-{5}\n\
+            *Ldi %rda $-{FIVE - 1}\n\
+            *Ldi %rda $-FIVE\n";
+        let (instructions, _, symbols) = parse_text(text, &[]).unwrap();
+        assert_eq!(symbols.get("NEGATED").unwrap().value, -10);
+        let loaded = instructions.into_iter().filter_map(|item| match item {
+            Union::A((_, parameters, ..)) => Some(parameters[1].clone()),
+            Union::B(_) => None,
+        }).collect::<Vec<_>>();
+        assert_eq!(loaded, [-5, -5, -5, -4, -5].map(Parameter::Constant));
+        assert_eq!(errors("*Ldi %rda $-{5\n", &[])[0].message, "this '{' is never closed");
+        assert_eq!(errors("*Ldi %rda $-\n", &[])[0].message, "expected a value, but found '-'");
+    }
+}
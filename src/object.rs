@@ -0,0 +1,206 @@
+// relocatable object files (.obj); an assembled program whose label addrs aren't fixed yet,
+//    so several can be laid out into one disc image by the linker
+//
+//    a line per entry, space separated; numbers in hex (0x...) except for the indices and the (signed) addends
+//        cisc-object 1
+//        section 0 relocatable            (placed wherever the linker puts it)
+//        section 1 fixed 0x0640           (.page; always at that disc addr)
+//        words 0 0100 4006 0000 ...       (appended to the section's byte pairs)
+//        export main_kernel 1 0x0000      (a label; section, byte pair offset)
+//        import schedule_callback         (an !extern)
+//        reloc 0 0x0007 addr16 section 1 0      (section, byte offset, operand size, the section or symbol + addend)
+//        reloc 0 0x0013 addr16 symbol schedule_callback 6
+//        reloc 0 0x0020 word section 0 9       (a .word's byte pair; high byte first, unlike the operands)
+//        alloc 0x0200 0001 0002 ...       (initialized ram)
+//        trait ram_size 0x00ff
+
+use crate::RamAllocs;
+
+pub const OBJECT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Default)]
+pub struct Section {
+    pub fixed: Option<usize>,  // the disc addr from .page; None when relocatable
+    pub byte_pairs: Vec<u16>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RelocTarget {
+    Section (usize),  // one of this object's own sections
+    Symbol (String),  // an import
+}
+
+#[derive(Debug, Clone)]
+pub struct Relocation {
+    pub section: usize,
+    pub offset: usize,  // in bytes from the start of the section
+    pub size: usize,  // 2 (Addr16 or a .word) or 4 (Addr32) bytes
+    pub word: bool,  // a .word byte pair (the high byte first), rather than an operand (the low byte first)
+    pub target: RelocTarget,
+    pub addend: i64,  // from the start of the target (the byte pair offset, for sections)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ObjectFile {
+    pub sections: Vec<Section>,
+    pub exports: Vec<(String, usize, usize)>,  // name, section, byte pair offset
+    pub imports: Vec<String>,
+    pub relocations: Vec<Relocation>,
+    pub allocs: RamAllocs,
+    pub traits: Vec<(String, u16)>,
+}
+
+fn hex_words(words: &[u16]) -> String {
+    words.iter().map(|word| format!("{:04x}", word)).collect::<Vec<String>>().join(" ")
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(text.strip_prefix("0x")?, 16).ok()
+}
+
+fn parse_words(fields: &[&str]) -> Option<Vec<u16>> {
+    fields.iter().map(|word| u16::from_str_radix(word, 16).ok()).collect()
+}
+
+impl ObjectFile {
+    pub fn to_text(&self) -> String {
+        let mut text = format!("cisc-object {}\n", OBJECT_VERSION);
+        for (index, section) in self.sections.iter().enumerate() {
+            match section.fixed {
+                Some(addr) => text.push_str(&format!("section {} fixed 0x{:04x}\n", index, addr)),
+                None => text.push_str(&format!("section {} relocatable\n", index)),
+            }
+        }
+        for (index, section) in self.sections.iter().enumerate() {
+            for chunk in section.byte_pairs.chunks(12) {
+                text.push_str(&format!("words {} {}\n", index, hex_words(chunk)));
+            }
+        }
+        for (name, section, offset) in &self.exports {
+            text.push_str(&format!("export {} {} 0x{:04x}\n", name, section, offset));
+        }
+        for name in &self.imports {
+            text.push_str(&format!("import {}\n", name));
+        }
+        for relocation in &self.relocations {
+            let target = match &relocation.target {
+                RelocTarget::Section(section) => format!("section {}", section),
+                RelocTarget::Symbol(name) => format!("symbol {}", name),
+            };
+            let field = if relocation.word { String::from("word") } else { format!("addr{}", relocation.size * 8) };
+            text.push_str(&format!(
+                "reloc {} 0x{:04x} {} {} {}\n", relocation.section, relocation.offset, field, target, relocation.addend
+            ));
+        }
+        for (addr, byte_pairs) in &self.allocs {
+            text.push_str(&format!("alloc 0x{:04x} {}\n", addr, hex_words(byte_pairs)));
+        }
+        for (name, value) in &self.traits {
+            text.push_str(&format!("trait {} 0x{:04x}\n", name, value));
+        }
+        text
+    }
+
+    // the error names the (1 based) line that couldn't be read
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+        match lines.next().map(|(_, line)| line.split_whitespace().collect::<Vec<&str>>()) {
+            Some(header) if header.len() == 2 && header[0] == "cisc-object" => {
+                if header[1] != OBJECT_VERSION.to_string() {
+                    return Err(format!("unsupported object version '{}' (expected {})", header[1], OBJECT_VERSION));
+                }
+            },
+            _ => { return Err(String::from("not a cisc object (missing the 'cisc-object' line)")); },
+        }
+        let mut object = ObjectFile::default();
+        for (index, line) in lines {
+            let fields = line.split_whitespace().collect::<Vec<&str>>();
+            let invalid = || format!("line {}: invalid '{}' entry", index + 1, fields[0]);
+            let section = |field: &str| field.parse::<usize>().ok().filter(|section| *section < object.sections.len());
+            match fields[..] {
+                ["section", id, "relocatable"] if id == object.sections.len().to_string() => {
+                    object.sections.push(Section::default());
+                },
+                ["section", id, "fixed", addr] if id == object.sections.len().to_string() => {
+                    object.sections.push(Section { fixed: Some(parse_hex(addr).ok_or_else(invalid)?), byte_pairs: vec![] });
+                },
+                ["words", id, ref words @ ..] => {
+                    let section = section(id).ok_or_else(invalid)?;
+                    object.sections[section].byte_pairs.extend(parse_words(words).ok_or_else(invalid)?);
+                },
+                ["export", name, id, offset] => {
+                    object.exports.push((name.to_string(), section(id).ok_or_else(invalid)?, parse_hex(offset).ok_or_else(invalid)?));
+                },
+                ["import", name] => { object.imports.push(name.to_string()); },
+                ["reloc", id, offset, size, kind, target, addend] => {
+                    let target = match kind {
+                        "section" => RelocTarget::Section(section(target).ok_or_else(invalid)?),
+                        "symbol" => RelocTarget::Symbol(target.to_string()),
+                        _ => { return Err(invalid()); },
+                    };
+                    object.relocations.push(Relocation {
+                        section: section(id).ok_or_else(invalid)?,
+                        offset: parse_hex(offset).ok_or_else(invalid)?,
+                        size: match size { "addr16" | "word" => 2, "addr32" => 4, _ => { return Err(invalid()); } },
+                        word: size == "word",
+                        target,
+                        addend: addend.parse().map_err(|_| invalid())?,
+                    });
+                },
+                ["alloc", addr, ref words @ ..] => {
+                    object.allocs.push((parse_hex(addr).ok_or_else(invalid)?, parse_words(words).ok_or_else(invalid)?));
+                },
+                ["trait", name, value] => {
+                    object.traits.push((name.to_string(), parse_hex(value).ok_or_else(invalid)? as u16));
+                },
+                _ => { return Err(format!("line {}: unknown entry '{}'", index + 1, line.trim())); },
+            }
+        } Ok(object)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::parse_text;
+
+    // what a cisc-object holds, field by field (it has no PartialEq of its own)
+    fn fields(object: &ObjectFile) -> String {
+        format!("{:?}", (&object.sections, &object.exports, &object.imports, &object.relocations, &object.allocs, &object.traits))
+    }
+
+    #[test]
+    fn round_trips_through_its_text() {
+        let text = "\
+            .ram_size 0x0040\n\
+            !extern helper\n\
+            !alloc #2 $7 \"hi\" zeros 3\n\
+            !header main\n\
+            *Call #helper\n\
+            *Jmp #{main + 3}\n\
+            !label table\n\
+            .word #table $5\n\
+            .page 0x2000\n\
+            !header far\n\
+            *Jmp #main\n";
+        let (instructions, _, symbols) = parse_text(text, &[]).unwrap();
+        let object = crate::compile_object(instructions, &symbols).unwrap();
+        assert_eq!(object.sections.iter().map(|section| section.fixed).collect::<Vec<_>>(), [None, Some(0x2000)]);
+        assert_eq!(object.imports, ["helper"]);
+        assert_eq!(object.allocs, [(2, vec![7, 'h' as u16, 'i' as u16, 0, 0, 0])]);
+        assert_eq!(object.traits, [(String::from("ram_size"), 0x40)]);
+        assert!(object.relocations.iter().any(|relocation| relocation.word && relocation.target == RelocTarget::Section(0)));
+        assert!(object.relocations.iter().any(|relocation| relocation.target == RelocTarget::Symbol(String::from("helper"))));
+        let parsed = ObjectFile::parse(&object.to_text()).unwrap();
+        assert_eq!(fields(&parsed), fields(&object));
+        assert_eq!(parsed.to_text(), object.to_text());
+    }
+
+    #[test]
+    fn rejects_what_isnt_an_object() {
+        assert!(ObjectFile::parse("").unwrap_err().contains("not a cisc object"));
+        assert!(ObjectFile::parse("cisc-object 2\n").unwrap_err().contains("unsupported object version"));
+        assert_eq!(ObjectFile::parse("cisc-object 1\nwords 0 0001\n").unwrap_err(), "line 2: invalid 'words' entry");
+        assert_eq!(ObjectFile::parse("cisc-object 1\nsection 0 relocatable\nexport main 0\n").unwrap_err(), "line 3: unknown entry 'export main 0'");
+    }
+}
@@ -0,0 +1,363 @@
+// the peephole optimizer; an optional pass (asm/run -O) over the parsed program, rewriting short runs of instructions
+//    into cheaper ones that do the same, and then placing the labels again
+//
+//    jumps to the instruction right after them are dropped, as are the movs that change nothing; an Ldi or a Mov
+//    feeding the next instruction is folded into it when its register isn't read again before it's next written, and
+//    the constant forms replace the register ones when they cost fewer cycles
+//
+//    only the addresses given relative to a label (#label, #{label + 3}, .word #label) are moved along with the code, as
+//    with objects; a label whose value is taken as a number anywhere else ($label, {label * 2}, !define x label) is fixed,
+//    and the code leading up to it (from its .page) is left as is so it stays where it was. an addr written as a number
+//    keeps what it was assembled as
+
+use crate::symbols::{SymbolKind, SymbolTable};
+use crate::{placed_addrs, AddrRefs, Instruction, InstructionStream, Label, Parameter, SrcLine, Union, INSTRUCTIONS, RELATIVE_FORMS};
+
+type Item = Union<(Instruction, Vec<Parameter>, SrcLine, AddrRefs), Label>;
+
+// the jumps to an addr operand; the conditional ones don't change the flags, so skipping to the next is the same
+pub static JUMPS: &[&str] = &["Jmp", "Jic", "Jnc", "Jiz", "Jnz", "JiErr", "JnErr", "JiCry", "JnCry"];
+
+// the instructions whose only effect on the registers is reading their register & pointer operands, and writing the
+//    one at the index given; anything else (jumps, calls, interrupts, etc.) is taken as reading every register
+static EFFECTS: &[(&str, Option<usize>)] = &[
+    ("Nop", None), ("Ldi", Some(0)), ("Mov", Some(1)), ("LdiR", None), ("Sto", None), ("Get", Some(1)),
+    ("LdiPtr", None), ("StoPtr", None), ("GetPtr", Some(1)), ("StoPtrOff", None), ("GetPtrOff", Some(2)),
+    ("StoPtrOffPtr", None), ("GetPtrOffPtr", Some(2)), ("MovR", None),
+    ("Add", Some(2)), ("Sub", Some(2)), ("Mul", Some(2)), ("Div", Some(2)), ("Mod", Some(2)), ("And", Some(2)),
+    ("Or", Some(2)), ("Not", Some(1)), ("Xor", Some(2)), ("Pow", Some(2)), ("Left", Some(2)), ("Right", Some(2)),
+    ("AddImm", Some(2)), ("SubImm", Some(2)), ("SubRevImm", Some(2)), ("MulImm", Some(2)), ("DivImm", Some(2)),
+    ("ModImm", Some(2)), ("AndImm", Some(2)), ("OrImm", Some(2)), ("XorImm", Some(2)), ("PowImm", Some(2)),
+    ("LeftImm", Some(2)), ("RightImm", Some(2)),
+    ("Less", None), ("Grtr", None), ("Eq", None), ("LessImm", None), ("GrtrImm", None), ("EqImm", None), ("Zero", None),
+    ("Psh", None), ("PshCon", None), ("Pop", Some(0)), ("IndexOffConst", Some(1)), ("PshPtr", None),
+];
+
+// an Ldi'd register folded into the constant form of the instruction using it; (instruction, operand the register is
+//    at, constant form, the operands it takes from the instruction's, the register's being where the constant goes)
+static FOLDS: &[(&str, usize, &str, &[usize])] = &[
+    ("Add", 1, "AddImm", &[0, 1, 2]), ("Add", 0, "AddImm", &[1, 0, 2]),
+    ("Sub", 1, "SubImm", &[0, 1, 2]), ("Sub", 0, "SubRevImm", &[1, 0, 2]),
+    ("Mul", 1, "MulImm", &[0, 1, 2]), ("Mul", 0, "MulImm", &[1, 0, 2]),
+    ("Div", 1, "DivImm", &[0, 1, 2]), ("Mod", 1, "ModImm", &[0, 1, 2]),
+    ("And", 1, "AndImm", &[0, 1, 2]), ("And", 0, "AndImm", &[1, 0, 2]),
+    ("Or", 1, "OrImm", &[0, 1, 2]), ("Or", 0, "OrImm", &[1, 0, 2]),
+    ("Xor", 1, "XorImm", &[0, 1, 2]), ("Xor", 0, "XorImm", &[1, 0, 2]),
+    ("Left", 1, "LeftImm", &[0, 1, 2]), ("Right", 1, "RightImm", &[0, 1, 2]),
+    ("Less", 1, "LessImm", &[0, 1]), ("Less", 0, "GrtrImm", &[1, 0]),
+    ("Grtr", 1, "GrtrImm", &[0, 1]), ("Grtr", 0, "LessImm", &[1, 0]),
+    ("Eq", 1, "EqImm", &[0, 1]), ("Eq", 0, "EqImm", &[1, 0]),
+    ("Mov", 0, "Ldi", &[1, 0]), ("Psh", 0, "PshCon", &[0]),
+    ("Sto", 1, "LdiR", &[0, 1]), ("StoPtr", 1, "LdiPtr", &[0, 1]),
+];
+
+fn native(name: &str) -> Instruction {
+    INSTRUCTIONS.iter().find(|instruction| instruction.name == name).cloned().expect("the peephole tables only name known instructions")
+}
+
+// the register operands an instruction reads, and the one it writes; None when its effects aren't known
+fn effects(instruction: &Instruction, parameters: &[Parameter]) -> Option<(Vec<u8>, Option<u8>)> {
+    let (_, write) = EFFECTS.iter().find(|(name, _)| *name == instruction.name)?;
+    let reads = parameters.iter().enumerate().filter_map(|(index, parameter)| match parameter {
+        Parameter::Register(register) if Some(index) != *write => Some(*register),
+        Parameter::Pointer(register) => Some(*register),
+        _ => None,
+    }).collect();
+    Some((reads, write.and_then(|index| match parameters[index] {
+        Parameter::Register(register) => Some(register),
+        _ => None,
+    })))
+}
+
+// the next instruction still in the stream, and whether a label (which could be jumped to) sits before it
+fn next(items: &[Option<Item>], from: usize) -> Option<(usize, bool)> {
+    let mut labelled = false;
+    for (index, item) in items.iter().enumerate().skip(from + 1) {
+        match item {
+            Some(Union::A(_)) => { return Some((index, labelled)); },
+            Some(Union::B(_)) => { labelled = true; },
+            None => {},
+        }
+    } None
+}
+
+// the register is written before anything could read it again, falling through from the instruction given
+//    labels fallen into don't matter; any other way in has its own path
+fn dead_after(items: &[Option<Item>], from: usize, register: u8) -> bool {
+    for item in items.iter().skip(from + 1) {
+        match item {
+            Some(Union::A((instruction, parameters, ..))) => match effects(instruction, parameters) {
+                Some((reads, _)) if reads.contains(&register) => { return false; },
+                Some((_, write)) if write == Some(register) => { return true; },
+                Some(_) => {},
+                None => { return false; },
+            },
+            Some(Union::B(Label::Header(_))) | None => {},
+            Some(Union::B(_)) => { return false; },
+        }
+    } false
+}
+
+// the items between a label and an addr given relative to it (#{label + 3}), and between a pc-relative jump (*JmpRel
+//    $6) and where it lands; they're left as they are, so the addr still lands on the same instruction. as are those
+//    leading up to a fixed label, from the start of its .page
+fn pinned(items: &InstructionStream, symbols: &SymbolTable) -> Vec<bool> {
+    let addrs = placed_addrs(items);
+    let refs = items.iter().flat_map(|item| match item {
+        Union::A((_, _, _, refs)) | Union::B(Label::Data(_, _, refs)) => refs.as_slice(),
+        _ => &[],
+    }).filter(|(_, _, addend)| *addend != 0).filter_map(|(_, name, addend)| {
+        symbols.get(name).filter(|symbol| symbol.kind == SymbolKind::Label).map(|symbol| (symbol.value, symbol.value + addend))
+    });
+    let relative = items.iter().zip(&addrs).filter_map(|(item, addr)| match item {
+        Union::A((instruction, parameters, ..)) if RELATIVE_FORMS.iter().any(|(_, relative)| *relative == instruction.name) => match parameters[0] {
+            Parameter::Constant(offset) => Some((*addr as i64, *addr as i64 + offset as i16 as i64)),
+            _ => None,
+        },
+        _ => None,
+    });
+    let mut pinned = vec![false; items.len()];
+    for (from, to) in refs.chain(relative).collect::<Vec<(i64, i64)>>() {
+        for (index, addr) in addrs.iter().enumerate() {
+            if (from.min(to)..=from.max(to)).contains(&(*addr as i64)) { pinned[index] = true; }
+        }
+    }
+    let mut page = 0;
+    for (index, item) in items.iter().enumerate() {
+        match item {
+            Union::B(Label::Trait(name, _)) if name == "page" => { page = index; },
+            Union::B(Label::Header(name)) if symbols.is_fixed(name) => { pinned[page..index].fill(true); },
+            _ => {},
+        }
+    } pinned
+}
+
+// the instruction rewritten, after the register fed to it by a Mov is swapped for the one moved from
+fn propagate(parameters: &[Parameter], write: Option<usize>, from: u8, to: u8) -> Vec<Parameter> {
+    parameters.iter().enumerate().map(|(index, parameter)| match parameter {
+        Parameter::Register(register) if *register == to && Some(index) != write => Parameter::Register(from),
+        Parameter::Pointer(register) if *register == to => Parameter::Pointer(from),
+        parameter => parameter.clone(),
+    }).collect()
+}
+
+// one rewrite of the instruction at the index (and the one after it); whether anything changed
+fn rewrite(items: &mut [Option<Item>], pinned: &[bool], index: usize) -> bool {
+    let Some(Union::A((instruction, parameters, _, refs))) = &items[index] else { return false; };
+    let (instruction, parameters, refs) = (instruction.clone(), parameters.clone(), refs.clone());
+    if pinned[index] { return false; }
+
+    // a jump to the label right after it
+    if JUMPS.contains(&instruction.name) && let [(0, name, 0)] = refs.as_slice() {
+        let follows = items[index + 1..].iter().flatten().take_while(|item| matches!(item, Union::B(Label::Header(_))))
+            .any(|item| matches!(item, Union::B(Label::Header(label)) if label == name));
+        if follows {
+            items[index] = None;
+            return true;
+        }
+    }
+    // a mov onto itself, or a mov (or ldi) into a register that's written again before it's read
+    let overwritten = match (instruction.name, parameters.as_slice()) {
+        ("Mov", [_, Parameter::Register(register)]) | ("Ldi", [Parameter::Register(register), _]) => dead_after(items, index, *register),
+        _ => false,
+    };
+    if instruction.name == "Mov" && parameters[0] == parameters[1] || overwritten {
+        items[index] = None;
+        return true;
+    }
+
+    let Some((following, false)) = next(items, index) else { return false; };
+    if pinned[following] { return false; }
+    let Some(Union::A((next_instruction, next_parameters, next_line, next_refs))) = &items[following] else { return false; };
+    let (next_instruction, next_parameters, next_line, next_refs) = (next_instruction.clone(), next_parameters.clone(), *next_line, next_refs.clone());
+    match (instruction.name, parameters.as_slice()) {
+        // a mov straight after the same one, or back the other way
+        ("Mov", [Parameter::Register(from), Parameter::Register(to)]) if next_instruction.name == "Mov" &&
+            (next_parameters == parameters || next_parameters == [Parameter::Register(*to), Parameter::Register(*from)]) => {
+            items[following] = None;
+            true
+        },
+        // a mov only there to feed the next instruction its register
+        ("Mov", [Parameter::Register(from), Parameter::Register(to)]) => {
+            let Some((reads, write)) = effects(&next_instruction, &next_parameters) else { return false; };
+            if !reads.contains(to) || write != Some(*to) && !dead_after(items, following, *to) { return false; }
+            let write_index = EFFECTS.iter().find(|(name, _)| *name == next_instruction.name).and_then(|(_, write)| *write);
+            items[following] = Some(Union::A((next_instruction, propagate(&next_parameters, write_index, *from, *to), next_line, next_refs)));
+            items[index] = None;
+            true
+        },
+        // an ldi only there to feed the next instruction its constant
+        ("Ldi", [Parameter::Register(register), constant]) => {
+            let Some((_, at, name, order)) = FOLDS.iter().find(|(name, at, ..)| {
+                *name == next_instruction.name && next_parameters.get(*at) == Some(&Parameter::Register(*register))
+            }) else { return false; };
+            let Some((reads, write)) = effects(&next_instruction, &next_parameters) else { return false; };
+            if reads.iter().filter(|read| *read == register).count() > 1 { return false; }  // used as more than the one operand
+            let folded = native(name);
+            let folded_parameters = order.iter().map(|from| if from == at { constant.clone() } else { next_parameters[*from].clone() }).collect();
+            let folded_refs = next_refs.iter().filter_map(|(operand, name, addend)| {
+                order.iter().position(|from| from == operand).map(|operand| (operand, name.clone(), *addend))
+            }).collect();
+            let dead = write == Some(*register) || dead_after(items, following, *register);
+            if dead && folded.cycle_cost <= instruction.cycle_cost + next_instruction.cycle_cost {
+                items[index] = None;
+            } else if folded.cycle_cost >= next_instruction.cycle_cost {
+                return false;  // still needs the ldi, and wouldn't save anything
+            }
+            items[following] = Some(Union::A((folded, folded_parameters, next_line, folded_refs)));
+            true
+        },
+        // two constants added in a row
+        ("AddImm", [source, Parameter::Constant(first), Parameter::Register(register)]) if next_instruction.name == "AddImm" &&
+            next_parameters[0] == Parameter::Register(*register) && next_parameters[2] == Parameter::Register(*register) => {
+            let Parameter::Constant(second) = next_parameters[1] else { return false; };
+            let sum = Parameter::Constant((first + second).rem_euclid(1 << 16));
+            items[following] = Some(Union::A((next_instruction, vec![source.clone(), sum, Parameter::Register(*register)], next_line, next_refs)));
+            items[index] = None;
+            true
+        },
+        _ => false,
+    }
+}
+
+// rewrites the program until nothing more can be, then places the labels where they've moved to (updating the symbol
+//    table) and patches the addrs given relative to them
+pub fn optimize(instructions: InstructionStream, symbols: &mut SymbolTable) -> InstructionStream {
+    let pinned = pinned(&instructions, symbols);
+    let mut items = instructions.into_iter().map(Some).collect::<Vec<Option<Item>>>();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for index in 0..items.len() {
+            changed |= rewrite(&mut items, &pinned, index);
+        }
+    }
+    let mut instructions = items.into_iter().flatten().collect::<InstructionStream>();
+
+    for (item, addr) in instructions.iter().zip(placed_addrs(&instructions)) {
+        if let Union::B(Label::Header(name)) = item { symbols.set_value(name, addr as i64); }
+    }
+    let value = |name: &String, addend: i64| symbols.get(name).filter(|symbol| symbol.kind == SymbolKind::Label).map(|symbol| symbol.value + addend);
+    for item in &mut instructions {
+        match item {
+            Union::A((_, parameters, _, refs)) => for (operand, name, addend) in refs.iter() {
+                if let Some(value) = value(name, *addend) { parameters[*operand] = Parameter::Address(value as u32); }
+            },
+            Union::B(Label::Data(byte_pairs, _, refs)) => for (index, name, addend) in refs.iter() {
+                if let Some(value) = value(name, *addend) { byte_pairs[*index] = value as u16; }
+            },
+            Union::B(_) => {},
+        }
+    } instructions
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{parse_text, register, run_text};
+    use crate::{Parameter, Union};
+
+    // the instructions left in the optimized program, by name
+    fn optimized(text: &str) -> Vec<(&'static str, Vec<Parameter>)> {
+        let (instructions, ..) = parse_text(text, &["-O"]).unwrap();
+        instructions.into_iter().filter_map(|item| match item {
+            Union::A((instruction, parameters, ..)) => Some((instruction.name, parameters)),
+            Union::B(_) => None,
+        }).collect()
+    }
+
+    #[test]
+    fn folds_an_ldi_into_the_constant_form() {
+        let text = "\
+            Ldi %rdb $2\n\
+            Add %rda %rdb %rda\n\
+            Ldi %rdb $7\n\
+            *Kill\n";
+        assert_eq!(optimized(text), [
+            ("AddImm", vec![Parameter::Register(0), Parameter::Constant(2), Parameter::Register(0)]),
+            ("Ldi", vec![Parameter::Register(1), Parameter::Constant(7)]),
+            ("Kill", vec![]),
+        ]);
+        assert_eq!(register(&run_text(text, &["-O"]), "rda"), 2);
+    }
+
+    #[test]
+    fn keeps_an_ldi_still_read() {
+        let text = "\
+            Ldi %rdb $2\n\
+            Add %rda %rdb %rda\n\
+            Add %rda %rdb %rda\n\
+            *Kill\n";
+        assert_eq!(optimized(text)[0], ("Ldi", vec![Parameter::Register(1), Parameter::Constant(2)]));
+        assert_eq!(register(&run_text(text, &["-O"]), "rda"), 4);
+    }
+
+    #[test]
+    fn merges_added_constants_and_drops_needless_movs() {
+        let text = "\
+            Add %rda $3 %rda\n\
+            Add %rda $4 %rda\n\
+            Mov %rda %rda\n\
+            Mov %rda %rdb\n\
+            Mov %rda %rdb\n\
+            *Kill\n";
+        assert_eq!(optimized(text), [
+            ("AddImm", vec![Parameter::Register(0), Parameter::Constant(7), Parameter::Register(0)]),
+            ("Mov", vec![Parameter::Register(0), Parameter::Register(1)]),
+            ("Kill", vec![]),
+        ]);
+        assert_eq!(register(&run_text(text, &["-O"]), "rdb"), 7);
+    }
+
+    #[test]
+    fn drops_a_jump_to_the_next_instruction() {
+        let text = "\
+            *Jmp #next\n\
+            !label next\n\
+            Jeq %rda $0 #after\n\
+            !label after\n\
+            *Kill\n";
+        assert_eq!(optimized(text).iter().map(|(name, _)| *name).collect::<Vec<_>>(), ["EqImm", "Kill"]);
+    }
+
+    #[test]
+    fn moves_the_labels_and_their_addrs() {
+        let text = "\
+            Mov %rda %rda\n\
+            *Jmp #skip\n\
+            !label skip\n\
+            Ldi %rda $5\n\
+            *Jmp #end\n\
+            Ldi %rda $9\n\
+            !label end\n\
+            .word #end\n\
+            *Kill\n";
+        let (instructions, _, symbols) = parse_text(text, &["-O"]).unwrap();
+        // the mov & the first jump are gone, so everything's 6 byte pairs earlier
+        assert_eq!(symbols.get("skip").unwrap().value, 3);
+        assert_eq!(symbols.get("end").unwrap().value, 12);
+        assert!(instructions.iter().any(|item| matches!(item, Union::A((instruction, parameters, ..))
+            if instruction.name == "Jmp" && parameters[0] == Parameter::Address(12))));
+        assert!(instructions.iter().any(|item| matches!(item, Union::B(crate::Label::Data(byte_pairs, ..)) if byte_pairs == &[12])));
+        assert_eq!(register(&run_text(text, &["-O"]), "rda"), 5);
+    }
+
+    #[test]
+    fn leaves_a_label_used_as_a_number_in_place() {
+        let text = "\
+            Mov %rda %rda\n\
+            *Jmp #target\n\
+            !label target\n\
+            Ldi %rdc $target\n\
+            Ldi %rdd {target + 1}\n\
+            Mov %rdb %rdb\n\
+            *Jmp #end\n\
+            !label end\n\
+            *Kill\n";
+        let (_, _, symbols) = parse_text(text, &["-O"]).unwrap();
+        assert_eq!(symbols.get("target").unwrap().value, 9);
+        // past it, the code's still optimized
+        assert_eq!(symbols.get("end").unwrap().value, 15);
+        let emulator = run_text(text, &["-O"]);
+        assert_eq!((register(&emulator, "rdc"), register(&emulator, "rdd")), (9, 10));
+    }
+}
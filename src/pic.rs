@@ -0,0 +1,110 @@
+// position-independent code (asm/run --pic); the jumps & calls to labels are rewritten into their pc-relative forms,
+//    so the code runs wherever it's loaded (a blob the kernel LoadSeg's into ram, etc.) rather than only at the addr
+//    it was assembled for
+//
+//    only those landing in their own section (no .page between them) are, as the sections are placed apart; the rest,
+//    and the addrs taken any other way (*JmpPtr, *SetIntAddr, .word #label, etc.), stay absolute
+
+use crate::symbols::{SymbolKind, SymbolTable};
+use crate::{placed_addrs, InstructionStream, Label, Parameter, Union, INSTRUCTIONS, RELATIVE_FORMS};
+
+pub fn make_relative(instructions: &mut InstructionStream, symbols: &SymbolTable) {
+    let addrs = placed_addrs(instructions);
+    // every .page starts a new section
+    let sections = instructions.iter().scan(0, |section, item| {
+        if let Union::B(Label::Trait(name, _)) = item && name == "page" { *section += 1; }
+        Some(*section)
+    }).collect::<Vec<usize>>();
+    let labels = instructions.iter().zip(&sections).filter_map(|(item, section)| match item {
+        Union::B(Label::Header(name)) => Some((name.clone(), *section)),
+        _ => None,
+    }).collect::<Vec<(String, usize)>>();
+
+    for ((item, addr), section) in instructions.iter_mut().zip(addrs).zip(sections) {
+        let Union::A((instruction, parameters, _, refs)) = item else { continue; };
+        let Some((_, relative)) = RELATIVE_FORMS.iter().find(|(absolute, _)| *absolute == instruction.name) else { continue; };
+        let [(0, name, addend)] = refs.as_slice() else { continue; };
+        if !labels.contains(&(name.clone(), section)) { continue; }
+        let Some(symbol) = symbols.get(name).filter(|symbol| symbol.kind == SymbolKind::Label) else { continue; };
+        let Some(relative) = INSTRUCTIONS.iter().find(|instruction| instruction.name == *relative) else { continue; };
+        // wrapping, the same as the pc does
+        let offset = (symbol.value + addend - addr as i64) as i16;
+        *instruction = relative.clone();
+        *parameters = vec![Parameter::Constant(offset as i64)];
+        refs.clear();  // nothing to relocate; it lands on the same instruction wherever the section's placed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{parse_text, register, run_text};
+    use crate::{Label, Parameter, Union};
+
+    // each instruction (by name) & its operands, with the data's byte pairs under .word
+    fn relative(text: &str) -> Vec<(&'static str, Vec<Parameter>)> {
+        let (instructions, ..) = parse_text(text, &["--pic"]).unwrap();
+        instructions.into_iter().filter_map(|item| match item {
+            Union::A((instruction, parameters, ..)) => Some((instruction.name, parameters)),
+            Union::B(Label::Data(byte_pairs, ..)) => Some((".word", byte_pairs.into_iter().map(|byte_pair| Parameter::Constant(byte_pair as i64)).collect())),
+            Union::B(_) => None,
+        }).collect()
+    }
+
+    #[test]
+    fn jumps_in_their_own_section_become_relative() {
+        let text = "\
+            !header start\n\
+            *Jmp #forward\n\
+            Add %rda $1 %rda\n\
+            !label forward\n\
+            Jeq %rda $0 #start\n\
+            *Call #{start + 3}\n";
+        assert_eq!(relative(text), [
+            ("JmpRel", vec![Parameter::Constant(6)]),
+            ("AddImm", vec![Parameter::Register(0), Parameter::Constant(1), Parameter::Register(0)]),
+            ("EqImm", vec![Parameter::Register(0), Parameter::Constant(0)]),
+            ("JicRel", vec![Parameter::Constant(-9)]),
+            ("CallRel", vec![Parameter::Constant(-9)]),
+        ]);
+    }
+
+    #[test]
+    fn other_addrs_stay_absolute() {
+        let text = "\
+            !header start\n\
+            *Jmp #far\n\
+            *JmpPtr %rda\n\
+            .word #start\n\
+            .page 100\n\
+            !label far\n\
+            *Call #start\n";
+        assert_eq!(relative(text), [
+            ("Jmp", vec![Parameter::Address(100)]),
+            ("JmpPtr", vec![Parameter::Register(0)]),
+            (".word", vec![Parameter::Constant(3)]),
+            ("Call", vec![Parameter::Address(3)]),
+        ]);
+    }
+
+    #[test]
+    fn relative_jumps_and_calls_land() {
+        let text = "\
+            !header main\n\
+            Ldi %rdb $3\n\
+            !label again\n\
+            *Call #bump\n\
+            Sub %rdb $1 %rdb\n\
+            Jne %rdb $0 #again\n\
+            *Jmp #done\n\
+            Ldi %rda $99\n\
+            !label done\n\
+            *Kill\n\
+            !header bump\n\
+            Add %rda $1 %rda\n\
+            *Ret\n";
+        assert!(relative(text).iter().any(|(name, _)| *name == "CallRel"));
+        for args in [&[][..], &["--pic"], &["--pic", "-O"]] {
+            assert_eq!(register(&run_text(text, args), "rda"), 3);
+        }
+    }
+}
@@ -0,0 +1,155 @@
+// the symbol map (.sym); every label, !const, !define and trait of an assembled program with its value and src line,
+//    written out by the assembler and read back in by the emulator (and any other tools) to name raw addrs
+//
+//    one symbol per line, tab separated; the kind (header, const, variable or trait), the name, the value in hex,
+//    and the defining src line (path:line). lines starting with ';' are comments, and negative values are written -0x...
+//        header	schedule_callback	0x0030	scripts/boot.cisc:59
+
+use crate::diagnostics::SourceMap;
+use crate::symbols::{SymbolKind, SymbolTable};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapSymbol {
+    pub kind: SymbolKind,
+    pub name: String,
+    pub value: i64,
+    pub src: String,  // path:line (1 based)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SymbolMap {
+    symbols: Vec<MapSymbol>,
+}
+
+fn kind_keyword(kind: SymbolKind) -> &'static str {
+    match kind {
+        SymbolKind::Label => "header",
+        SymbolKind::Const => "const",
+        SymbolKind::Variable => "variable",
+        SymbolKind::Trait => "trait",
+        SymbolKind::Extern => "extern",
+    }
+}
+
+impl SymbolMap {
+    pub fn from_table(table: &SymbolTable, sources: &SourceMap) -> Self {
+        // the !extern's are defined by other programs, so they're left to their own maps; the hidden labels aren't named at all
+        let symbols = table.iter().chain(table.traits()).filter(|symbol| symbol.kind != SymbolKind::Extern && !table.is_hidden(&symbol.name)).map(|symbol| MapSymbol {
+            kind: symbol.kind,
+            name: symbol.name.clone(),
+            value: symbol.value,
+            src: format!("{}:{}", sources.path(symbol.span.file), symbol.span.line + 1),
+        }).collect();
+        SymbolMap { symbols }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("; kind\tname\tvalue\tsrc\n");
+        for symbol in &self.symbols {
+            let sign = if symbol.value < 0 { "-" } else { "" };
+            text.push_str(&format!("{}\t{}\t{}0x{:04x}\t{}\n", kind_keyword(symbol.kind), symbol.name, sign, symbol.value.unsigned_abs(), symbol.src));
+        } text
+    }
+
+    // the error names the (1 based) line that couldn't be read
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut symbols = vec![];
+        for (index, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with(';') { continue; }
+            let fields = line.splitn(4, '\t').collect::<Vec<&str>>();
+            let [kind, name, value, src] = fields[..] else {
+                return Err(format!("line {}: expected 4 tab separated fields (kind, name, value, src)", index + 1));
+            };
+            let kind = match kind {
+                "header" => SymbolKind::Label,
+                "const" => SymbolKind::Const,
+                "variable" => SymbolKind::Variable,
+                "trait" => SymbolKind::Trait,
+                "extern" => SymbolKind::Extern,
+                other => { return Err(format!("line {}: unknown symbol kind '{}'", index + 1, other)); },
+            };
+            let (sign, magnitude) = match value.strip_prefix('-') { Some(magnitude) => (-1, magnitude), None => (1, value) };
+            let value = magnitude.strip_prefix("0x").and_then(|hex| i64::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| format!("line {}: invalid value '{}'; expected hex (0x...)", index + 1, value))? * sign;
+            symbols.push(MapSymbol { kind, name: name.to_string(), value, src: src.to_string() });
+        } Ok(SymbolMap { symbols })
+    }
+
+    pub fn push(&mut self, symbol: MapSymbol) {
+        self.symbols.push(symbol);
+    }
+
+    pub fn extend(&mut self, other: SymbolMap) {
+        self.symbols.extend(other.symbols);
+    }
+
+    // the header nearest at or before the addr, and how far past it the addr is (in byte pairs)
+    pub fn nearest_label(&self, addr: usize) -> Option<(&str, usize)> {
+        self.symbols.iter()
+            .filter(|symbol| symbol.kind == SymbolKind::Label && symbol.value >= 0 && symbol.value as usize <= addr)
+            .fold(None, |nearest: Option<&MapSymbol>, symbol| match nearest {
+                Some(nearest) if nearest.value >= symbol.value => Some(nearest),
+                _ => Some(symbol),
+            })
+            .map(|symbol| (symbol.name.as_str(), addr - symbol.value as usize))
+    }
+
+    // every header at exactly the addr
+    pub fn labels_at(&self, addr: usize) -> impl Iterator<Item = &str> {
+        self.symbols.iter()
+            .filter(move |symbol| symbol.kind == SymbolKind::Label && symbol.value == addr as i64)
+            .map(|symbol| symbol.name.as_str())
+    }
+
+    // the addr as the nearest header at or before it, plus the offset in byte pairs; schedule_callback+6
+    //    addrs before every header are left as hex
+    pub fn symbolize(&self, addr: usize) -> String {
+        match self.nearest_label(addr) {
+            Some((name, 0)) => name.to_string(),
+            Some((name, offset)) => format!("{}+{}", name, offset),
+            None => format!("{:04x}", addr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::options;
+
+    #[test]
+    fn round_trips_through_its_text() {
+        let text = "\
+            !const NEG {-5}\n\
+            .ram_size 0x4000\n\
+            !header main\n\
+            *Ldi %rda $NEG\n\
+            !header lib::double\n\
+            *Ret\n";
+        let (sources, _, _, parsed) = crate::parse_source("test.cisc", Some(text.to_string()), &options(&["asm", "test.cisc"]));
+        let (_, _, symbols) = parsed.unwrap();
+        let map = SymbolMap::from_table(&symbols, &sources);
+        let text = map.to_text();
+        assert!(text.contains("const\tNEG\t-0x0005\ttest.cisc:1\n"), "{}", text);
+        assert!(text.contains("header\tlib::double\t"), "{}", text);
+        assert!(text.contains("trait\tram_size\t0x4000\ttest.cisc:2\n"), "{}", text);
+        assert_eq!(SymbolMap::parse(&text).unwrap().symbols, map.symbols);
+    }
+
+    #[test]
+    fn rejects_what_it_cant_read() {
+        assert_eq!(SymbolMap::parse("; comment\n\nheader\tmain\n").unwrap_err(), "line 3: expected 4 tab separated fields (kind, name, value, src)");
+        assert_eq!(SymbolMap::parse("label\tmain\t0x0003\ta:1\n").unwrap_err(), "line 1: unknown symbol kind 'label'");
+        assert_eq!(SymbolMap::parse("header\tmain\t3\ta:1\n").unwrap_err(), "line 1: invalid value '3'; expected hex (0x...)");
+    }
+
+    #[test]
+    fn names_addrs_by_the_nearest_header() {
+        let mut map = SymbolMap::default();
+        for (kind, name, value) in [(SymbolKind::Label, "main", 3), (SymbolKind::Const, "LIMIT", 10), (SymbolKind::Label, "lib::double", 12)] {
+            map.push(MapSymbol { kind, name: name.to_string(), value, src: String::from("test.cisc:1") });
+        }
+        assert_eq!([2, 3, 5, 11, 12, 14].map(|addr| map.symbolize(addr)), ["0002", "main", "main+2", "main+8", "lib::double", "lib::double+2"]);
+        assert_eq!(map.labels_at(12).collect::<Vec<_>>(), ["lib::double"]);
+    }
+}
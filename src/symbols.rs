@@ -0,0 +1,134 @@
+// the symbol table; every label, !const and !define name along with where it was defined,
+//    so names resolve through their file's namespace and undefined ones can suggest the closest match
+
+use crate::diagnostics::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Label,  // !header, !function, etc.; the value is the program addr
+    Const,
+    Variable,  // !define; the value is the ram addr
+    Trait,  // .page, .ram_size, etc.; kept only for the symbol map, as they can't be referred to
+    Extern,  // a label of another object; its addr is only known once linked
+}
+
+impl SymbolKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SymbolKind::Label => "label",
+            SymbolKind::Const => "!const",
+            SymbolKind::Variable => "!define",
+            SymbolKind::Trait => "trait",
+            SymbolKind::Extern => "!extern",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,  // qualified (module::name) for anything outside of the root file
+    pub kind: SymbolKind,
+    pub value: i64,
+    pub span: Span,  // the name within its definition
+}
+
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+    traits: Vec<Symbol>,  // in the order given (.page can be given any number of times)
+    fixed: Vec<String>,  // labels whose value is taken as a number somewhere, so the code can't be moved out from under them
+    hidden: Vec<String>,  // labels lowered from !if/!while; kept out of object exports, symbol maps and completions
+}
+
+// a name under a file's namespace; names that are already qualified (module::name) are left as is
+pub fn qualify(namespace: &str, name: &str) -> String {
+    if namespace.is_empty() || name.contains("::") { name.to_string() } else { format!("{}::{}", namespace, name) }
+}
+
+impl SymbolTable {
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+
+    // the earlier definition is given back if the name is already taken
+    pub fn define(&mut self, symbol: Symbol) -> Result<(), &Symbol> {
+        match self.symbols.iter().position(|other| other.name == symbol.name) {
+            Some(index) => Err(&self.symbols[index]),
+            None => {
+                self.symbols.push(symbol);
+                Ok(())
+            },
+        }
+    }
+
+    // unqualified names are looked for in the file's own namespace first, and then the global one (the root file's)
+    pub fn lookup(&self, name: &str, namespace: &str) -> Option<&Symbol> {
+        self.get(&qualify(namespace, name)).or_else(|| self.get(name))
+    }
+
+    // a label moved once its code's been rewritten (by the peephole optimizer)
+    pub fn set_value(&mut self, name: &str, value: i64) {
+        if let Some(symbol) = self.symbols.iter_mut().find(|symbol| symbol.name == name) { symbol.value = value; }
+    }
+
+    pub fn fix(&mut self, name: &str) {
+        if !self.is_fixed(name) { self.fixed.push(name.to_string()); }
+    }
+
+    pub fn is_fixed(&self, name: &str) -> bool {
+        self.fixed.iter().any(|fixed| fixed == name)
+    }
+
+    pub fn hide(&mut self, name: &str) {
+        self.hidden.push(name.to_string());
+    }
+
+    pub fn is_hidden(&self, name: &str) -> bool {
+        self.hidden.iter().any(|hidden| hidden == name)
+    }
+
+    pub fn add_trait(&mut self, symbol: Symbol) {
+        self.traits.push(symbol);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.iter()
+    }
+
+    pub fn traits(&self) -> impl Iterator<Item = &Symbol> {
+        self.traits.iter()
+    }
+
+    // the closest defined name to an undefined one, written how it'd be referred to from the namespace
+    //    only names within a few edits (about a third of the name) are suggested
+    pub fn suggest(&self, name: &str, namespace: &str) -> Option<(String, &Symbol)> {
+        let prefix = format!("{}::", namespace);
+        self.symbols.iter().map(|symbol| {
+            // the own namespace's symbols can be written without it
+            let written = match symbol.name.strip_prefix(&prefix) {
+                Some(local) if !namespace.is_empty() => local.to_string(),
+                _ => symbol.name.clone(),
+            };
+            // a missing module:: is compared against the name alone
+            let unqualified = written.rsplit("::").next().unwrap_or(&written);
+            let distance = edit_distance(name, &written).min(edit_distance(name, unqualified));
+            (distance, written, symbol)
+        }).filter(|(distance, ..)| *distance <= (name.chars().count() / 3).max(1))
+            .min_by_key(|(distance, ..)| *distance)
+            .map(|(_, written, symbol)| (written, symbol))
+    }
+}
+
+// the levenshtein distance, in chars
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<char>>();
+    let mut previous = (0..=b.len()).collect::<Vec<usize>>();
+    for (i, chr_a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, chr_b) in b.iter().enumerate() {
+            let substitution = previous[j] + (chr_a != *chr_b) as usize;
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    } previous[b.len()]
+}